@@ -0,0 +1,118 @@
+use crate::error::ItError;
+use crate::ignore::wildmatch;
+use std::fs;
+use std::path::Path;
+
+// gitattributes-style path attributes, read from `.itattributes` at the
+// worktree root and `.it/info/attributes`, which takes precedence. A line is
+// a pattern followed by attributes: `name` sets one, `-name` unsets it,
+// `!name` makes it unspecified again and `name=value` gives it a value.
+// Patterns follow the .itignore rules, except that there is no `!` negation
+// and patterns for directories (`dir/`) never match. For each attribute the
+// last matching line that mentions it decides.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttrValue {
+    Set,
+    Unset,
+    Value(String),
+    Unspecified,
+}
+
+#[derive(Debug, Clone)]
+pub struct AttrRule {
+    pub pattern: String,
+    basename_only: bool,
+    pub attrs: Vec<(String, AttrValue)>,
+    pub source: String,
+}
+
+impl AttrRule {
+    fn parse(line: &str, source: &str) -> Option<AttrRule> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let mut fields = line.split_whitespace();
+        let pattern = fields.next()?;
+        if pattern.starts_with('!') || (pattern.ends_with('/') && pattern.len() > 1) {
+            return None;
+        }
+        let attrs = fields
+            .map(|field| match field.split_once('=') {
+                Some((name, value)) => (name.to_string(), AttrValue::Value(value.to_string())),
+                None => match (field.strip_prefix('-'), field.strip_prefix('!')) {
+                    (Some(name), _) => (name.to_string(), AttrValue::Unset),
+                    (_, Some(name)) => (name.to_string(), AttrValue::Unspecified),
+                    _ => (field.to_string(), AttrValue::Set),
+                },
+            })
+            .collect();
+
+        Some(AttrRule {
+            pattern: pattern.strip_prefix('/').unwrap_or(pattern).to_string(),
+            basename_only: !pattern.contains('/'),
+            attrs,
+            source: source.to_string(),
+        })
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        if self.basename_only {
+            let name = path.rsplit('/').next().unwrap_or(path);
+            wildmatch(&self.pattern, name, true)
+        } else {
+            wildmatch(&self.pattern, path, true)
+        }
+    }
+}
+
+pub struct Attributes {
+    worktree: Vec<AttrRule>,
+    info: Vec<AttrRule>,
+}
+
+impl Attributes {
+    pub fn load(worktree: &Path, repo_path: &Path) -> Result<Attributes, ItError> {
+        Ok(Attributes {
+            worktree: read_rules(&worktree.join(".itattributes"), ".itattributes")?,
+            info: read_rules(&repo_path.join("info/attributes"), ".it/info/attributes")?,
+        })
+    }
+
+    // Replaces the worktree rules, for a checkout that brings its own
+    // `.itattributes` before the file is written.
+    pub fn set_worktree_rules(&mut self, text: &str) {
+        self.worktree = parse_rules(text, ".itattributes");
+    }
+
+    // The rules from `.itattributes`, in file order.
+    pub fn worktree_rules(&self) -> &[AttrRule] {
+        &self.worktree
+    }
+
+    // The value of `name` for the worktree-relative `path`.
+    pub fn get(&self, path: &str, name: &str) -> AttrValue {
+        self.worktree
+            .iter()
+            .chain(&self.info)
+            .filter(|rule| rule.matches(path))
+            .flat_map(|rule| rule.attrs.iter().filter(|(attr, _)| attr == name))
+            .last()
+            .map(|(_, value)| value.clone())
+            .unwrap_or(AttrValue::Unspecified)
+    }
+}
+
+fn parse_rules(text: &str, source: &str) -> Vec<AttrRule> {
+    text.lines().filter_map(|line| AttrRule::parse(line, source)).collect()
+}
+
+fn read_rules(file: &Path, source: &str) -> Result<Vec<AttrRule>, ItError> {
+    match fs::read_to_string(file) {
+        Ok(text) => Ok(parse_rules(&text, source)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(_) if !file.is_file() => Ok(vec![]),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,476 @@
+// Reachability bitmaps for the multi-pack-index, in git's format, stored as
+// objects/pack/multi-pack-index-<midx checksum>.bitmap:
+//
+//      "BITM", version 1, flags, number of bitmapped commits, midx checksum
+//      four EWAH bitmaps marking the commits, trees, blobs and tags
+//      per bitmapped commit: its midx position, an XOR offset, flags and
+//      an EWAH bitmap of every object the commit reaches
+//      name-hash cache: one u32 per object, in midx order
+//      SHA-1 of everything before it
+//
+// Bit i stands for the i-th object in pseudo-pack order (see midx.rs). An
+// entry with XOR offset n stores its bitmap XORed with the one n entries
+// before it; the bitmaps written here never use that, but git's may.
+use crate::{
+    error::ItError,
+    lockfile::write_atomic,
+    midx::{self, MultiPackIndex},
+    pack::{self, read_entry, type_name},
+    reachable::object_links,
+};
+use sha1::{Digest, Sha1};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const FULL_DAG: u16 = 0x1;
+const HASH_CACHE: u16 = 0x4;
+// besides ref tips, every this many commits of history gets a bitmap
+const COMMIT_INTERVAL: usize = 100;
+
+#[derive(Clone, Default, PartialEq)]
+pub struct Bitmap {
+    words: Vec<u64>,
+}
+
+impl Bitmap {
+    pub fn set(&mut self, bit: usize) {
+        if self.words.len() <= bit / 64 {
+            self.words.resize(bit / 64 + 1, 0);
+        }
+        self.words[bit / 64] |= 1 << (bit % 64);
+    }
+
+    pub fn get(&self, bit: usize) -> bool {
+        self.words.get(bit / 64).is_some_and(|w| w & (1 << (bit % 64)) != 0)
+    }
+
+    pub fn or(&mut self, other: &Bitmap) {
+        if self.words.len() < other.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word |= other;
+        }
+    }
+
+    fn xor(&mut self, other: &Bitmap) {
+        if self.words.len() < other.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word ^= other;
+        }
+    }
+
+    pub fn ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words
+            .iter()
+            .enumerate()
+            .flat_map(|(i, &word)| (0..64).filter(move |b| word & (1 << b) != 0).map(move |b| i * 64 + b))
+    }
+
+    // The words without trailing zeros, for comparisons.
+    fn trimmed(&self) -> &[u64] {
+        let len = self.words.iter().rposition(|&w| w != 0).map_or(0, |i| i + 1);
+        &self.words[..len]
+    }
+}
+
+// EWAH: a sequence of marker words, each followed by literal words. A
+// marker's bit 0 is the value of a run of uniform words, bits 1-32 the run's
+// length in words and bits 33-63 how many literal words follow. Serialized
+// as the size in bits, the word count, the big-endian words and the position
+// of the last marker.
+fn ewah_encode(bitmap: &Bitmap, out: &mut Vec<u8>) {
+    const MAX_RUN: usize = u32::MAX as usize;
+    const MAX_LITERALS: usize = (1 << 31) - 1;
+    let words = &bitmap.words;
+    let clean = |w: u64| w == 0 || w == u64::MAX;
+
+    let mut buffer: Vec<u64> = Vec::new();
+    let mut last_marker = 0;
+    let mut i = 0;
+    while i < words.len() || buffer.is_empty() {
+        let running_bit = words.get(i) == Some(&u64::MAX);
+        let mut run = 0;
+        if let Some(&word) = words.get(i).filter(|&&w| clean(w)) {
+            while i < words.len() && words[i] == word && run < MAX_RUN {
+                run += 1;
+                i += 1;
+            }
+        }
+        let start = i;
+        while i < words.len() && !clean(words[i]) && i - start < MAX_LITERALS {
+            i += 1;
+        }
+        last_marker = buffer.len();
+        buffer.push(running_bit as u64 | (run as u64) << 1 | ((i - start) as u64) << 33);
+        buffer.extend_from_slice(&words[start..i]);
+    }
+
+    out.extend_from_slice(&((words.len() * 64) as u32).to_be_bytes());
+    out.extend_from_slice(&(buffer.len() as u32).to_be_bytes());
+    for word in &buffer {
+        out.extend_from_slice(&word.to_be_bytes());
+    }
+    out.extend_from_slice(&(last_marker as u32).to_be_bytes());
+}
+
+fn ewah_decode(data: &[u8], pos: &mut usize) -> Option<Bitmap> {
+    let read_u32 = |pos: &mut usize| -> Option<u32> {
+        let bytes = data.get(*pos..*pos + 4)?;
+        *pos += 4;
+        Some(u32::from_be_bytes(bytes.try_into().unwrap()))
+    };
+    let _bit_size = read_u32(pos)?;
+    let len = read_u32(pos)? as usize;
+    let buffer: Vec<u64> = data
+        .get(*pos..*pos + len * 8)?
+        .chunks(8)
+        .map(|c| u64::from_be_bytes(c.try_into().unwrap()))
+        .collect();
+    *pos += len * 8;
+    let _last_marker = read_u32(pos)?;
+
+    let mut words = Vec::new();
+    let mut i = 0;
+    while i < buffer.len() {
+        let marker = buffer[i];
+        i += 1;
+        let fill = if marker & 1 != 0 { u64::MAX } else { 0 };
+        let run = ((marker >> 1) & 0xffff_ffff) as usize;
+        let literals = (marker >> 33) as usize;
+        words.extend(std::iter::repeat_n(fill, run));
+        words.extend_from_slice(buffer.get(i..i + literals)?);
+        i += literals;
+    }
+    Some(Bitmap { words })
+}
+
+pub struct BitmapIndex {
+    // everything each bitmapped commit reaches, keyed by the commit's midx position
+    pub commits: HashMap<u32, Bitmap>,
+    // commits, trees, blobs, tags
+    pub types: [Bitmap; 4],
+    // by midx position
+    pub name_hashes: Option<Vec<u32>>,
+}
+
+pub fn bitmap_path(objects_dir: &Path, midx: &MultiPackIndex) -> PathBuf {
+    objects_dir.join("pack").join(format!("multi-pack-index-{}.bitmap", hex::encode(midx.checksum)))
+}
+
+// Reads the bitmap belonging to `midx`. A missing file gives `None`; one with
+// a bad checksum or written for another multi-pack-index is an error.
+pub fn load_bitmap(objects_dir: &Path, midx: &MultiPackIndex) -> Result<Option<BitmapIndex>, ItError> {
+    let path = bitmap_path(objects_dir, midx);
+    let Ok(data) = fs::read(&path) else {
+        return Ok(None);
+    };
+    let corrupt = |msg: &str| ItError::CorruptObject(format!("{}: {msg}", path.display()));
+    if data.len() < 32 + 20 || &data[..4] != b"BITM" || data[4..6] != [0, 1] {
+        return Err(corrupt("not a version 1 bitmap"));
+    }
+    let (body, trailer) = data.split_at(data.len() - 20);
+    if Sha1::digest(body)[..] != *trailer {
+        return Err(corrupt("checksum mismatch"));
+    }
+    let flags = u16::from_be_bytes([data[6], data[7]]);
+    let count = u32::from_be_bytes(data[8..12].try_into().unwrap()) as usize;
+    if data[12..32] != midx.checksum {
+        return Err(corrupt("written for a different multi-pack-index"));
+    }
+
+    let mut pos = 32;
+    let mut types: [Bitmap; 4] = Default::default();
+    for bitmap in types.iter_mut() {
+        *bitmap = ewah_decode(body, &mut pos).ok_or_else(|| corrupt("bad type bitmap"))?;
+    }
+
+    let mut entries: Vec<(u32, Bitmap)> = Vec::with_capacity(count);
+    for _ in 0..count {
+        let header = body.get(pos..pos + 6).ok_or_else(|| corrupt("truncated entry"))?;
+        let position = u32::from_be_bytes(header[..4].try_into().unwrap());
+        let xor_offset = header[4] as usize;
+        pos += 6;
+        let mut bitmap = ewah_decode(body, &mut pos).ok_or_else(|| corrupt("bad commit bitmap"))?;
+        if xor_offset > 0 {
+            let base = entries.len().checked_sub(xor_offset).ok_or_else(|| corrupt("bad XOR offset"))?;
+            let base = entries[base].1.clone();
+            bitmap.xor(&base);
+        }
+        entries.push((position, bitmap));
+    }
+
+    let name_hashes = if flags & HASH_CACHE != 0 {
+        let cache = body
+            .get(pos..pos + midx.hashes.len() * 4)
+            .ok_or_else(|| corrupt("truncated name-hash cache"))?;
+        Some(cache.chunks(4).map(|c| u32::from_be_bytes(c.try_into().unwrap())).collect())
+    } else {
+        None
+    };
+    Ok(Some(BitmapIndex { commits: entries.into_iter().collect(), types, name_hashes }))
+}
+
+// The object graph of everything in the multi-pack-index, by midx position.
+struct Graph {
+    kinds: Vec<u8>,
+    // None where an object links outside the multi-pack-index
+    links: Vec<Option<Vec<usize>>>,
+}
+
+fn load_graph(objects_dir: &Path, midx: &MultiPackIndex) -> Result<(Graph, Vec<u32>), ItError> {
+    let count = midx.hashes.len();
+    let mut graph = Graph { kinds: Vec::with_capacity(count), links: Vec::with_capacity(count) };
+    let mut names: Vec<Vec<(usize, String)>> = Vec::with_capacity(count);
+    let packs: Vec<_> = midx
+        .pack_names
+        .iter()
+        .map(|name| pack::load_index(&objects_dir.join("pack").join(name)))
+        .collect::<Result<_, _>>()?;
+
+    for (pack_id, offset) in &midx.objects {
+        let (kind, body) = read_entry(&packs[*pack_id as usize], *offset)?;
+        let mut links = Some(Vec::new());
+        let mut entry_names = Vec::new();
+        for (hash, name) in object_links(type_name(kind), &body)? {
+            let sha: Option<[u8; 20]> = hex::decode(&hash).ok().and_then(|s| s.try_into().ok());
+            match sha.and_then(|sha| midx.find(&sha)) {
+                Some(i) => {
+                    if let Some(links) = &mut links {
+                        links.push(i);
+                    }
+                    entry_names.push((i, name));
+                }
+                None => links = None,
+            }
+        }
+        graph.kinds.push(kind);
+        graph.links.push(links);
+        names.push(entry_names);
+    }
+
+    // name hashes come from the path an object is first found at below a commit
+    let mut name_hashes = vec![0u32; count];
+    let mut seen = vec![false; count];
+    let mut queue: VecDeque<(usize, String)> =
+        (0..count).filter(|&i| graph.kinds[i] == pack::OBJ_COMMIT).map(|i| (i, String::new())).collect();
+    while let Some((i, path)) = queue.pop_front() {
+        if std::mem::replace(&mut seen[i], true) {
+            continue;
+        }
+        name_hashes[i] = pack::name_hash(&path);
+        for (link, name) in &names[i] {
+            let child = if path.is_empty() { name.clone() } else { format!("{path}/{name}") };
+            queue.push_back((*link, child));
+        }
+    }
+    Ok((graph, name_hashes))
+}
+
+// Everything `commit` reaches as a bitmap over pseudo-pack positions, reusing
+// the bitmaps already computed for its ancestors. `None` when some object it
+// reaches is not in the multi-pack-index.
+fn closure(commit: usize, graph: &Graph, positions: &[usize], done: &HashMap<usize, Bitmap>) -> Option<Bitmap> {
+    let mut bitmap = Bitmap::default();
+    let mut stack = vec![commit];
+    while let Some(i) = stack.pop() {
+        if bitmap.get(positions[i]) {
+            continue;
+        }
+        if let Some(known) = done.get(&i).filter(|_| i != commit) {
+            bitmap.or(known);
+            continue;
+        }
+        bitmap.set(positions[i]);
+        stack.extend(graph.links[i].as_ref()?);
+    }
+    Some(bitmap)
+}
+
+// Writes the bitmap for `midx`. Bitmaps are made for the commits at `tips`
+// and for every COMMIT_INTERVAL-th commit of the history below them, oldest
+// first so each can build on its ancestors'. Returns how many were written.
+pub fn write_bitmap(objects_dir: &Path, midx: &MultiPackIndex, tips: &[String]) -> Result<usize, ItError> {
+    let order = midx
+        .pack_order
+        .as_ref()
+        .ok_or_else(|| ItError::Fatal("multi-pack-index has no pack order (RIDX) chunk".to_string()))?;
+    let (graph, name_hashes) = load_graph(objects_dir, midx)?;
+    let mut positions = vec![0usize; order.len()];
+    for (pos, &i) in order.iter().enumerate() {
+        positions[i as usize] = pos;
+    }
+    let is_commit = |i: usize| graph.kinds[i] == pack::OBJ_COMMIT;
+
+    // pick commits breadth-first from the tips
+    let tip_positions: Vec<usize> = tips
+        .iter()
+        .filter_map(|h| hex::decode(h).ok()?.try_into().ok())
+        .filter_map(|sha: [u8; 20]| midx.find(&sha))
+        .filter(|&i| is_commit(i))
+        .collect();
+    let mut selected = Vec::new();
+    let mut seen = vec![false; order.len()];
+    let mut queue: VecDeque<usize> = tip_positions.iter().copied().collect();
+    let mut walked = 0;
+    while let Some(i) = queue.pop_front() {
+        if std::mem::replace(&mut seen[i], true) {
+            continue;
+        }
+        if tip_positions.contains(&i) || walked % COMMIT_INTERVAL == 0 {
+            selected.push(i);
+        }
+        walked += 1;
+        for &link in graph.links[i].iter().flatten() {
+            if is_commit(link) {
+                queue.push_back(link);
+            }
+        }
+    }
+
+    // generation numbers put ancestors first
+    let mut generation: HashMap<usize, usize> = HashMap::new();
+    for &start in &selected {
+        let mut stack = vec![start];
+        while let Some(&i) = stack.last() {
+            if generation.contains_key(&i) {
+                stack.pop();
+                continue;
+            }
+            let parents: Vec<usize> = graph.links[i].iter().flatten().copied().filter(|&p| is_commit(p)).collect();
+            let pending: Vec<usize> = parents.iter().copied().filter(|p| !generation.contains_key(p)).collect();
+            if pending.is_empty() {
+                let g = parents.iter().map(|p| generation[p]).max().map_or(1, |g| g + 1);
+                generation.insert(i, g);
+                stack.pop();
+            } else {
+                stack.extend(pending);
+            }
+        }
+    }
+    selected.sort_by_key(|i| (generation[i], *i));
+
+    let mut done: HashMap<usize, Bitmap> = HashMap::new();
+    let mut entries = Vec::new();
+    for &commit in &selected {
+        if let Some(bitmap) = closure(commit, &graph, &positions, &done) {
+            done.insert(commit, bitmap.clone());
+            entries.push((commit, bitmap));
+        }
+    }
+
+    let mut types: [Bitmap; 4] = Default::default();
+    for (i, &kind) in graph.kinds.iter().enumerate() {
+        if (1..=4).contains(&kind) {
+            types[kind as usize - 1].set(positions[i]);
+        }
+    }
+
+    let mut data = Vec::new();
+    data.extend_from_slice(b"BITM");
+    data.extend_from_slice(&1u16.to_be_bytes());
+    data.extend_from_slice(&(FULL_DAG | HASH_CACHE).to_be_bytes());
+    data.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+    data.extend_from_slice(&midx.checksum);
+    for bitmap in &types {
+        ewah_encode(bitmap, &mut data);
+    }
+    for (commit, bitmap) in &entries {
+        data.extend_from_slice(&(*commit as u32).to_be_bytes());
+        data.extend_from_slice(&[0, 0]);
+        ewah_encode(bitmap, &mut data);
+    }
+    for hash in &name_hashes {
+        data.extend_from_slice(&hash.to_be_bytes());
+    }
+    let checksum: [u8; 20] = Sha1::digest(&data).into();
+    data.extend_from_slice(&checksum);
+    write_atomic(&bitmap_path(objects_dir, midx), &data)?;
+    Ok(entries.len())
+}
+
+// Checks every commit bitmap and the type bitmaps against a fresh walk of
+// the objects, returning what does not match.
+pub fn verify_bitmap(objects_dir: &Path, midx: &MultiPackIndex, bitmaps: &BitmapIndex) -> Result<Vec<String>, ItError> {
+    let mut errors = Vec::new();
+    let Some(order) = &midx.pack_order else {
+        return Ok(vec!["bitmap without a pack order (RIDX) chunk".to_string()]);
+    };
+    let (graph, _) = load_graph(objects_dir, midx)?;
+    let mut positions = vec![0usize; order.len()];
+    for (pos, &i) in order.iter().enumerate() {
+        positions[i as usize] = pos;
+    }
+
+    for (kind, bitmap) in bitmaps.types.iter().enumerate() {
+        let mut expected = Bitmap::default();
+        for (i, &k) in graph.kinds.iter().enumerate() {
+            if k as usize == kind + 1 {
+                expected.set(positions[i]);
+            }
+        }
+        if expected.trimmed() != bitmap.trimmed() {
+            errors.push(format!("{} bitmap does not match the objects", type_name(kind as u8 + 1)));
+        }
+    }
+
+    let mut commits: Vec<(&u32, &Bitmap)> = bitmaps.commits.iter().collect();
+    commits.sort_by_key(|(commit, _)| **commit);
+    for (&commit, bitmap) in commits {
+        let hash = midx.hashes.get(commit as usize).map(hex::encode).unwrap_or_default();
+        match closure(commit as usize, &graph, &positions, &HashMap::new()) {
+            Some(expected) if expected.trimmed() == bitmap.trimmed() => {}
+            Some(_) => errors.push(format!("bitmap for commit {hash} does not match what it reaches")),
+            None => errors.push(format!("commit {hash} reaches objects outside the multi-pack-index")),
+        }
+    }
+    Ok(errors)
+}
+
+// The objects reachable from `roots` as far as the bitmaps know: the union
+// of the bitmaps of every root that has one, with their name hashes. Empty
+// without a usable multi-pack-index and bitmap.
+pub fn reachable_from(objects_dir: &Path, roots: &[String]) -> Result<BTreeMap<String, u32>, ItError> {
+    let mut found = BTreeMap::new();
+    let Some(midx) = midx::load_current(objects_dir)? else {
+        return Ok(found);
+    };
+    let (Some(order), Ok(Some(bitmaps))) = (&midx.pack_order, load_bitmap(objects_dir, &midx)) else {
+        return Ok(found);
+    };
+
+    let mut reached = Bitmap::default();
+    for root in roots {
+        let sha: Option<[u8; 20]> = hex::decode(root).ok().and_then(|s| s.try_into().ok());
+        if let Some(bitmap) = sha.and_then(|sha| midx.find(&sha)).and_then(|i| bitmaps.commits.get(&(i as u32))) {
+            reached.or(bitmap);
+        }
+    }
+    for bit in reached.ones() {
+        let Some(&i) = order.get(bit) else {
+            continue;
+        };
+        let name_hash = bitmaps.name_hashes.as_ref().map_or(0, |h| h[i as usize]);
+        found.insert(hex::encode(midx.hashes[i as usize]), name_hash);
+    }
+    Ok(found)
+}
+
+// Stale bitmaps left behind by earlier multi-pack-indexes.
+pub fn remove_stale_bitmaps(objects_dir: &Path, keep: Option<&Path>) -> Result<(), ItError> {
+    let Ok(entries) = fs::read_dir(objects_dir.join("pack")) else {
+        return Ok(());
+    };
+    for entry in entries {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        if name.starts_with("multi-pack-index-") && name.ends_with(".bitmap") && Some(path.as_path()) != keep {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
@@ -1,33 +1,32 @@
-use colored::*;
-
-pub fn success(msg: &str) {
-    println!("{} {}", "✓".green().bold(), msg.green());
-}
-
-pub fn error(msg: &str) {
-    eprintln!("{} {}", "✗".red().bold(), msg.red());
-}
-
-pub fn info(msg: &str) {
-    println!("{} {}", "i".blue().bold(), msg.blue());
-}
-
-pub fn warning(msg: &str) {
-    println!("{} {}", "⚠".yellow().bold(), msg.yellow());
-}
-
-pub fn step(step_num: usize, total: usize, msg: &str) {
-    println!("[{}/{}] {}", step_num, total, msg.cyan());
-}
-
-pub fn commit_hash(hash: &str) -> String {
-    hash.bright_green().bold().to_string()
-}
-
-pub fn branch_name(name: &str) -> String {
-    name.cyan().bold().to_string()
-}
-
-pub fn file_path(path: &str) -> String {
-    path.white().to_string()
+// color.ui: "always" forces colors, "never"/false disables them and "auto"
+// or true (the default) leave the decision to `colored`'s own detection.
+// Returns the override to apply, if any.
+pub fn parse_color_ui(value: &str) -> Result<Option<bool>, crate::error::ItError> {
+    match value.to_ascii_lowercase().as_str() {
+        "always" => Ok(Some(true)),
+        "never" => Ok(Some(false)),
+        "auto" => Ok(None),
+        v => match crate::config::parse_bool(v) {
+            Some(true) => Ok(None),
+            Some(false) => Ok(Some(false)),
+            None => Err(crate::error::ItError::InvalidConfig(format!("bad color.ui value '{value}'"))),
+        },
+    }
+}
+
+// A bad color.ui only costs the colors, so it is warned about rather than
+// failing the command, which would leave no way to fix it with `it config`.
+pub fn apply_color_config() -> Result<(), crate::error::ItError> {
+    // outside a repository only the global settings apply
+    let repo_path = crate::repository::repo_path().unwrap_or_else(|_| std::path::PathBuf::from(".it"));
+    let config = crate::config::Config::load(&repo_path)?;
+    let Some(value) = config.get("color.ui") else {
+        return Ok(());
+    };
+    match parse_color_ui(value) {
+        Ok(Some(on)) => colored::control::set_override(on),
+        Ok(None) => {}
+        Err(_) => eprintln!("warning: bad color.ui value '{value}', using auto"),
+    }
+    Ok(())
 }
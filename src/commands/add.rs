@@ -1,55 +1,197 @@
 use crate::{
-    commands::hash_object::{hash_object, hex_to_sha_bytes},
-    index::{IndexEntry, read_index, write_index},
+    config::Config,
+    error::ItError,
+    ignore::Ignore,
+    index::{
+        IndexEntry, StatCheck, StatData, effective_mode, hash_worktree_blob, lock_index, read_index, write_locked_index,
+    },
+    lfs::Lfs,
+    pathspec::Pathspec,
+    repository,
 };
 use std::{
+    collections::BTreeSet,
     fs,
-    path::{Path, PathBuf},
+    path::Path,
 };
 use colored::*;
 
-pub fn add(paths: Vec<String>) -> std::io::Result<()> {
-    let repo_path = std::env::current_dir().unwrap().join(".it");
-    let mut entries = read_index(&repo_path).unwrap();
+pub struct AddArgs {
+    pub paths: Vec<String>,
+    pub force: bool,
+    // -A: stage new, modified and deleted files, the whole tree if no paths
+    pub all: bool,
+    // -u: only modified and deleted tracked files, the whole tree if no paths
+    pub update: bool,
+    pub dry_run: bool,
+}
+
+pub fn add(args: AddArgs) -> Result<(), ItError> {
+    let worktree = repository::worktree()?;
+    let repo_path = repository::repo_path()?;
+    if args.paths.is_empty() && !args.all && !args.update {
+        println!("{}", "Nothing specified, nothing added.".yellow());
+        return Ok(());
+    }
+
+    let lock = lock_index(&repo_path)?;
+    let mut entries = read_index(&repo_path)?;
+    let config = Config::load(&repo_path)?;
+    let mut ignore = Ignore::load(&worktree, &repo_path, &config)?;
+    let check = StatCheck::load(&repo_path, &config)?;
+    let lfs = Lfs::load(&worktree, &repo_path, &config)?;
+    let pathspec = Pathspec::parse(&args.paths)?;
+    let tracked: BTreeSet<String> = entries.iter().map(|e| e.path.clone()).collect();
+    let in_repo_dir = |p: &str| p == ".it" || p.starts_with(".it/");
+
+    let mut candidates = BTreeSet::new();
+    let mut ignored_paths = Vec::new();
 
-    for path_str in &paths {
-        let path = PathBuf::from(path_str);
-        if !path.exists() {
-            println!("{}",format!("error: '{}' did not match any files", path_str).red());
-            continue;
+    // tracked files are always refreshed, even when an ignore rule matches them
+    for path in tracked.iter().filter(|p| pathspec.matches(p)) {
+        if fs::symlink_metadata(worktree.join(path)).is_ok() {
+            candidates.insert(path.clone());
         }
-        collect_files(&path, &mut entries).unwrap();
     }
 
-    entries.sort_by(|a, b| a.path.cmp(&b.path));
-    write_index(&repo_path, &entries)
-}
+    if !args.update {
+        for item in pathspec.includes().filter(|i| !i.has_wildcard() && !i.pattern.is_empty()) {
+            let Ok(meta) = fs::symlink_metadata(worktree.join(&item.pattern)) else {
+                continue;
+            };
+            // naming an ignored path explicitly needs -f, its contents are skipped silently
+            if !args.force
+                && !in_repo_dir(&item.pattern)
+                && !tracked.contains(&item.pattern)
+                && ignore.is_ignored(&item.pattern, meta.is_dir())?
+            {
+                ignored_paths.push(item.pattern.clone());
+            }
+        }
 
-fn collect_files(path: &Path, entries: &mut Vec<IndexEntry>) -> std::io::Result<()> {
-    if path.is_dir() {
-        for entry in fs::read_dir(path)? {
-            let entry = entry?;
-            let entry_path = entry.path();
-            if entry.file_name() == ".it" || entry.file_name() == "target" {
+        // walk only below the literal part of each pathspec
+        let mut roots: Vec<&str> = pathspec.includes().map(|i| i.literal_dir()).collect();
+        if roots.is_empty() {
+            roots.push("");
+        }
+        roots.sort();
+        roots.dedup();
+
+        for root in roots.into_iter().filter(|r| !in_repo_dir(r)) {
+            let abs = if root.is_empty() { worktree.clone() } else { worktree.join(root) };
+            let Ok(meta) = fs::symlink_metadata(&abs) else {
+                continue;
+            };
+            if !root.is_empty() && !args.force && ignore.is_ignored(root, meta.is_dir())? {
+                continue;
+            }
+            if !meta.is_dir() {
+                if pathspec.matches(root) {
+                    candidates.insert(root.to_string());
+                }
                 continue;
             }
-            collect_files(&entry_path, entries)?;
+            for file in ignore.walk(root, args.force)? {
+                if pathspec.matches(&file.path) {
+                    candidates.insert(file.path);
+                }
+            }
+        }
+    }
+
+    // tracked files under the pathspec that are gone from disk are staged as deletions
+    let removed: Vec<String> = tracked
+        .iter()
+        .filter(|p| pathspec.matches(p) && fs::symlink_metadata(worktree.join(p)).is_err())
+        .cloned()
+        .collect();
+
+    // as in git, a pathspec that matches nothing fails the whole add, before
+    // anything is staged; with -u only tracked files count
+    for item in pathspec.includes().filter(|i| !i.pattern.is_empty()) {
+        let known = tracked.iter().chain(&candidates).any(|p| item.matches(p));
+        let on_disk = !args.update && fs::symlink_metadata(worktree.join(&item.pattern)).is_ok();
+        if !known && !on_disk {
+            let what = if args.update { "file(s) known to it" } else { "files" };
+            return Err(ItError::Fatal(format!("pathspec '{}' did not match any {what}", item.original)));
+        }
+    }
+
+    if args.dry_run {
+        for path in &candidates {
+            let existing = entries.iter().find(|e| &e.path == path);
+            if would_change(&worktree, path, existing, &check, &lfs)? {
+                println!("add '{}'", path);
+            }
+        }
+        for path in &removed {
+            println!("remove '{}'", path);
         }
     } else {
-        stage_file(path, entries)?;
+        for path in &candidates {
+            stage_file(&worktree, path, &check, &lfs, &mut entries)?;
+        }
+        entries.retain(|e| !removed.contains(&e.path));
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        write_locked_index(lock, &repo_path, &entries)?;
+    }
+
+    if !ignored_paths.is_empty() {
+        println!("{}", "The following paths are ignored by one of your .itignore files:".yellow());
+        for path in &ignored_paths {
+            println!("{}", path);
+        }
+        println!("{}", "hint: Use -f if you really want to add them.".yellow());
     }
     Ok(())
 }
 
-fn stage_file(path: &Path, entries: &mut Vec<IndexEntry>) -> std::io::Result<()> {
-    let data = fs::read(path)?;
-    let sha_hex = hash_object(&data)?;
-    let path_str = path.to_string_lossy().to_string().replace("\\", "/");
+// for --dry-run: hashes without writing the blob to the object store
+fn would_change(
+    worktree: &Path,
+    path: &str,
+    existing: Option<&IndexEntry>,
+    check: &StatCheck,
+    lfs: &Lfs,
+) -> std::io::Result<bool> {
+    let Some(entry) = existing else {
+        return Ok(true);
+    };
+    let abs = worktree.join(path);
+    if check.is_unchanged(entry, &fs::symlink_metadata(&abs)?) {
+        return Ok(false);
+    }
+    let (sha, disk_mode) = hash_worktree_blob(&abs, lfs, false)?;
+    let mode = effective_mode(disk_mode, Some(entry.mode), check.trust_filemode);
+    Ok(mode != entry.mode || sha != entry.sha)
+}
+
+fn stage_file(
+    worktree: &Path,
+    path_str: &str,
+    check: &StatCheck,
+    lfs: &Lfs,
+    entries: &mut Vec<IndexEntry>,
+) -> std::io::Result<()> {
+    let abs = worktree.join(path_str);
+    let meta = fs::symlink_metadata(&abs)?;
+    let existing = entries.iter().find(|e| e.path == path_str);
+    if existing.is_some_and(|e| check.is_unchanged(e, &meta)) {
+        return Ok(());
+    }
+    let old_mode = existing.map(|e| e.mode);
+
+    let (sha, disk_mode) = hash_worktree_blob(&abs, lfs, true)?;
+    let path_str = path_str.to_string();
+    let mode = effective_mode(disk_mode, old_mode, check.trust_filemode);
     entries.retain(|e| e.path != path_str);
 
-    let flags = (path_str.len() as u16) & 0x0FFF; // better than doing min
+    // stage 0, write_index fills in the name length
+    let flags = 0;
     entries.push(IndexEntry {
-        sha: hex_to_sha_bytes(&sha_hex),
+        stat: StatData::from_metadata(&meta),
+        mode,
+        sha,
         flags,
         path: path_str,
     });
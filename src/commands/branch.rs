@@ -1,9 +1,10 @@
 use colored::Colorize;
 
-use crate::{ commands::log::log_branch, error::ItError};
+use crate::repository;
+use crate::{ commands::log::log_branch, error::ItError, refs::update_ref};
 use std::fs;
 pub fn branch(name: Option<String>) -> Result<(), ItError> {
-    let repo_path = std::env::current_dir()?.join(".it");
+    let repo_path = repository::repo_path()?;
     if !repo_path.exists() || !repo_path.is_dir() {
         return Err(ItError::NotARepository);
     }
@@ -25,9 +26,8 @@ pub fn branch(name: Option<String>) -> Result<(), ItError> {
             }
 
             let current_hash = fs::read_to_string(current_ref_path)?;
-            fs::write(new_branch_path, current_hash)?;
-            
-     
+            // the branch must still not exist once we hold its lock
+            update_ref(&repo_path, &format!("refs/heads/{branch_name}"), current_hash.trim(), Some(None))?;
 
             log_branch(&branch_name)?;
             println!("{} {} ",  "branch".green(), format!("'{}' created", branch_name.cyan()).bold());
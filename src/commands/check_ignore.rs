@@ -0,0 +1,38 @@
+use crate::repository;
+use crate::{config::Config, error::ItError, ignore::Ignore};
+
+pub fn check_ignore(paths: Vec<String>, verbose: bool, non_matching: bool) -> Result<(), ItError> {
+    let worktree = repository::worktree()?;
+    let repo_path = repository::repo_path()?;
+    if !repo_path.is_dir() {
+        return Err(ItError::NotARepository);
+    }
+    let config = Config::load(&repo_path)?;
+    let mut ignore = Ignore::load(&worktree, &repo_path, &config)?;
+
+    let mut any_ignored = false;
+    for path_str in &paths {
+        let rel = repository::worktree_path(path_str)?;
+        let is_dir = path_str.ends_with('/') || worktree.join(&rel).is_dir();
+
+        match ignore.check(&rel, is_dir)? {
+            // with -v a matching `!pattern` is reported too, it explains why
+            // the path is *not* ignored
+            Some(pattern) if verbose => {
+                any_ignored |= !pattern.negated;
+                println!("{}:{}:{}\t{}", pattern.source, pattern.line, pattern.original, path_str);
+            }
+            Some(pattern) if !pattern.negated => {
+                any_ignored = true;
+                println!("{}", path_str);
+            }
+            _ if verbose && non_matching => println!("::\t{}", path_str),
+            _ => {}
+        }
+    }
+
+    if !any_ignored {
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use crate::{
+    commands::status::collapse_untracked,
+    config::Config,
+    error::ItError,
+    ignore::Ignore,
+    index::read_index,
+    repository,
+};
+use colored::Colorize;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+pub struct CleanArgs {
+    pub dry_run: bool,
+    pub force: bool,
+    pub directories: bool,
+    // -x: also remove ignored files, -X: remove only ignored files
+    pub ignored_too: bool,
+    pub only_ignored: bool,
+}
+
+pub fn clean(args: CleanArgs) -> Result<(), ItError> {
+    let worktree = repository::worktree()?;
+    let repo_path = repository::repo_path()?;
+    if !repo_path.is_dir() {
+        return Err(ItError::NotARepository);
+    }
+
+    let config = Config::load(&repo_path)?;
+    let require_force = config.get_bool("clean.requireforce")?.unwrap_or(true);
+    if require_force && !args.force && !args.dry_run {
+        return Err(ItError::Fatal(
+            "clean.requireForce defaults to true and neither -n nor -f given; refusing to clean".to_string(),
+        ));
+    }
+
+    let entries = read_index(&repo_path)?;
+    let tracked: BTreeSet<&str> = entries.iter().map(|e| e.path.as_str()).collect();
+    let mut ignore = Ignore::load(&worktree, &repo_path, &config)?;
+
+    let candidates: Vec<String> = ignore
+        .walk("", args.ignored_too || args.only_ignored)?
+        .into_iter()
+        .filter(|f| !tracked.contains(f.path.as_str()))
+        .filter(|f| !args.only_ignored || f.ignored)
+        .map(|f| f.path)
+        .collect();
+
+    for shown in collapse_untracked(&candidates, &tracked) {
+        let is_dir = shown.ends_with('/');
+        if is_dir && !args.directories {
+            continue;
+        }
+        if args.dry_run {
+            println!("Would remove {}", shown);
+            continue;
+        }
+
+        if is_dir {
+            let dir = shown.trim_end_matches('/');
+            for file in candidates.iter().filter(|c| c.starts_with(&shown)) {
+                fs::remove_file(worktree.join(file))?;
+            }
+            remove_empty_dirs(&worktree.join(dir))?;
+        } else {
+            fs::remove_file(worktree.join(&shown))?;
+        }
+        println!("{} {}", "Removing".red(), shown);
+    }
+
+    Ok(())
+}
+
+// removes `dir` and any directories below it that are left empty
+fn remove_empty_dirs(dir: &Path) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            remove_empty_dirs(&entry.path())?;
+        }
+    }
+    if fs::read_dir(dir)?.next().is_none() {
+        fs::remove_dir(dir)?;
+    }
+    Ok(())
+}
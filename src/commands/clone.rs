@@ -0,0 +1,203 @@
+use crate::{
+    commands::{
+        init::{create_repository, template_dir},
+        log::log_clone,
+    },
+    config::{Config, set_value},
+    error::ItError,
+    index::{IndexEntry, StatData, write_index},
+    lfs::Lfs,
+    lockfile::write_atomic,
+    object_id::ObjectFormat,
+    reachable::{object_exists, shallow_commits},
+    refs::{read_symbolic_ref, update_ref, write_head},
+    repository,
+    transport::{self, add_shallow, copy_objects, link_objects, list_refs},
+    tree::{checkout_tree, commit_tree_hash, flatten_tree, peel_tag},
+};
+use colored::Colorize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct CloneArgs {
+    pub source: String,
+    pub directory: Option<PathBuf>,
+    pub bare: bool,
+    // branch (or tag) to check out instead of the source's HEAD
+    pub branch: Option<String>,
+    pub depth: Option<usize>,
+}
+
+// `it clone <path> [<dir>]` copies a repository reached through the
+// filesystem. The source's branches become refs/remotes/origin/*, its
+// checked-out branch (or --branch) becomes the local branch, which is
+// checked out and staged, and remote.origin.* records where it came from.
+//
+// A full clone hard-links the source's loose objects and packs as they are.
+// --depth <n> takes only the last n commits of the chosen branch, copying
+// just the objects they need and listing the commits where history stops in
+// .it/shallow. --bare copies the branches as they are into a repository with
+// no worktree.
+pub fn clone(args: CloneArgs) -> Result<(), ItError> {
+    if args.depth == Some(0) {
+        return Err(ItError::Fatal("depth 0 is not a positive number".to_string()));
+    }
+    let source = transport::open(&args.source)?;
+    let url = transport::url_path(&args.source)?;
+    let format = ObjectFormat::load(&source)?;
+
+    let directory = std::env::current_dir()?.join(match args.directory {
+        Some(directory) => directory,
+        None => default_directory(&url, args.bare),
+    });
+    if fs::read_dir(&directory).is_ok_and(|mut entries| entries.next().is_some()) {
+        return Err(ItError::Fatal(format!(
+            "destination path '{}' already exists and is not an empty directory.",
+            directory.display()
+        )));
+    }
+
+    let refs = list_refs(&source)?;
+    let lookup = |name: &str| refs.iter().find(|(n, _)| n == name).map(|(_, hash)| hash.clone());
+    let head_branch = read_symbolic_ref(&source, "HEAD")?
+        .and_then(|target| target.strip_prefix("refs/heads/").map(str::to_string));
+
+    // the ref to check out: a branch, or a tag, which leaves HEAD detached
+    let (checkout_ref, tip) = match &args.branch {
+        Some(name) => match (lookup(&format!("refs/heads/{name}")), lookup(&format!("refs/tags/{name}"))) {
+            (Some(hash), _) => (Some(format!("refs/heads/{name}")), Some(hash)),
+            (None, Some(hash)) => (Some(format!("refs/tags/{name}")), Some(hash)),
+            (None, None) => {
+                return Err(ItError::Fatal(format!("Remote branch {name} not found in upstream origin")));
+            }
+        },
+        None => {
+            let name = head_branch.as_ref().map(|b| format!("refs/heads/{b}"));
+            let tip = name.as_deref().and_then(lookup);
+            (name, tip)
+        }
+    };
+    let branch = checkout_ref.as_deref().and_then(|r| r.strip_prefix("refs/heads/")).map(str::to_string);
+
+    println!("Cloning into {}'{}'...", if args.bare { "bare repository " } else { "" }, directory.display());
+    let repo_path = if args.bare { directory.clone() } else { directory.join(".it") };
+    let config = Config::load(&repo_path)?;
+    let initial = branch.clone().or(head_branch.clone()).unwrap_or_else(|| "main".to_string());
+    create_repository(&repo_path, args.bare, &initial, format, template_dir(None, &config).as_deref())?;
+    repository::setup(Some(repo_path.clone()), (!args.bare).then(|| directory.clone()))?;
+
+    // --depth implies a single branch, as in git
+    let single = args.depth.is_some();
+    let wanted: Vec<(String, String)> = match (single, &checkout_ref, &tip) {
+        (true, Some(name), Some(hash)) => vec![(name.clone(), hash.clone())],
+        (true, _, _) => Vec::new(),
+        (false, _, _) => refs.clone(),
+    };
+    let objects = match args.depth {
+        None => {
+            add_shallow(&repo_path, &shallow_commits(&source)?)?;
+            link_objects(&source, &repo_path)?
+        }
+        Some(depth) => {
+            let tips: Vec<String> = wanted.iter().map(|(_, hash)| hash.clone()).collect();
+            let transfer = copy_objects(&source, &repo_path, &tips, Some(depth))?;
+            add_shallow(&repo_path, &transfer.shallow)?;
+            transfer.objects
+        }
+    };
+    println!("Copied {objects} object file(s)");
+
+    // tags come along when what they point at did
+    let mut fetched = wanted.clone();
+    if single {
+        for (name, hash) in refs.iter().filter(|(n, _)| n.starts_with("refs/tags/")) {
+            if !fetched.iter().any(|(n, _)| n == name) && object_exists(&repo_path, hash)? {
+                fetched.push((name.clone(), hash.clone()));
+            }
+        }
+    }
+    for (name, hash) in &fetched {
+        let local = match name.strip_prefix("refs/heads/") {
+            Some(b) if !args.bare => format!("refs/remotes/origin/{b}"),
+            _ => name.clone(),
+        };
+        update_ref(&repo_path, &local, hash, None)?;
+    }
+
+    let config_path = repo_path.join("config");
+    set_value(&config_path, "remote.origin.url", &url.to_string_lossy())?;
+    if !args.bare {
+        let fetch = match (&branch, single) {
+            (Some(b), true) => format!("+refs/heads/{b}:refs/remotes/origin/{b}"),
+            _ => "+refs/heads/*:refs/remotes/origin/*".to_string(),
+        };
+        set_value(&config_path, "remote.origin.fetch", &fetch)?;
+    }
+    if source.join("lfs/objects").is_dir() {
+        set_value(&config_path, "lfs.url", &source.join("lfs/objects").to_string_lossy())?;
+    }
+
+    let Some(tip) = tip else {
+        // branches or tags came along, only the one HEAD names is missing
+        let warning = if refs.is_empty() {
+            "warning: You appear to have cloned an empty repository."
+        } else {
+            "warning: remote HEAD refers to nonexistent ref, unable to checkout"
+        };
+        eprintln!("{}", warning.yellow());
+        return Ok(());
+    };
+    if args.bare {
+        return Ok(());
+    }
+
+    // origin/HEAD follows the source's checked-out branch, if it came along
+    if let Some(remote_head) = head_branch.filter(|b| fetched.iter().any(|(n, _)| *n == format!("refs/heads/{b}"))) {
+        write_atomic(&repo_path.join("refs/remotes/origin/HEAD"), format!("ref: refs/remotes/origin/{remote_head}\n").as_bytes())?;
+    }
+    let commit = peel_tag(&tip)?;
+    match &branch {
+        Some(b) => {
+            update_ref(&repo_path, &format!("refs/heads/{b}"), &commit, None)?;
+            set_value(&config_path, &format!("branch.{b}.remote"), "origin")?;
+            set_value(&config_path, &format!("branch.{b}.merge"), &format!("refs/heads/{b}"))?;
+            log_clone(b, &commit, &url.to_string_lossy())?;
+        }
+        None => write_head(&repo_path, &commit)?,
+    }
+    checkout(&directory, &repo_path, &commit)?;
+    println!("{}", "done.".green());
+    Ok(())
+}
+
+// "../src/project" clones into "project", "server.it" into "server", and a
+// bare clone gets the ".it" suffix back.
+fn default_directory(url: &Path, bare: bool) -> PathBuf {
+    let name = url
+        .components()
+        .filter_map(|c| match c {
+            std::path::Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .rfind(|part| part != ".it")
+        .unwrap_or_else(|| "repository".to_string());
+    let name = name.strip_suffix(".it").unwrap_or(&name).to_string();
+    PathBuf::from(if bare { format!("{name}.it") } else { name })
+}
+
+// Writes the commit's tree into the worktree and stages it, taking each
+// entry's stat data from the file just written.
+fn checkout(worktree: &Path, repo_path: &Path, commit: &str) -> Result<(), ItError> {
+    let tree = commit_tree_hash(commit)?;
+    let mut lfs = Lfs::load(worktree, repo_path, &Config::load(repo_path)?)?;
+    lfs.use_tree_attributes(&tree)?;
+    checkout_tree(&tree, worktree, &lfs)?;
+
+    let mut entries = Vec::new();
+    for (path, (mode, sha)) in flatten_tree(&tree)? {
+        let stat = fs::symlink_metadata(worktree.join(&path)).map(|m| StatData::from_metadata(&m)).unwrap_or_default();
+        let mode = u32::from_str_radix(&mode, 8).unwrap_or(0o100644);
+        entries.push(IndexEntry { stat, mode, sha, flags: 0, path });
+    }
+    write_index(repo_path, &entries)
+}
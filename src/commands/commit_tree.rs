@@ -1,13 +1,16 @@
 use std::{
     fs,
-    path::Path,
+    io::Read,
     time::{SystemTime, UNIX_EPOCH},
 };
 
 use crate::{
-    commands::hash_object::{compress_data, compute_hash, store_object},
+    commands::hash_object::{build_object, compress_data, compute_hash, read_object, store_object},
     commands::log::log_commit,
+    config::Config,
     error::ItError,
+    refs::{resolve_object, resolve_rev, update_ref},
+    repository,
 };
 use colored::*;
 
@@ -16,66 +19,92 @@ pub fn commit_tree(
     parent: Option<String>,
     message: &str,
 ) -> Result<String, Box<dyn std::error::Error>> {
-    let author_name = std::env::var("GIT_AUTHOR_NAME").unwrap_or_else(|_| "Unknown".to_string());
-    let author_email = std::env::var("GIT_AUTHOR_EMAIL").unwrap_or_else(|_| "Unknown".to_string());
+    let parents: Vec<String> = parent.iter().cloned().collect();
+    let commit_hash = create_commit(tree_hash, &parents, message)?;
 
-    let committer_name = author_name.clone();
-    let committer_email = author_email.clone();
+    let repo_path = repository::repo_path()?;
+    let head_content = fs::read_to_string(repo_path.join("HEAD"))?;
+
+    if head_content.starts_with("ref:") {
+        let ref_path_str = head_content.trim_start_matches("ref:").trim();
+        // fails if another commit moved the branch since we read the parent
+        update_ref(&repo_path, ref_path_str, &commit_hash, Some(parent.as_deref()))?;
+    } else {
+        println!("{}","HEAD is detached; commit created without updating refs".yellow().bold());
+    }
 
-    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    log_commit(&commit_hash, parent.clone(), message)?;
+
+    println!(" {} {}","committed:".green(), commit_hash[..8].to_string().yellow().bold());
+    Ok(commit_hash)
+}
+
+// Writes a commit object and returns its hash, touching no refs. The author
+// comes from GIT_AUTHOR_NAME / GIT_AUTHOR_EMAIL, then user.name / user.email;
+// the committer from GIT_COMMITTER_*, then the author. GIT_AUTHOR_DATE and
+// GIT_COMMITTER_DATE ("<unix seconds> <+hhmm>") pin the timestamps.
+pub fn create_commit(tree_hash: &str, parents: &[String], message: &str) -> Result<String, ItError> {
+    let config = Config::load(&repository::repo_path()?)?;
+
+    // environment wins over user.name / user.email, like git
+    let author_name = std::env::var("GIT_AUTHOR_NAME")
+        .ok()
+        .or_else(|| config.get("user.name").map(str::to_string))
+        .unwrap_or_else(|| "Unknown".to_string());
+    let author_email = std::env::var("GIT_AUTHOR_EMAIL")
+        .ok()
+        .or_else(|| config.get("user.email").map(str::to_string))
+        .unwrap_or_else(|| "Unknown".to_string());
+
+    let committer_name = std::env::var("GIT_COMMITTER_NAME").unwrap_or_else(|_| author_name.clone());
+    let committer_email = std::env::var("GIT_COMMITTER_EMAIL").unwrap_or_else(|_| author_email.clone());
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| ItError::Fatal(e.to_string()))?
+        .as_secs();
 
     // TODO: use utc
     let timezone_offset = 19800;
     let timezone = "+0530";
-    let local_timestamp = now + timezone_offset;
+    let local_timestamp = format!("{} {}", now + timezone_offset, timezone);
+    let author_date = env_date("GIT_AUTHOR_DATE")?.unwrap_or(local_timestamp);
+    let committer_date = env_date("GIT_COMMITTER_DATE")?.unwrap_or_else(|| author_date.clone());
 
     let mut content = String::new();
     content.push_str(&format!("tree {}\n", tree_hash));
-    if let Some(parent_hash) = &parent {
+    for parent_hash in parents {
         content.push_str(&format!("parent {}\n", parent_hash));
     }
 
-    content.push_str(&format!(
-        "author {} <{}> {} {}\n",
-        author_name, author_email, local_timestamp, timezone
-    ));
-    content.push_str(&format!(
-        "committer {} <{}> {} {}\n",
-        committer_name, committer_email, local_timestamp, timezone
-    ));
-    content.push_str("\n");
+    content.push_str(&format!("author {} <{}> {}\n", author_name, author_email, author_date));
+    content.push_str(&format!("committer {} <{}> {}\n", committer_name, committer_email, committer_date));
+    content.push('\n');
     content.push_str(message);
 
-    let content_bytes = content.as_bytes();
-    let header = format!("commit {}\0", content_bytes.len());
-    let mut full_data = Vec::new();
-    full_data.extend_from_slice(header.as_bytes());
-    full_data.extend_from_slice(content_bytes);
-
+    let full_data = build_object(content.as_bytes(), "commit");
     let commit_hash = compute_hash(&full_data);
     let compressed_data = compress_data(&full_data)?;
     store_object(&commit_hash, &compressed_data)?;
+    Ok(commit_hash)
+}
 
-    let head_path = Path::new(".it/HEAD");
-    let head_content = fs::read_to_string(head_path)?;
-
-    if head_content.starts_with("ref:") {
-        let ref_path_str = head_content.trim_start_matches("ref:").trim();
-        let ref_path = Path::new(".it").join(ref_path_str);
-        fs::create_dir_all(ref_path.parent().unwrap())?;
-        fs::write(&ref_path, &commit_hash)?;
-    } else {
-        println!("{}","HEAD is detached; commit created without updating refs".yellow().bold());
+// "<seconds> <+hhmm>", optionally written "@<seconds> <+hhmm>" as git allows
+fn env_date(var: &str) -> Result<Option<String>, ItError> {
+    let Ok(value) = std::env::var(var) else {
+        return Ok(None);
+    };
+    let invalid = || ItError::Fatal(format!("invalid date format in {var}: {value}"));
+    let (secs, tz) = value.trim().trim_start_matches('@').split_once(' ').ok_or_else(invalid)?;
+    let tz_valid = tz.len() == 5 && tz.starts_with(['+', '-']) && tz[1..].chars().all(|c| c.is_ascii_digit());
+    if secs.parse::<u64>().is_err() || !tz_valid {
+        return Err(invalid());
     }
-
-    log_commit(&commit_hash, parent.clone(), message)?;
-
-    println!(" {} {}","committed:".green(), commit_hash[..8].to_string().yellow().bold());
-    Ok(commit_hash)
+    Ok(Some(format!("{secs} {tz}")))
 }
 
 pub fn get_parent() -> Result<Option<String>, ItError> {
-    let repo_path = std::env::current_dir()?.join(".it");
+    let repo_path = repository::repo_path()?;
     let head_content = fs::read_to_string(repo_path.join("HEAD"))?;
 
     // if head is detached
@@ -93,3 +122,47 @@ pub fn get_parent() -> Result<Option<String>, ItError> {
         Ok(None)
     }
 }
+
+// `it commit-tree`: writes a commit for an existing tree and prints its hash.
+// Several -m paragraphs are joined by blank lines; without -m the message is
+// read from stdin. No ref is moved.
+pub fn commit_tree_plumbing(tree: &str, parents: &[String], messages: &[String]) -> Result<(), ItError> {
+    let repo_path = repository::repo_path()?;
+    if !repo_path.is_dir() {
+        return Err(ItError::NotARepository);
+    }
+
+    let tree_hash = resolve_object(&repo_path, tree)?;
+    let (tree_type, _) = read_object(&tree_hash)?;
+    if tree_type != "tree" {
+        return Err(ItError::Fatal(format!("{tree} is a {tree_type}, not a tree")));
+    }
+
+    let mut parent_hashes: Vec<String> = Vec::new();
+    for parent in parents {
+        let hash = resolve_rev(&repo_path, parent)?;
+        if read_object(&hash)?.0 != "commit" {
+            return Err(ItError::Fatal(format!("{parent} is not a valid commit")));
+        }
+        // git drops repeated parents with a warning
+        if parent_hashes.contains(&hash) {
+            eprintln!("error: duplicate parent {hash} ignored");
+            continue;
+        }
+        parent_hashes.push(hash);
+    }
+
+    let mut message = if messages.is_empty() {
+        let mut input = String::new();
+        std::io::stdin().read_to_string(&mut input)?;
+        input
+    } else {
+        messages.join("\n\n")
+    };
+    if !message.is_empty() && !message.ends_with('\n') {
+        message.push('\n');
+    }
+
+    println!("{}", create_commit(&tree_hash, &parent_hashes, &message)?);
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use crate::color_utils::parse_color_ui;
+use crate::config::{Config, global_config_path, set_value, unset_value};
+use crate::error::ItError;
+use crate::repository;
+
+pub struct ConfigArgs {
+    pub global: bool,
+    pub get: bool,
+    pub unset: bool,
+    pub list: bool,
+    pub key: Option<String>,
+    pub value: Option<String>,
+}
+
+pub fn config(args: ConfigArgs) -> Result<(), ItError> {
+    // outside a repository only --global and reads of the global settings work
+    let repo_path = repository::repo_path().unwrap_or_else(|_| std::path::PathBuf::from(".it"));
+
+    let file = if args.global {
+        global_config_path()
+            .ok_or_else(|| ItError::InvalidConfig("$HOME not set, cannot locate global config".to_string()))?
+    } else {
+        if !repo_path.is_dir() && (args.unset || args.value.is_some()) {
+            return Err(ItError::NotARepository);
+        }
+        repo_path.join("config")
+    };
+
+    if args.list {
+        let config = if args.global {
+            Config::load_file(&file)?
+        } else {
+            Config::load(&repo_path)?
+        };
+        for entry in &config.entries {
+            match &entry.value {
+                Some(value) => println!("{}={}", entry.key, value),
+                None => println!("{}", entry.key),
+            }
+        }
+        return Ok(());
+    }
+
+    let key = args
+        .key
+        .ok_or_else(|| ItError::InvalidConfig("missing config key".to_string()))?;
+
+    if args.unset {
+        if unset_value(&file, &key)? == 0 {
+            // git exits 5 when there was nothing to unset
+            std::process::exit(5);
+        }
+        return Ok(());
+    }
+
+    match args.value {
+        Some(value) if !args.get => {
+            if key.eq_ignore_ascii_case("color.ui") {
+                parse_color_ui(&value)?;
+            }
+            set_value(&file, &key, &value)
+        }
+        _ => {
+            let config = if args.global {
+                Config::load_file(&file)?
+            } else {
+                Config::load(&repo_path)?
+            };
+            match config.get(&key) {
+                Some(value) => {
+                    println!("{value}");
+                    Ok(())
+                }
+                // nothing printed and exit 1, so scripts can test for a key
+                None => std::process::exit(1),
+            }
+        }
+    }
+}
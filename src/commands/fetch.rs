@@ -0,0 +1,143 @@
+use crate::{
+    commands::remote::Remote,
+    config::Config,
+    error::ItError,
+    lockfile::write_atomic,
+    reachable::object_exists,
+    refs::{read_ref, read_symbolic_ref, update_ref},
+    repository,
+    transport::{
+        self, add_shallow, check_format, copy_objects, is_fast_forward, list_refs, short_ref_name, update_line,
+        update_summary,
+    },
+};
+use std::path::Path;
+
+// A remote ref being fetched and the local ref it goes to, if any.
+struct Fetched {
+    name: String,
+    local: Option<String>,
+    hash: String,
+    force: bool,
+}
+
+// `it fetch <remote>` brings the remote's branches into refs/remotes/<remote>/*
+// (or wherever remote.<remote>.fetch maps them), copying the objects they
+// need, and follows tags that point into what was fetched. A path that is not
+// a configured remote is fetched too, with only FETCH_HEAD to show for it.
+//
+// A remote-tracking ref that would lose commits is only moved when its
+// refspec starts with `+`, as the default one does. Every fetched branch is
+// listed in FETCH_HEAD, the one to merge first.
+pub fn fetch(remote: String) -> Result<(), ItError> {
+    let repo_path = repository::repo_path()?;
+    let remote = Remote::lookup(&remote)?;
+    let source = transport::open(&remote.url)?;
+    check_format(&source)?;
+    let url = transport::url_path(&remote.url)?.to_string_lossy().to_string();
+
+    let refs = list_refs(&source)?;
+    let mut wanted = Vec::new();
+    for (name, hash) in refs.iter().filter(|(n, _)| n.starts_with("refs/heads/")) {
+        let spec = remote.fetch.iter().find(|spec| spec.map(name).is_some());
+        wanted.push(Fetched {
+            name: name.clone(),
+            local: spec.and_then(|spec| spec.map(name)),
+            hash: hash.clone(),
+            force: spec.is_some_and(|spec| spec.force),
+        });
+    }
+
+    let tips: Vec<String> = wanted.iter().map(|f| f.hash.clone()).collect();
+    let transfer = copy_objects(&source, &repo_path, &tips, None)?;
+    add_shallow(&repo_path, &transfer.shallow)?;
+
+    // tags whose commit is now here and which are not here yet; a path
+    // fetched without a remote name stores no refs, so takes no tags either
+    let tags = refs.iter().filter(|(n, _)| n.starts_with("refs/tags/") && remote.name.is_some());
+    for (name, hash) in tags {
+        if read_ref(&repo_path, name)?.is_none() && object_exists(&repo_path, &transport::peel(&source, hash)?)? {
+            let tag_transfer = copy_objects(&source, &repo_path, std::slice::from_ref(hash), None)?;
+            add_shallow(&repo_path, &tag_transfer.shallow)?;
+            wanted.push(Fetched { name: name.clone(), local: Some(name.clone()), hash: hash.clone(), force: false });
+        }
+    }
+
+    write_fetch_head(&repo_path, &source, &remote, &url, &wanted)?;
+
+    let checked_out = read_symbolic_ref(&repo_path, "HEAD")?.filter(|_| repository::worktree().is_ok());
+    let mut lines = Vec::new();
+    let mut rejected = false;
+    for Fetched { name, local, hash: new, force } in &wanted {
+        let Some(local) = local else {
+            continue;
+        };
+        let old = read_ref(&repo_path, local)?;
+        if old.as_deref() == Some(new.as_str()) {
+            continue;
+        }
+        let kind = if name.starts_with("refs/tags/") { "tag" } else { "branch" };
+        let (from, to) = (short_ref_name(name), short_ref_name(local));
+
+        if checked_out.as_deref() == Some(local.as_str()) {
+            lines.push(update_line('!', "[rejected]", from, to, " (refusing to fetch into the checked-out branch)"));
+            rejected = true;
+            continue;
+        }
+        let fast_forward = is_fast_forward(&repo_path, old.as_deref(), new)?;
+        if !fast_forward && !force {
+            lines.push(update_line('!', "[rejected]", from, to, " (non-fast-forward)"));
+            rejected = true;
+            continue;
+        }
+
+        update_ref(&repo_path, local, new, Some(old.as_deref()))?;
+        let (flag, summary) = update_summary(old.as_deref(), new, !fast_forward, kind);
+        let note = if fast_forward { "" } else { "  (forced update)" };
+        lines.push(update_line(flag, &summary, from, to, note));
+    }
+
+    if !lines.is_empty() {
+        println!("From {url}");
+        for line in lines {
+            println!("{line}");
+        }
+    }
+    if rejected {
+        return Err(ItError::Fatal("some local refs could not be updated".to_string()));
+    }
+    Ok(())
+}
+
+// FETCH_HEAD lists each fetched branch as "<hash>\t[not-for-merge]\tbranch
+// '<name>' of <url>". The branch marked for merge is the current branch's
+// upstream when it comes from this remote, else the remote's HEAD branch.
+fn write_fetch_head(
+    repo_path: &Path,
+    source: &Path,
+    remote: &Remote,
+    url: &str,
+    wanted: &[Fetched],
+) -> Result<(), ItError> {
+    let config = Config::load(repo_path)?;
+    let upstream = read_symbolic_ref(repo_path, "HEAD")?
+        .and_then(|head| head.strip_prefix("refs/heads/").map(str::to_string))
+        .filter(|branch| config.get(&format!("branch.{branch}.remote")) == remote.name.as_deref())
+        .and_then(|branch| config.get(&format!("branch.{branch}.merge")).map(str::to_string));
+    let merge = match (upstream, &remote.name) {
+        (Some(merge), Some(_)) => Some(merge),
+        _ => read_symbolic_ref(source, "HEAD")?,
+    };
+
+    let mut heads: Vec<&Fetched> = wanted.iter().filter(|f| f.name.starts_with("refs/heads/")).collect();
+    // the branch to merge goes first
+    heads.sort_by_key(|f| Some(&f.name) != merge.as_ref());
+    let text: String = heads
+        .iter()
+        .map(|f| {
+            let marker = if Some(&f.name) == merge.as_ref() { "" } else { "not-for-merge" };
+            format!("{}\t{marker}\tbranch '{}' of {url}\n", f.hash, short_ref_name(&f.name))
+        })
+        .collect();
+    write_atomic(&repo_path.join("FETCH_HEAD"), text.as_bytes())
+}
@@ -0,0 +1,428 @@
+use crate::{
+    commands::hash_object::{build_object, compute_hash, read_object},
+    error::ItError,
+    pack,
+    reachable::{LooseObject, index_hashes, is_hex_hash, loose_objects, reflog_hashes, ref_tips, shallow_commits},
+    refs::{read_ref, read_symbolic_ref},
+    repository,
+    tree::parse_tree,
+};
+use flate2::{Crc, read::ZlibDecoder};
+use sha1::{Digest, Sha1};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+pub struct FsckArgs {
+    // also verify pack and index checksums and per-entry CRCs
+    pub full: bool,
+    pub unreachable: bool,
+    pub lost_found: bool,
+}
+
+// What fsck learned about one object: its type and the objects it names,
+// each with the type the reference expects.
+struct ObjectInfo {
+    kind: String,
+    links: Vec<(&'static str, String)>,
+}
+
+#[derive(Default)]
+struct Report {
+    errors: usize,
+}
+
+impl Report {
+    fn error(&mut self, msg: String) {
+        eprintln!("error: {msg}");
+        self.errors += 1;
+    }
+
+    fn object_error(&mut self, kind: &str, hash: &str, msg: &str) {
+        eprintln!("error in {kind} {hash}: {msg}");
+        self.errors += 1;
+    }
+}
+
+// Checks every loose and packed object (zlib stream, header, SHA-1 against
+// its name, tree/commit/tag syntax), then walks from refs, reflogs and the index to
+// find missing, dangling and unreachable objects.
+pub fn fsck(args: FsckArgs) -> Result<(), ItError> {
+    let repo_path = repository::repo_path()?;
+    if !repo_path.is_dir() {
+        return Err(ItError::NotARepository);
+    }
+    let mut report = Report::default();
+    let mut objects = scan_objects(&repo_path, &mut report)?;
+    scan_packs(&repo_path, args.full, &mut objects, &mut report)?;
+
+    // the history of a shallow clone ends at its shallow commits
+    for hash in shallow_commits(&repo_path)? {
+        if let Some(info) = objects.get_mut(&hash) {
+            info.links.retain(|(kind, _)| *kind != "commit");
+        }
+    }
+
+    // every link must name an object of the expected type
+    for (hash, info) in &objects {
+        for (kind, target) in &info.links {
+            match objects.get(target) {
+                None => report.error(format!("broken link from {} {hash} to {kind} {target}", info.kind)),
+                Some(found) if found.kind != *kind && *kind != "object" => report.error(format!(
+                    "{} {hash} links to {target} as a {kind}, but it is a {}",
+                    info.kind, found.kind
+                )),
+                Some(_) => {}
+            }
+        }
+    }
+
+    let roots = collect_roots(&repo_path, &objects, &mut report)?;
+
+    let mut reachable = BTreeSet::new();
+    let mut queue: VecDeque<String> = roots.into_iter().collect();
+    while let Some(hash) = queue.pop_front() {
+        if !reachable.insert(hash.clone()) {
+            continue;
+        }
+        match objects.get(&hash) {
+            Some(info) => queue.extend(info.links.iter().map(|(_, h)| h.clone())),
+            None => report.error(format!("missing object {hash}")),
+        }
+    }
+
+    // dangling: unreachable and not named by any other object either
+    let referenced: BTreeSet<&String> = objects.values().flat_map(|i| i.links.iter().map(|(_, h)| h)).collect();
+    for (hash, info) in objects.iter().filter(|(h, _)| !reachable.contains(*h)) {
+        let dangling = !referenced.contains(hash);
+        if args.unreachable {
+            println!("unreachable {} {hash}", info.kind);
+        } else if dangling {
+            println!("dangling {} {hash}", info.kind);
+        }
+        if dangling && args.lost_found {
+            write_lost_found(&repo_path, hash, &info.kind)?;
+        }
+    }
+
+    if report.errors > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn scan_objects(repo_path: &Path, report: &mut Report) -> Result<BTreeMap<String, ObjectInfo>, ItError> {
+    let mut objects = BTreeMap::new();
+    let (files, garbage) = loose_objects(repo_path)?;
+    for path in garbage {
+        eprintln!("warning: garbage found: {}", path.display());
+    }
+
+    for LooseObject { hash: name, path } in files {
+        let mut raw = Vec::new();
+        let read = fs::File::open(&path).and_then(|f| ZlibDecoder::new(f).read_to_end(&mut raw));
+        if read.is_err() {
+            report.error(format!("{name}: object corrupt or missing: {}", path.display()));
+            continue;
+        }
+
+        let actual = compute_hash(&raw);
+        if actual != name {
+            report.error(format!("hash mismatch for {} (expected {name}, got {actual})", path.display()));
+            continue;
+        }
+        if let Some(info) = check_object(&name, &raw, report) {
+            objects.insert(name, info);
+        }
+    }
+    Ok(objects)
+}
+
+// Decodes every entry of every pack and checks it like a loose object. With
+// `full` the pack and index trailers and the index's CRC of each entry are
+// verified as well.
+fn scan_packs(
+    repo_path: &Path,
+    full: bool,
+    objects: &mut BTreeMap<String, ObjectInfo>,
+    report: &mut Report,
+) -> Result<(), ItError> {
+    for pack in pack::packs(&repo_path.join("objects"))? {
+        let pack_name = pack.pack_path.display().to_string();
+        if full {
+            let data = fs::read(&pack.pack_path)?;
+            if !verify_trailer(&data) || data[data.len() - 20..] != pack.pack_checksum {
+                report.error(format!("{pack_name}: pack checksum mismatch"));
+            }
+            if !verify_trailer(&fs::read(&pack.idx_path)?) {
+                report.error(format!("{}: index checksum mismatch", pack.idx_path.display()));
+            }
+            // an entry runs up to the next one, the last up to the trailer
+            let mut starts: Vec<u64> = pack.offsets.clone();
+            starts.push(data.len().saturating_sub(20) as u64);
+            starts.sort();
+            for (offset, crc) in pack.offsets.iter().zip(&pack.crcs) {
+                let next = starts.iter().find(|&&s| s > *offset).copied().unwrap_or(*offset) as usize;
+                let mut actual = Crc::new();
+                actual.update(data.get(*offset as usize..next).unwrap_or_default());
+                if actual.sum() != *crc {
+                    report.error(format!("{pack_name}: CRC mismatch for entry at offset {offset}"));
+                }
+            }
+        }
+
+        for (sha, offset) in pack.hashes.iter().zip(&pack.offsets) {
+            let name = hex::encode(sha);
+            let raw = match pack::read_entry(&pack, *offset) {
+                Ok((kind, body)) => build_object(&body, pack::type_name(kind)),
+                Err(e) => {
+                    report.error(format!("{pack_name}: cannot read {name} at offset {offset}: {e}"));
+                    continue;
+                }
+            };
+            let actual = compute_hash(&raw);
+            if actual != name {
+                report.error(format!("{pack_name}: hash mismatch for {name} (got {actual})"));
+                continue;
+            }
+            if let Some(info) = check_object(&name, &raw, report) {
+                objects.entry(name).or_insert(info);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn verify_trailer(data: &[u8]) -> bool {
+    data.len() >= 20 && Sha1::digest(&data[..data.len() - 20])[..] == data[data.len() - 20..]
+}
+
+// Checks the "<type> <size>\0" header and the body's syntax.
+fn check_object(name: &str, raw: &[u8], report: &mut Report) -> Option<ObjectInfo> {
+    let Some(null) = raw.iter().position(|&b| b == 0) else {
+        report.error(format!("{name}: object has no header"));
+        return None;
+    };
+    let header = String::from_utf8_lossy(&raw[..null]).to_string();
+    let body = &raw[null + 1..];
+    let Some((kind, size)) = header.split_once(' ') else {
+        report.error(format!("{name}: malformed header '{header}'"));
+        return None;
+    };
+    if size.parse::<usize>().ok() != Some(body.len()) {
+        report.error(format!("{name}: header claims {size} bytes, object has {}", body.len()));
+        return None;
+    }
+
+    let links = match kind {
+        "blob" => Some(Vec::new()),
+        "tree" => check_tree(name, body, report),
+        "commit" => check_commit(name, body, report),
+        "tag" => check_tag(name, body, report),
+        other => {
+            report.error(format!("{name}: unknown object type '{other}'"));
+            None
+        }
+    };
+    links.map(|links| ObjectInfo { kind: kind.to_string(), links })
+}
+
+fn check_tree(hash: &str, body: &[u8], report: &mut Report) -> Option<Vec<(&'static str, String)>> {
+    let entries = match parse_tree(body) {
+        Ok(entries) => entries,
+        Err(e) => {
+            report.object_error("tree", hash, &e.to_string());
+            return None;
+        }
+    };
+
+    let mut links = Vec::new();
+    let mut previous: Option<String> = None;
+    for entry in &entries {
+        let kind = match entry.mode.as_str() {
+            "100644" | "100755" | "120000" => "blob",
+            "40000" => "tree",
+            // submodule commits live in another repository
+            "160000" => "",
+            "040000" => {
+                eprintln!("warning in tree {hash}: zeroPaddedFilemode: contains zero-padded file modes");
+                "tree"
+            }
+            "100664" => {
+                eprintln!("warning in tree {hash}: badFilemode: contains bad file modes");
+                "blob"
+            }
+            other => {
+                report.object_error("tree", hash, &format!("badFilemode: '{}' has mode {other}", entry.name));
+                "blob"
+            }
+        };
+
+        match entry.name.as_str() {
+            "" => report.object_error("tree", hash, "emptyName: contains empty pathname"),
+            "." | ".." => report.object_error("tree", hash, "hasDot: contains '.' or '..'"),
+            ".it" | ".git" => report.object_error("tree", hash, "hasDotgit: contains '.it'"),
+            name if name.contains('/') => report.object_error("tree", hash, "fullPathname: contains full pathnames"),
+            _ => {}
+        }
+
+        // entries are sorted as if directory names ended in '/'
+        let key = if entry.is_tree() { format!("{}/", entry.name) } else { entry.name.clone() };
+        if let Some(prev) = &previous {
+            if prev.trim_end_matches('/') == entry.name {
+                report.object_error("tree", hash, "duplicateEntries: contains duplicate file entries");
+            } else if *prev > key {
+                report.object_error("tree", hash, "treeNotSorted: not properly sorted");
+            }
+        }
+        previous = Some(key);
+
+        if !kind.is_empty() {
+            links.push((kind, hex::encode(entry.sha)));
+        }
+    }
+    Some(links)
+}
+
+fn check_commit(hash: &str, body: &[u8], report: &mut Report) -> Option<Vec<(&'static str, String)>> {
+    let text = String::from_utf8_lossy(body);
+    let headers = text.split("\n\n").next().unwrap_or("");
+    let mut lines = headers.lines().peekable();
+    let mut links = Vec::new();
+    let mut fail = |msg: &str| {
+        report.object_error("commit", hash, msg);
+        None
+    };
+
+    match lines.next().and_then(|l| l.strip_prefix("tree ")) {
+        Some(tree) if is_hex_hash(tree) => links.push(("tree", tree.to_string())),
+        Some(_) => return fail("badTreeSha1: invalid 'tree' line format - bad sha1"),
+        None => return fail("missingTree: invalid format - expected 'tree' line"),
+    }
+    while let Some(parent) = lines.peek().and_then(|l| l.strip_prefix("parent ")) {
+        if !is_hex_hash(parent) {
+            return fail("badParentSha1: invalid 'parent' line format - bad sha1");
+        }
+        links.push(("commit", parent.to_string()));
+        lines.next();
+    }
+    for field in ["author", "committer"] {
+        match lines.next().and_then(|l| l.strip_prefix(field)).and_then(|l| l.strip_prefix(' ')) {
+            Some(ident) => {
+                if let Err(msg) = check_ident(ident) {
+                    return fail(&format!("{msg} in '{field}' line"));
+                }
+            }
+            None => return fail(&format!("missing{}: invalid format - expected '{field}' line", capitalize(field))),
+        }
+    }
+    Some(links)
+}
+
+fn check_tag(hash: &str, body: &[u8], report: &mut Report) -> Option<Vec<(&'static str, String)>> {
+    let text = String::from_utf8_lossy(body);
+    let headers = text.split("\n\n").next().unwrap_or("");
+    let mut lines = headers.lines();
+    let mut fail = |msg: &str| {
+        report.object_error("tag", hash, msg);
+        None
+    };
+
+    let object = match lines.next().and_then(|l| l.strip_prefix("object ")) {
+        Some(object) if is_hex_hash(object) => object.to_string(),
+        _ => return fail("missingObject: invalid format - expected 'object' line"),
+    };
+    let kind: &'static str = match lines.next().and_then(|l| l.strip_prefix("type ")) {
+        Some("commit") => "commit",
+        Some("tree") => "tree",
+        Some("blob") => "blob",
+        Some("tag") => "tag",
+        Some(_) => return fail("badType: invalid 'type' value"),
+        None => return fail("missingTypeEntry: invalid format - expected 'type' line"),
+    };
+    match lines.next().and_then(|l| l.strip_prefix("tag ")) {
+        Some(name) if !name.is_empty() => {}
+        _ => return fail("missingTagEntry: invalid format - expected 'tag' line"),
+    }
+    // very old tags have no tagger
+    if let Some(Err(msg)) = lines.next().and_then(|l| l.strip_prefix("tagger ")).map(check_ident) {
+        return fail(&format!("{msg} in 'tagger' line"));
+    }
+    Some(vec![(kind, object)])
+}
+
+// "Name <email> 1700000000 +0530"
+fn check_ident(ident: &str) -> Result<(), &'static str> {
+    let open = ident.find('<').ok_or("missingEmail: missing email")?;
+    let close = ident[open..].find('>').map(|i| i + open).ok_or("badEmail: bad email")?;
+    if open > 0 && !ident[..open].ends_with(' ') {
+        return Err("missingSpaceBeforeEmail: missing space before email");
+    }
+    let rest = ident[close + 1..].strip_prefix(' ').ok_or("missingSpaceBeforeDate: missing space before date")?;
+    let (date, tz) = rest.split_once(' ').ok_or("badDate: invalid author/committer line - bad date")?;
+    if date.is_empty() || !date.chars().all(|c| c.is_ascii_digit()) {
+        return Err("badDate: invalid author/committer line - bad date");
+    }
+    let tz_valid = tz.len() == 5 && tz.starts_with(['+', '-']) && tz[1..].chars().all(|c| c.is_ascii_digit());
+    if !tz_valid {
+        return Err("badTimezone: invalid author/committer line - bad time zone");
+    }
+    Ok(())
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars.next().map(|c| c.to_ascii_uppercase().to_string() + chars.as_str()).unwrap_or_default()
+}
+
+// Starting points of the reachability walk: HEAD and every ref (checked to
+// name a commit), every hash in the reflogs, and the blobs in the index.
+fn collect_roots(
+    repo_path: &Path,
+    objects: &BTreeMap<String, ObjectInfo>,
+    report: &mut Report,
+) -> Result<BTreeSet<String>, ItError> {
+    let mut roots = BTreeSet::new();
+
+    match read_symbolic_ref(repo_path, "HEAD")? {
+        Some(target) if read_ref(repo_path, &target)?.is_none() => {
+            eprintln!("notice: HEAD points to an unborn branch ({target})");
+        }
+        _ => {}
+    }
+
+    for (name, value) in ref_tips(repo_path)? {
+        if !is_hex_hash(&value) {
+            report.error(format!("{name}: invalid sha1 pointer {value}"));
+            continue;
+        }
+        match objects.get(&value) {
+            None => report.error(format!("{name}: invalid sha1 pointer {value}")),
+            // tags may point at tag objects, branches and HEAD only at commits
+            Some(info) if info.kind != "commit" && !(name.starts_with("refs/tags/") && info.kind == "tag") => {
+                report.error(format!("{name}: not a commit ({} {value})", info.kind))
+            }
+            Some(_) => {}
+        }
+        roots.insert(value);
+    }
+
+    // reflogs may name objects that have since been pruned
+    roots.extend(reflog_hashes(repo_path)?.into_iter().filter(|h| objects.contains_key(h)));
+    roots.extend(index_hashes(repo_path)?);
+    Ok(roots)
+}
+
+// Like git, dangling commits go to lost-found/commit/<hash> holding their own
+// hash, anything else to lost-found/other/<hash>, blobs with their content.
+fn write_lost_found(repo_path: &Path, hash: &str, kind: &str) -> Result<(), ItError> {
+    let dir = repo_path.join("lost-found").join(if kind == "commit" { "commit" } else { "other" });
+    fs::create_dir_all(&dir)?;
+    let content = match kind {
+        "blob" => read_object(hash)?.1,
+        _ => format!("{hash}\n").into_bytes(),
+    };
+    fs::write(dir.join(hash), content)?;
+    Ok(())
+}
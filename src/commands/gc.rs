@@ -0,0 +1,41 @@
+use crate::{
+    commands::{
+        multi_pack_index::write_index,
+        prune::{DEFAULT_EXPIRE, parse_expire, prune_objects},
+        repack::{RepackArgs, repack},
+    },
+    config::Config,
+    error::ItError,
+    object_id::ObjectFormat,
+    pack::pack_paths,
+    repository,
+};
+
+// Housekeeping: packs everything reachable into a single pack, indexes it
+// with a multi-pack-index and reachability bitmaps, then removes
+// unreachable loose objects older than gc.pruneExpire (two weeks unless
+// configured), or the date given with --prune. A dry run only reports what
+// pruning would remove. Packs hold SHA-1 objects only, so a sha256
+// repository is only pruned, with a warning that packing was skipped.
+pub fn gc(prune: Option<String>, dry_run: bool) -> Result<(), ItError> {
+    let repo_path = repository::repo_path()?;
+    if !repo_path.is_dir() {
+        return Err(ItError::NotARepository);
+    }
+    let config = Config::load(&repo_path)?;
+    let expire = prune
+        .or_else(|| config.get("gc.pruneExpire").map(str::to_string))
+        .unwrap_or_else(|| DEFAULT_EXPIRE.to_string());
+    let cutoff = parse_expire(&expire)?;
+    if dry_run {
+        // nothing is packed in a dry run
+    } else if ObjectFormat::current() == ObjectFormat::Sha1 {
+        repack(RepackArgs { all: true, delete: true, window: 10, depth: 50 })?;
+        if !pack_paths(&repo_path.join("objects"))?.is_empty() {
+            write_index(&repo_path, true)?;
+        }
+    } else {
+        eprintln!("warning: skipping repack, packs are not supported in sha256 repositories");
+    }
+    prune_objects(&repo_path, cutoff, dry_run)
+}
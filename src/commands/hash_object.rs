@@ -1,17 +1,16 @@
+use crate::{
+    error::ItError,
+    lockfile::TempFile,
+    object_id::{Hasher, ObjectFormat, ObjectId},
+    pack,
+    repository,
+};
 use flate2::Compression;
+use flate2::read::ZlibDecoder;
 use flate2::write::ZlibEncoder;
-use sha1::{Digest, Sha1};
 use std::fs;
-use std::io::Write;
-use std::path::Path;
-
-pub fn hash_object(data: &[u8]) -> Result<String, std::io::Error> {
-    let blob = build_object(data, "blob");
-    let hash = compute_hash(&blob);
-    let compressed = compress_data(&blob)?;
-    store_object(&hash, &compressed)?;
-    Ok(hash)
-}
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 
 pub(crate) fn build_object(data: &[u8], obj_type: &str) -> Vec<u8> {
     let header = format!("{} {}\0", obj_type, data.len());
@@ -20,8 +19,9 @@ pub(crate) fn build_object(data: &[u8], obj_type: &str) -> Vec<u8> {
     store_data
 }
 
+// The hex id of a raw object, in the repository's object format.
 pub(crate) fn compute_hash(data: &[u8]) -> String {
-    format!("{:x}", Sha1::digest(data))
+    ObjectFormat::current().digest(data).to_hex()
 }
 
 pub(crate) fn compress_data(data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
@@ -31,19 +31,250 @@ pub(crate) fn compress_data(data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
 }
 
 pub(crate) fn store_object(hash: &str, compressed_data: &[u8]) -> Result<(), std::io::Error> {
-    let objects_dir = Path::new(".it/objects").join(&hash[0..2]);
-    fs::create_dir_all(&objects_dir)?;
-    let object_path = objects_dir.join(&hash[2..]);
-    if !object_path.exists() {
-        fs::write(&object_path, compressed_data)?;
+    store_object_in(&repository::objects_dir(), hash, compressed_data)
+}
+
+// Like store_object, into the objects directory of another repository.
+pub(crate) fn store_object_in(objects_dir: &Path, hash: &str, compressed_data: &[u8]) -> Result<(), std::io::Error> {
+    if object_path_in(objects_dir, hash).exists() {
+        return Ok(());
+    }
+    let mut temp = TempFile::create(objects_dir, "tmp_obj_")?;
+    temp.write_all(compressed_data)?;
+    temp.install(&object_path_in(objects_dir, hash))
+}
+
+// Hashes a file as a blob in CHUNK_SIZE pieces, and with `store` also writes
+// it to the object store, so memory use does not grow with the file.
+pub(crate) fn hash_file(path: &Path, store: bool) -> Result<ObjectId, std::io::Error> {
+    let mut file = fs::File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut writer = ObjectWriter::new("blob", size, store)?;
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        writer.write(&buf[..n])?;
+    }
+    writer
+        .finish()
+        .map_err(|e| std::io::Error::other(format!("{}: {e}", path.display())))
+}
+
+// how much of a file or object is held in memory at once while streaming
+pub(crate) const CHUNK_SIZE: usize = 64 * 1024;
+
+// Hashes and compresses an object of known size as its content arrives.
+// When storing, the compressed bytes go to a temporary file in .it/objects
+// that `finish` renames into objects/xx/ once the hash is known, so a reader
+// never sees a partial object.
+pub(crate) struct ObjectWriter {
+    hasher: Hasher,
+    encoder: Option<(ZlibEncoder<TempFile>, PathBuf)>,
+    remaining: u64,
+}
+
+impl ObjectWriter {
+    pub(crate) fn new(obj_type: &str, size: u64, store: bool) -> Result<ObjectWriter, std::io::Error> {
+        ObjectWriter::open(obj_type, size, store.then(repository::objects_dir).as_deref())
+    }
+
+    // A writer that stores into the objects directory of another repository.
+    pub(crate) fn new_in(objects_dir: &Path, obj_type: &str, size: u64) -> Result<ObjectWriter, std::io::Error> {
+        ObjectWriter::open(obj_type, size, Some(objects_dir))
+    }
+
+    fn open(obj_type: &str, size: u64, objects_dir: Option<&Path>) -> Result<ObjectWriter, std::io::Error> {
+        let header = format!("{obj_type} {size}\0");
+        let mut hasher = ObjectFormat::current().hasher();
+        hasher.update(header.as_bytes());
+        let encoder = if let Some(objects_dir) = objects_dir {
+            let mut encoder = ZlibEncoder::new(TempFile::create(objects_dir, "tmp_obj_")?, Compression::default());
+            encoder.write_all(header.as_bytes())?;
+            Some((encoder, objects_dir.to_path_buf()))
+        } else {
+            None
+        };
+        Ok(ObjectWriter { hasher, encoder, remaining: size })
+    }
+
+    pub(crate) fn write(&mut self, data: &[u8]) -> Result<(), std::io::Error> {
+        if data.len() as u64 > self.remaining {
+            return Err(std::io::Error::other("content changed while it was being hashed"));
+        }
+        self.remaining -= data.len() as u64;
+        self.hasher.update(data);
+        if let Some((encoder, _)) = &mut self.encoder {
+            encoder.write_all(data)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish(self) -> Result<ObjectId, std::io::Error> {
+        if self.remaining != 0 {
+            return Err(std::io::Error::other("content changed while it was being hashed"));
+        }
+        let id = self.hasher.finish();
+        if let Some((encoder, objects_dir)) = self.encoder {
+            encoder.finish()?.install(&object_path_in(&objects_dir, &id.to_hex()))?;
+        }
+        Ok(id)
+    }
+}
+
+pub(crate) fn object_path_in(objects_dir: &Path, hash: &str) -> PathBuf {
+    objects_dir.join(&hash[0..2]).join(&hash[2..])
+}
+
+// Returns the object type and its body with the "<type> <size>\0" header stripped.
+// Loose objects are tried first, then the packs.
+pub(crate) fn read_object(hash: &str) -> Result<(String, Vec<u8>), ItError> {
+    read_object_in(&repository::objects_dir(), hash)
+}
+
+// Like read_object, from the objects directory of another repository.
+pub(crate) fn read_object_in(objects_dir: &Path, hash: &str) -> Result<(String, Vec<u8>), ItError> {
+    let (obj_type, size, mut reader) = open_object_in(objects_dir, hash)?;
+    // the size comes from the object itself, so it is not trusted for more
+    // than a first allocation
+    let mut body = Vec::with_capacity(size.min(CHUNK_SIZE as u64) as usize);
+    reader.read_to_end(&mut body)?;
+    Ok((obj_type, body))
+}
+
+// An object's type and size, with a reader over its body.
+pub(crate) type ObjectStream = (String, u64, Box<dyn Read>);
+
+// Like read_object, but returns the body as a reader along with its size, so
+// a large blob can be copied out without holding all of it in memory.
+pub(crate) fn open_object(hash: &str) -> Result<ObjectStream, ItError> {
+    open_object_in(&repository::objects_dir(), hash)
+}
+
+pub(crate) fn open_object_in(objects_dir: &Path, hash: &str) -> Result<ObjectStream, ItError> {
+    if hash.len() < 3 {
+        return Err(ItError::InvalidRef(hash.to_string()));
+    }
+    let file = match fs::File::open(object_path_in(objects_dir, hash)) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return pack::open_packed(objects_dir, hash)?.ok_or(ItError::Io(e));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let mut decoder = ZlibDecoder::new(file);
+
+    let mut header = Vec::new();
+    let mut byte = [0u8; 1];
+    loop {
+        if decoder.read(&mut byte)? == 0 || header.len() > 64 {
+            return Err(ItError::CorruptObject(format!("{hash}: missing header")));
+        }
+        if byte[0] == 0 {
+            break;
+        }
+        header.push(byte[0]);
+    }
+    let header = String::from_utf8_lossy(&header).to_string();
+    let (obj_type, size) = header
+        .split_once(' ')
+        .and_then(|(t, size)| Some((t.to_string(), size.parse::<u64>().ok()?)))
+        .ok_or_else(|| ItError::CorruptObject(format!("{hash}: malformed header '{header}'")))?;
+    Ok((obj_type, size, Box::new(ExactReader::new(decoder, size, hash))))
+}
+
+// Reads exactly `size` bytes of an object body and fails, rather than
+// returning short or extra content, when the stream ends early or holds
+// more than its header said.
+pub(crate) struct ExactReader<R> {
+    inner: R,
+    remaining: u64,
+    hash: String,
+}
+
+impl<R: Read> ExactReader<R> {
+    pub(crate) fn new(inner: R, size: u64, hash: &str) -> ExactReader<R> {
+        ExactReader { inner, remaining: size, hash: hash.to_string() }
+    }
+
+    fn corrupt(&self, what: &str) -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{}: {what}", self.hash))
     }
-    Ok(())
 }
 
-pub fn hex_to_sha_bytes(hex: &str) -> [u8; 20] {
-    let mut bytes = [0u8; 20];
-    for i in 0..20 {
-        bytes[i] = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap();
+impl<R: Read> Read for ExactReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.remaining == 0 {
+            return match self.inner.read(&mut [0u8; 1])? {
+                0 => Ok(0),
+                _ => Err(self.corrupt("object is longer than its header says")),
+            };
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let want = buf.len().min(self.remaining.min(usize::MAX as u64) as usize);
+        let n = self.inner.read(&mut buf[..want])?;
+        if n == 0 {
+            return Err(self.corrupt("object is truncated"));
+        }
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_all(data: &[u8], size: u64) -> std::io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        ExactReader::new(data, size, "abc").read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn exact_reader_checks_the_size() {
+        assert_eq!(read_all(b"hello", 5).unwrap(), b"hello");
+        assert_eq!(read_all(b"", 0).unwrap(), b"");
+        let short = read_all(b"hell", 5).unwrap_err();
+        assert_eq!(short.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(short.to_string(), "abc: object is truncated");
+        assert_eq!(read_all(b"hello!", 5).unwrap_err().to_string(), "abc: object is longer than its header says");
+    }
+
+    #[test]
+    fn object_writer_streams_into_the_store() {
+        let objects = std::env::temp_dir().join(format!("it-hash-object-test-{}", std::process::id()));
+        // more than one chunk, written in uneven pieces
+        let data: Vec<u8> = (0..CHUNK_SIZE * 2 + 123).map(|i| (i % 251) as u8).collect();
+        let mut writer = ObjectWriter::new_in(&objects, "blob", data.len() as u64).unwrap();
+        for piece in data.chunks(10_000) {
+            writer.write(piece).unwrap();
+        }
+        let id = writer.finish().unwrap().to_hex();
+        assert_eq!(id, compute_hash(&build_object(&data, "blob")));
+        let (kind, body) = read_object_in(&objects, &id).unwrap();
+        assert_eq!((kind.as_str(), body == data), ("blob", true));
+
+        // content that does not match the announced size, either way
+        let mut long = ObjectWriter::new_in(&objects, "blob", 3).unwrap();
+        assert!(long.write(b"four").is_err());
+        let mut short = ObjectWriter::new_in(&objects, "blob", 3).unwrap();
+        short.write(b"ab").unwrap();
+        assert!(short.finish().is_err());
+        drop(long);
+
+        // nothing but the one object is left behind
+        let mut files = Vec::new();
+        for dir in fs::read_dir(&objects).unwrap() {
+            let dir = dir.unwrap();
+            assert!(dir.file_type().unwrap().is_dir(), "{:?} left behind", dir.file_name());
+            files.extend(fs::read_dir(dir.path()).unwrap().map(|f| f.unwrap().file_name()));
+        }
+        fs::remove_dir_all(&objects).unwrap();
+        assert_eq!(files, vec![std::ffi::OsString::from(&id[2..])]);
     }
-    bytes
 }
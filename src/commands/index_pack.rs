@@ -0,0 +1,30 @@
+use crate::{
+    error::ItError,
+    lockfile::write_atomic,
+    pack::{build_index, parse_pack, resolve_entries},
+};
+use std::fs;
+use std::path::Path;
+
+// Builds the .idx for a pack file, as `git index-pack` does: every entry is
+// inflated, deltas are resolved and each object hashed. The index is written
+// next to the pack and the pack's checksum printed. Packs from git can be
+// imported by copying them into .it/objects/pack and indexing them there.
+pub fn index_pack(pack_path: &Path) -> Result<(), ItError> {
+    if pack_path.extension().is_none_or(|e| e != "pack") {
+        return Err(ItError::Fatal(format!(
+            "packfile name '{}' does not end with '.pack'",
+            pack_path.display()
+        )));
+    }
+    let data = fs::read(pack_path)?;
+    let (entries, checksum) = parse_pack(&data)?;
+    let resolved = resolve_entries(&entries)?;
+
+    let shas: Vec<[u8; 20]> = resolved.iter().map(|r| r.sha).collect();
+    let offsets: Vec<u64> = entries.iter().map(|e| e.offset).collect();
+    let crcs: Vec<u32> = entries.iter().map(|e| e.crc).collect();
+    write_atomic(&pack_path.with_extension("idx"), &build_index(&shas, &offsets, &crcs, &checksum))?;
+    println!("{}", hex::encode(checksum));
+    Ok(())
+}
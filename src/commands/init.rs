@@ -1,20 +1,106 @@
 use std::{env, fs};
+use std::path::{Path, PathBuf};
 use colored::*;
+use crate::{config::Config, error::ItError, object_id::ObjectFormat, refs::is_valid_ref_name};
 
-pub fn init() -> Result<(), std::io::Error> {
-    let repo_path = env::current_dir()?.join(".it");
-    if repo_path.exists() && repo_path.is_dir() {
+pub struct InitArgs {
+    // where to create the repository, the current directory by default
+    pub directory: Option<PathBuf>,
+    // no worktree: the repository layout goes directly into the directory
+    pub bare: bool,
+    pub initial_branch: Option<String>,
+    pub object_format: Option<String>,
+    pub template: Option<PathBuf>,
+}
+
+// What a new repository's info/exclude holds when no template provides one.
+const DEFAULT_EXCLUDE: &str = "\
+# it ls-files --others --exclude-from=.it/info/exclude
+# Lines that start with '#' are comments.
+# For a project mostly in C, the following would be a good set of
+# exclude patterns (uncomment them if you want to use them):
+# *.[oa]
+# *~
+";
+
+// `--object-format=sha256` makes a repository whose objects are named by
+// SHA-256; the choice is recorded as extensions.objectFormat and cannot be
+// changed afterwards.
+//
+// The first branch is --initial-branch, else init.defaultBranch, else
+// `main`. The files of a template directory (--template, IT_TEMPLATE_DIR or
+// init.templateDir) are copied into the new repository, typically hooks/ and
+// info/exclude; without one an empty hooks/ and a commented info/exclude are
+// created.
+pub fn init(args: InitArgs) -> Result<(), ItError> {
+    let format = match args.object_format.as_deref() {
+        None => ObjectFormat::Sha1,
+        Some(name) => ObjectFormat::from_name(name)
+            .ok_or_else(|| ItError::Fatal(format!("unknown hash algorithm '{name}'")))?,
+    };
+    let directory = env::current_dir()?.join(args.directory.unwrap_or_default());
+    let repo_path = if args.bare { directory.clone() } else { directory.join(".it") };
+    if repo_path.join("HEAD").is_file() {
         println!("{} {}", "✓".green().bold(), "Already a IT Repository".green());
         return Ok(());
     }
-    
+
+    // settings that shape a new repository can only come from the global
+    // config or the environment, as there is no repository config yet
+    let config = Config::load(&repo_path)?;
+    let branch = match args.initial_branch {
+        Some(branch) => branch,
+        None => config.get("init.defaultbranch").unwrap_or("main").to_string(),
+    };
+    if !is_valid_ref_name(&format!("refs/heads/{branch}")) {
+        return Err(ItError::Fatal(format!("invalid initial branch name: '{branch}'")));
+    }
+    let template = template_dir(args.template, &config);
+
     println!("{}", "Initializing IT repository...".cyan().bold());
-       
-     
+    create_repository(&repo_path, args.bare, &branch, format, template.as_deref())?;
+    let kind = if args.bare { "bare " } else { "" };
+    println!("{}", format!("✓ Initialized empty {kind}IT repository in {}", repo_path.display()).green().bold());
+
+    Ok(())
+}
+
+pub(crate) fn template_dir(explicit: Option<PathBuf>, config: &Config) -> Option<PathBuf> {
+    explicit
+        .or_else(|| env::var_os("IT_TEMPLATE_DIR").map(PathBuf::from))
+        .or_else(|| config.get("init.templatedir").map(crate::config::expand_home))
+}
+
+// Lays out an empty repository at `repo_path` with HEAD on `branch`.
+pub(crate) fn create_repository(
+    repo_path: &Path,
+    bare: bool,
+    branch: &str,
+    format: ObjectFormat,
+    template: Option<&Path>,
+) -> Result<(), ItError> {
     fs::create_dir_all(repo_path.join("objects"))?;
     fs::create_dir_all(repo_path.join("refs/heads"))?;
+    fs::create_dir_all(repo_path.join("refs/tags"))?;
+
+    match template {
+        Some(template) => copy_template(template, repo_path)?,
+        None => {
+            fs::create_dir_all(repo_path.join("hooks"))?;
+            fs::create_dir_all(repo_path.join("info"))?;
+            fs::write(repo_path.join("info/exclude"), DEFAULT_EXCLUDE)?;
+        }
+    }
 
-    fs::write(repo_path.join("HEAD"), format!("ref: refs/heads/main\n"))?;
+    fs::write(repo_path.join("HEAD"), format!("ref: refs/heads/{branch}\n"))?;
+    // extensions are only honoured from format version 1 on
+    let version = if format == ObjectFormat::Sha1 { 0 } else { 1 };
+    let mut config = format!("[core]\n\trepositoryformatversion = {version}\n\tbare = {bare}\n");
+    if format != ObjectFormat::Sha1 {
+        config.push_str(&format!("[extensions]\n\tobjectFormat = {}\n", format.name()));
+    }
+    fs::write(repo_path.join("config"), config)?;
+    ObjectFormat::set_current(format);
 
     // LOG FILES
     let logs_path = repo_path.join("logs");
@@ -22,9 +108,32 @@ pub fn init() -> Result<(), std::io::Error> {
     fs::create_dir_all(logs_path.join("refs/heads"))?;
     fs::write(logs_path.join("HEAD.md"), "")?;
 
-    // for it add command (staging area)
-    fs::File::create(repo_path.join("index"))?;
-    println!("{}", "✓ Initialized empty IT repository".green().bold());
-      
+    // for it add command (staging area); a bare repository has none
+    if !bare {
+        fs::File::create(repo_path.join("index"))?;
+    }
+    Ok(())
+}
+
+// Copies a template directory into the repository. Files the repository
+// already has are kept; a missing template is skipped with a warning, as git
+// does.
+fn copy_template(template: &Path, repo_path: &Path) -> Result<(), ItError> {
+    if !template.is_dir() {
+        eprintln!("warning: templates not found in {}", template.display());
+        return Ok(());
+    }
+    for entry in fs::read_dir(template)? {
+        let entry = entry?;
+        let dest = repo_path.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            fs::create_dir_all(&dest)?;
+            copy_template(&entry.path(), &dest)?;
+        } else if !dest.exists() {
+            // fs::copy keeps the permissions, so hooks stay executable
+            fs::copy(entry.path(), &dest)?;
+        }
+    }
     Ok(())
 }
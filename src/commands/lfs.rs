@@ -0,0 +1,185 @@
+use crate::{
+    attributes::AttrValue,
+    config::Config,
+    error::ItError,
+    index::read_index,
+    lfs::{Lfs, Pointer, read_pointer},
+    reachable::reachable_objects,
+    refs::head_commit,
+    repository,
+    tree::{commit_tree_hash, flatten_tree},
+};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+fn open() -> Result<(PathBuf, PathBuf, Lfs), ItError> {
+    let worktree = repository::worktree()?;
+    let repo_path = repository::repo_path()?;
+    if !repo_path.is_dir() {
+        return Err(ItError::NotARepository);
+    }
+    let lfs = Lfs::load(&worktree, &repo_path, &Config::load(&repo_path)?)?;
+    Ok((worktree, repo_path, lfs))
+}
+
+// Without patterns, lists the patterns tracked in .itattributes. Otherwise
+// appends a `filter=lfs` line for each pattern not tracked yet.
+pub fn track(patterns: Vec<String>) -> Result<(), ItError> {
+    let (worktree, _, lfs) = open()?;
+    let is_lfs = |attrs: &[(String, AttrValue)]| {
+        attrs.iter().any(|(name, value)| name == "filter" && *value == AttrValue::Value("lfs".to_string()))
+    };
+    let rules = lfs.attributes.worktree_rules();
+
+    if patterns.is_empty() {
+        println!("Listing tracked patterns");
+        for rule in rules.iter().filter(|r| is_lfs(&r.attrs)) {
+            println!("    {} ({})", rule.pattern, rule.source);
+        }
+        return Ok(());
+    }
+
+    let path = worktree.join(".itattributes");
+    let mut text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    for pattern in patterns {
+        let pattern = pattern.strip_prefix("./").unwrap_or(&pattern).to_string();
+        if rules.iter().any(|r| r.pattern == pattern.trim_start_matches('/') && is_lfs(&r.attrs)) {
+            println!("\"{pattern}\" already supported");
+            continue;
+        }
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text.push_str(&format!("{pattern} filter=lfs diff=lfs merge=lfs -text\n"));
+        println!("Tracking \"{pattern}\"");
+    }
+    fs::write(&path, text)?;
+    Ok(())
+}
+
+// Lists the staged files stored as pointers: the start of the oid, `*` when
+// the content is in the local store or `-` when only the pointer is, and
+// the path.
+pub fn ls_files() -> Result<(), ItError> {
+    let (_, repo_path, lfs) = open()?;
+    let mut out = std::io::stdout().lock();
+    for (path, pointer) in index_pointers(&repo_path)? {
+        let marker = if lfs.has_object(&pointer.oid) { '*' } else { '-' };
+        writeln!(out, "{} {marker} {path}", &pointer.oid[..10])?;
+    }
+    Ok(())
+}
+
+// Checks that every pointer in HEAD and the index has its content in the
+// local store, and that the content still hashes to its oid.
+pub fn fsck() -> Result<(), ItError> {
+    let (_, repo_path, lfs) = open()?;
+    let pointers = head_and_index_pointers(&repo_path)?;
+
+    let mut ok = true;
+    for (path, pointer) in &pointers {
+        match lfs.verify(pointer) {
+            Ok(true) => {}
+            Ok(false) => {
+                println!("objects: corruptObject: {path} ({}) is corrupt", pointer.oid);
+                ok = false;
+            }
+            Err(e) => {
+                println!("objects: openError: {path} ({}) could not be checked: {e}", pointer.oid);
+                ok = false;
+            }
+        }
+    }
+    if !ok {
+        std::process::exit(1);
+    }
+    println!("it lfs fsck OK");
+    Ok(())
+}
+
+// Copies the content of every pointer reachable from the refs, the index or
+// the reflogs to the remote store named by lfs.url.
+pub fn push() -> Result<(), ItError> {
+    let (_, repo_path, lfs) = open()?;
+    let mut oids = Vec::new();
+    for hash in reachable_objects(&repo_path)?.keys() {
+        if let Some(pointer) = read_pointer(hash)? {
+            oids.push(pointer.oid);
+        }
+    }
+    oids.sort();
+    oids.dedup();
+
+    let mut uploaded = 0;
+    for oid in &oids {
+        if !lfs.has_object(oid) {
+            return Err(ItError::Fatal(format!("lfs object {oid} is missing from the local store")));
+        }
+        if lfs.push(oid)? {
+            uploaded += 1;
+        }
+    }
+    println!("Uploaded {uploaded} lfs object(s)");
+    Ok(())
+}
+
+// Copies the content of every pointer in HEAD and the index that is missing
+// locally from the remote store.
+pub fn fetch() -> Result<(), ItError> {
+    let (_, repo_path, lfs) = open()?;
+    if lfs.remote().is_none() {
+        return Err(ItError::Fatal("no lfs remote store; set lfs.url".to_string()));
+    }
+    let pointers = head_and_index_pointers(&repo_path)?;
+
+    let mut fetched = 0;
+    for (path, pointer) in &pointers {
+        if lfs.has_object(&pointer.oid) {
+            continue;
+        }
+        match lfs.fetch(&pointer.oid)? {
+            true => fetched += 1,
+            false => eprintln!("warning: {path}: lfs object {} is not in the remote store", pointer.oid),
+        }
+    }
+    println!("Fetched {fetched} lfs object(s)");
+    Ok(())
+}
+
+// Staged pointers by path.
+fn index_pointers(repo_path: &Path) -> Result<Vec<(String, Pointer)>, ItError> {
+    let mut pointers = Vec::new();
+    if !repo_path.join("index").exists() {
+        return Ok(pointers);
+    }
+    for entry in read_index(repo_path)?.into_iter().filter(|e| e.mode != 0o160000 && e.mode != 0o120000) {
+        if let Some(pointer) = read_pointer(&hex::encode(entry.sha))? {
+            pointers.push((entry.path, pointer));
+        }
+    }
+    Ok(pointers)
+}
+
+// The pointers of HEAD's tree and the index, each (path, oid) once.
+fn head_and_index_pointers(repo_path: &Path) -> Result<Vec<(String, Pointer)>, ItError> {
+    let mut pointers = Vec::new();
+    if let Some(commit) = head_commit(repo_path)? {
+        for (path, (mode, sha)) in flatten_tree(&commit_tree_hash(&commit)?)? {
+            if mode == "160000" || mode == "120000" {
+                continue;
+            }
+            if let Some(pointer) = read_pointer(&hex::encode(sha))? {
+                pointers.push((path, pointer));
+            }
+        }
+    }
+    pointers.extend(index_pointers(repo_path)?);
+    pointers.sort_by(|a, b| (&a.0, &a.1.oid).cmp(&(&b.0, &b.1.oid)));
+    pointers.dedup();
+    Ok(pointers)
+}
@@ -1,17 +1,15 @@
 use std::{
-    fs::{self, read_to_string},
+    fs,
     io::Write,
-    time::{self, SystemTime, UNIX_EPOCH},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use colored::Colorize;
 
-use crate::{commands::commit_tree::get_parent, error::ItError};
+use crate::{error::ItError, object_id::ObjectFormat, repository};
 
 pub fn log() -> Result<(), ItError> {
-    let curr_dir = std::env::current_dir()?;
-
-    let repo_path = curr_dir.join(".it");
+    let repo_path = repository::repo_path()?;
     let head_content = fs::read_to_string(repo_path.join("HEAD"))?;
 
     let current_branch_path = head_content.trim_start_matches("ref:").trim();
@@ -36,7 +34,7 @@ pub fn log() -> Result<(), ItError> {
                    let hash = parts[0].bright_green().bold();
                    
                    // Colorize parent hash (dimmed if zeros, yellow if has value)
-                   let parent = if parts[1] == "0000000000000000000000000000000000000000" {
+                   let parent = if parts[1].chars().all(|c| c == '0') {
                        parts[1].dimmed()
                    } else {
                        parts[1].yellow()
@@ -49,7 +47,8 @@ pub fn log() -> Result<(), ItError> {
                    let message = line.split_whitespace().skip(5).collect::<Vec<_>>().join(" ");
                    let colored_msg = message
                        .replace("commit :", &"commit :".bright_blue().bold())
-                       .replace("BRANCH FROM", &"BRANCH FROM".bright_cyan().bold());
+                       .replace("BRANCH FROM", &"BRANCH FROM".bright_cyan().bold())
+                       .replace("clone : from", &"clone : from".bright_magenta().bold());
                    
                    // Print with colors
                    println!("{} \n{} \n{} {} \n{} \n{} ",
@@ -77,7 +76,6 @@ pub fn log() -> Result<(), ItError> {
 ///    - get time       -
 ///    - get zone       -
 ///    - get message    -
-
 pub struct CommitArgs<'info> {
     new_commit_hash: &'info str,
     parent_commit_hash: Option<String>,
@@ -89,10 +87,8 @@ pub struct CommitArgs<'info> {
 pub fn form_commit_log(args: CommitArgs) -> String {
     // this is GMT
     let zone = "+5:30";
-    let parent_display = args
-        .parent_commit_hash
-        .as_deref()
-        .unwrap_or("0000000000000000000000000000000000000000");
+    let null_hash = ObjectFormat::current().null_id().to_hex();
+    let parent_display = args.parent_commit_hash.as_deref().unwrap_or(&null_hash);
     let log = format!(
         "{}",
         format_args!(
@@ -120,9 +116,10 @@ pub fn log_commit(
     parent_commit_hash: Option<String>,
     message: &str,
 ) -> Result<(), ItError> {
-    let curr_dir = std::env::current_dir()?;
+    // a bare repository is named by its own directory
+    let curr_dir = repository::worktree().or_else(|_| repository::repo_path())?;
 
-    let repo_path = curr_dir.join(".it");
+    let repo_path = repository::repo_path()?;
     let head_content = fs::read_to_string(repo_path.join("HEAD"))?;
 
     let current_branch_path = head_content.trim_start_matches("ref:").trim();
@@ -156,16 +153,17 @@ pub fn log_commit(
         .expect("Cannot Open File");
 
     current_branch_log_file
-        .write(log_string.as_bytes())
+        .write_all(log_string.as_bytes())
         .expect("Could not write to file");
     Ok(())
 }
 
 // log branch
 pub fn log_branch(new_branch: &str) -> Result<(), ItError> {
-    let curr_dir = std::env::current_dir()?;
+    // a bare repository is named by its own directory
+    let curr_dir = repository::worktree().or_else(|_| repository::repo_path())?;
 
-    let repo_path = curr_dir.join(".it");
+    let repo_path = repository::repo_path()?;
     let head_content = fs::read_to_string(repo_path.join("HEAD"))?;
 
     let current_branch_path = head_content.trim_start_matches("ref:").trim();
@@ -185,7 +183,7 @@ pub fn log_branch(new_branch: &str) -> Result<(), ItError> {
     // this is for branch action
     let log_string = form_commit_log(CommitArgs {
         new_commit_hash: current_commit_hash,
-        parent_commit_hash: Some("0000000000000000000000000000000000000000".to_string()),
+        parent_commit_hash: Some(ObjectFormat::current().null_id().to_hex()),
         dir_name,
         time,
         message: branch_created_message(new_branch, current_branch_name),
@@ -201,7 +199,46 @@ pub fn log_branch(new_branch: &str) -> Result<(), ItError> {
         .expect("Cannot Open File");
 
     branch_log_file
-        .write(log_string.as_bytes())
+        .write_all(log_string.as_bytes())
+        .expect("Could not write to file");
+
+    Ok(())
+}
+
+pub fn clone_message(url: &str) -> String {
+    format!("{}", format_args!("{} {}", "clone : from".bright_magenta().bold(), url.white().bold()))
+}
+
+// log the branch a clone starts on
+pub fn log_clone(branch: &str, commit_hash: &str, url: &str) -> Result<(), ItError> {
+    let curr_dir = repository::worktree().or_else(|_| repository::repo_path())?;
+    let repo_path = repository::repo_path()?;
+    let dir_name = curr_dir.to_str().expect("Could not get the directory name");
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs();
+
+    let log_string = form_commit_log(CommitArgs {
+        new_commit_hash: commit_hash,
+        parent_commit_hash: None,
+        dir_name,
+        time,
+        message: clone_message(url),
+    });
+
+    let branch_logs_path = repo_path.join("logs/refs/heads").join(branch);
+    if let Some(parent) = branch_logs_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut branch_log_file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(branch_logs_path)
+        .expect("Cannot Open File");
+
+    branch_log_file
+        .write_all(log_string.as_bytes())
         .expect("Could not write to file");
 
     Ok(())
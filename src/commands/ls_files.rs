@@ -0,0 +1,111 @@
+use crate::{
+    config::Config,
+    error::ItError,
+    ignore::Ignore,
+    index::{StatCheck, read_index, worktree_matches},
+    lfs::Lfs,
+    pathspec::Pathspec,
+    repository,
+};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::Write;
+
+pub struct LsFilesArgs {
+    pub paths: Vec<String>,
+    pub stage: bool,
+    pub cached: bool,
+    pub modified: bool,
+    pub deleted: bool,
+    pub others: bool,
+    pub exclude_standard: bool,
+    pub zero: bool,
+}
+
+// Plumbing listing of the index. Untracked files (--others) come first, then
+// the index entries, then deleted and modified files, like git. With no
+// selection flag the cached entries are listed.
+pub fn ls_files(args: LsFilesArgs) -> Result<(), ItError> {
+    let worktree = repository::worktree()?;
+    let repo_path = repository::repo_path()?;
+    if !repo_path.is_dir() {
+        return Err(ItError::NotARepository);
+    }
+    let entries = read_index(&repo_path)?;
+    let pathspec = Pathspec::parse(&args.paths)?;
+    let config = Config::load(&repo_path)?;
+    let show_cached = args.cached || args.stage || !(args.modified || args.deleted || args.others);
+
+    let mut out = std::io::stdout().lock();
+    let mut emit = |line: String, path: &str| -> std::io::Result<()> {
+        match args.zero {
+            true => write!(out, "{line}{path}\0"),
+            false => writeln!(out, "{line}{}", quote_path(path)),
+        }
+    };
+
+    if args.others {
+        let tracked: BTreeSet<&str> = entries.iter().map(|e| e.path.as_str()).collect();
+        let mut ignore = Ignore::load(&worktree, &repo_path, &config)?;
+        // without --exclude-standard ignored files are listed too, as in git
+        for file in ignore.walk("", !args.exclude_standard)? {
+            if !tracked.contains(file.path.as_str()) && pathspec.matches(&file.path) {
+                emit(String::new(), &file.path)?;
+            }
+        }
+    }
+
+    let check = StatCheck::load(&repo_path, &config)?;
+    let lfs = Lfs::load(&worktree, &repo_path, &config)?;
+    for entry in entries.iter().filter(|e| pathspec.matches(&e.path)) {
+        // -s shows mode, sha and stage on every index-based line
+        let prefix = match args.stage {
+            true => format!("{:06o} {} {}\t", entry.mode, hex::encode(entry.sha), entry.stage()),
+            false => String::new(),
+        };
+        if show_cached {
+            emit(prefix.clone(), &entry.path)?;
+        }
+        if !args.modified && !args.deleted {
+            continue;
+        }
+        let file = worktree.join(&entry.path);
+        let exists = fs::symlink_metadata(&file).is_ok_and(|m| !m.is_dir());
+        if args.deleted && !exists {
+            emit(prefix.clone(), &entry.path)?;
+        }
+        // a deleted file counts as modified too
+        if args.modified && (!exists || !worktree_matches(&file, entry, &check, &lfs)?) {
+            emit(prefix, &entry.path)?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+// core.quotePath style quoting: paths with control characters, quotes,
+// backslashes or non-ASCII bytes are wrapped in quotes and escaped.
+pub(crate) fn quote_path(path: &str) -> String {
+    let needs_quoting = path.bytes().any(|b| !(0x20..0x7f).contains(&b) || b == b'"' || b == b'\\');
+    if !needs_quoting {
+        return path.to_string();
+    }
+    let mut quoted = String::from("\"");
+    for b in path.bytes() {
+        match b {
+            b'"' => quoted.push_str("\\\""),
+            b'\\' => quoted.push_str("\\\\"),
+            b'\t' => quoted.push_str("\\t"),
+            b'\n' => quoted.push_str("\\n"),
+            b'\r' => quoted.push_str("\\r"),
+            0x07 => quoted.push_str("\\a"),
+            0x08 => quoted.push_str("\\b"),
+            0x0b => quoted.push_str("\\v"),
+            0x0c => quoted.push_str("\\f"),
+            b if !(0x20..0x7f).contains(&b) => quoted.push_str(&format!("\\{b:03o}")),
+            b => quoted.push(b as char),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,116 @@
+use crate::{
+    commands::ls_files::quote_path,
+    error::ItError,
+    index::index_path,
+    refs::resolve_object,
+    repository,
+    tree::{TreeEntry, peel_to_tree, read_tree},
+};
+use std::io::Write;
+use std::path::Path;
+
+pub struct LsTreeArgs {
+    pub tree_ish: String,
+    pub paths: Vec<String>,
+    pub recursive: bool,
+    pub show_trees: bool,
+    pub name_only: bool,
+    pub zero: bool,
+}
+
+// How a tree entry relates to the path arguments.
+#[derive(PartialEq)]
+enum PathMatch {
+    // named exactly, or below a directory given with a trailing '/'
+    Show,
+    // a directory on the way to a named path
+    Lead,
+    None,
+}
+
+// Lists a tree the way `git ls-tree` does: "<mode> <type> <sha>\t<name>".
+// Paths limit the listing; "dir" names the directory entry itself while
+// "dir/" lists what is inside it.
+pub fn ls_tree(args: LsTreeArgs) -> Result<(), ItError> {
+    let repo_path = repository::repo_path()?;
+    if !repo_path.is_dir() {
+        return Err(ItError::NotARepository);
+    }
+    let tree = peel_to_tree(&resolve_object(&repo_path, &args.tree_ish)?)?;
+    let patterns: Vec<(String, bool)> = args
+        .paths
+        .iter()
+        .map(|p| (index_path(Path::new(p)), p.ends_with('/')))
+        .collect();
+
+    let mut out = std::io::stdout().lock();
+    list(&tree, "", &patterns, patterns.is_empty(), &args, &mut out)?;
+    out.flush()?;
+    Ok(())
+}
+
+fn list(
+    tree: &str,
+    prefix: &str,
+    patterns: &[(String, bool)],
+    inside_match: bool,
+    args: &LsTreeArgs,
+    out: &mut impl Write,
+) -> Result<(), ItError> {
+    for entry in read_tree(tree)? {
+        let path = format!("{prefix}{}", entry.name);
+        let matched = if inside_match { PathMatch::Show } else { match_path(&path, patterns) };
+        let sha = hex::encode(entry.sha);
+
+        match matched {
+            PathMatch::None => {}
+            PathMatch::Lead if !entry.is_tree() => {}
+            PathMatch::Lead => {
+                if args.show_trees {
+                    print_entry(&entry, &path, args, out)?;
+                }
+                list(&sha, &format!("{path}/"), patterns, false, args, out)?;
+            }
+            PathMatch::Show if entry.is_tree() && args.recursive => {
+                if args.show_trees {
+                    print_entry(&entry, &path, args, out)?;
+                }
+                list(&sha, &format!("{path}/"), patterns, true, args, out)?;
+            }
+            PathMatch::Show => print_entry(&entry, &path, args, out)?,
+        }
+    }
+    Ok(())
+}
+
+fn match_path(path: &str, patterns: &[(String, bool)]) -> PathMatch {
+    let mut result = PathMatch::None;
+    for (pattern, dir_contents) in patterns {
+        if pattern.is_empty() {
+            return PathMatch::Show;
+        }
+        let below = path.starts_with(&format!("{pattern}/"));
+        if below || (path == pattern && !dir_contents) {
+            return PathMatch::Show;
+        }
+        if pattern == path || pattern.starts_with(&format!("{path}/")) {
+            result = PathMatch::Lead;
+        }
+    }
+    result
+}
+
+fn print_entry(entry: &TreeEntry, path: &str, args: &LsTreeArgs, out: &mut impl Write) -> std::io::Result<()> {
+    let name = if args.zero { path.to_string() } else { quote_path(path) };
+    let end = if args.zero { '\0' } else { '\n' };
+    if args.name_only {
+        return write!(out, "{name}{end}");
+    }
+    let mode = u32::from_str_radix(&entry.mode, 8).unwrap_or(0);
+    let kind = match mode {
+        0o040000 => "tree",
+        0o160000 => "commit",
+        _ => "blob",
+    };
+    write!(out, "{mode:06o} {kind} {}\t{name}{end}", hex::encode(entry.sha))
+}
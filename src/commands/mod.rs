@@ -1,9 +1,34 @@
 pub mod add;
 pub mod branch;
+pub mod check_ignore;
+pub mod clean;
+pub mod clone;
 pub mod commit_tree;
+pub mod config;
+pub mod fetch;
+pub mod fsck;
+pub mod gc;
 pub mod hash_object;
+pub mod index_pack;
 pub mod init;
+pub mod lfs;
 pub mod log;
+pub mod ls_files;
+pub mod ls_tree;
+pub mod multi_pack_index;
+pub mod mv;
+pub mod prune;
+pub mod push;
+pub mod read_tree;
+pub mod remote;
+pub mod repack;
 pub mod reset;
+pub mod restore;
+pub mod rm;
+pub mod show;
+pub mod status;
 pub mod switch;
+pub mod symbolic_ref;
+pub mod update_ref;
+pub mod verify_pack;
 pub mod write_tree;
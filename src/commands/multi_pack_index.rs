@@ -0,0 +1,119 @@
+use crate::{
+    bitmap::{bitmap_path, load_bitmap, remove_stale_bitmaps, verify_bitmap, write_bitmap},
+    error::ItError,
+    midx::{MultiPackIndex, midx_path, write_midx},
+    pack,
+    reachable::ref_tips,
+    repository,
+};
+use sha1::{Digest, Sha1};
+use std::fs;
+use std::path::Path;
+
+// Writes a multi-pack-index over every pack, and with `bitmap` reachability
+// bitmaps for the ref tips and a sample of history. Bitmaps from earlier
+// indexes are removed.
+pub fn write(bitmap: bool) -> Result<(), ItError> {
+    let repo_path = repository::repo_path()?;
+    if !repo_path.is_dir() {
+        return Err(ItError::NotARepository);
+    }
+    write_index(&repo_path, bitmap)
+}
+
+pub(crate) fn write_index(repo_path: &Path, bitmap: bool) -> Result<(), ItError> {
+    let objects_dir = repo_path.join("objects");
+    let midx = write_midx(&objects_dir)?;
+    println!("multi-pack-index: {} object(s) in {} pack(s)", midx.hashes.len(), midx.pack_names.len());
+
+    let keep = bitmap_path(&objects_dir, &midx);
+    remove_stale_bitmaps(&objects_dir, bitmap.then_some(keep.as_path()))?;
+    if bitmap {
+        let tips: Vec<String> = ref_tips(repo_path)?.into_iter().map(|(_, hash)| hash).collect();
+        let count = write_bitmap(&objects_dir, &midx, &tips)?;
+        println!("bitmap: {count} commit(s)");
+    }
+    Ok(())
+}
+
+// Checks the multi-pack-index against the packs it covers and, when there is
+// one, its bitmap against the objects. Exits with status 1 on any error.
+pub fn verify() -> Result<(), ItError> {
+    let repo_path = repository::repo_path()?;
+    if !repo_path.is_dir() {
+        return Err(ItError::NotARepository);
+    }
+    let objects_dir = repo_path.join("objects");
+    let path = midx_path(&objects_dir);
+    if !path.exists() {
+        return Ok(());
+    }
+
+    let errors = match verify_midx(&objects_dir, &path) {
+        Ok(errors) => errors,
+        Err(e) => vec![e.to_string()],
+    };
+    for error in &errors {
+        eprintln!("error: {error}");
+    }
+    if !errors.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn verify_midx(objects_dir: &Path, path: &Path) -> Result<Vec<String>, ItError> {
+    let mut errors = Vec::new();
+    let data = fs::read(path)?;
+    if data.len() < 20 || Sha1::digest(&data[..data.len() - 20])[..] != data[data.len() - 20..] {
+        errors.push("multi-pack-index checksum mismatch".to_string());
+    }
+    let midx = MultiPackIndex::load(path)?;
+
+    if midx.pack_names.windows(2).any(|w| w[0] >= w[1]) {
+        errors.push("pack names out of order".to_string());
+    }
+    let mut packs = Vec::new();
+    for name in &midx.pack_names {
+        let idx_path = objects_dir.join("pack").join(name);
+        match idx_path.with_extension("pack").is_file().then(|| pack::load_index(&idx_path)) {
+            Some(Ok(index)) => packs.push(Some(index)),
+            _ => {
+                errors.push(format!("failed to load pack {name}"));
+                packs.push(None);
+            }
+        }
+    }
+
+    if midx.hashes.windows(2).any(|w| w[0] >= w[1]) {
+        errors.push("object ids out of order".to_string());
+    }
+    for (sha, (pack_id, offset)) in midx.hashes.iter().zip(&midx.objects) {
+        let Some(pack) = &packs[*pack_id as usize] else {
+            continue;
+        };
+        match pack.find(sha) {
+            Some(i) if pack.offsets[i] == *offset => {}
+            Some(_) => errors.push(format!("incorrect object offset for {}", hex::encode(sha))),
+            None => errors.push(format!("{} is not in {}", hex::encode(sha), midx.pack_names[*pack_id as usize])),
+        }
+    }
+
+    if let Some(order) = &midx.pack_order {
+        let mut seen = vec![false; order.len()];
+        for &i in order {
+            match seen.get_mut(i as usize) {
+                Some(seen) if !*seen => *seen = true,
+                _ => errors.push("pack order (RIDX) is not a permutation".to_string()),
+            }
+        }
+    }
+    if !errors.is_empty() {
+        return Ok(errors);
+    }
+
+    if let Some(bitmaps) = load_bitmap(objects_dir, &midx)? {
+        errors.extend(verify_bitmap(objects_dir, &midx, &bitmaps)?);
+    }
+    Ok(errors)
+}
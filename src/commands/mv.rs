@@ -0,0 +1,69 @@
+use crate::{
+    error::ItError,
+    index::{lock_index, read_index, write_locked_index},
+    repository,
+};
+use colored::Colorize;
+use std::fs;
+use std::path::Path;
+
+pub fn mv(source: String, destination: String, force: bool) -> Result<(), ItError> {
+    let worktree = repository::worktree()?;
+    let repo_path = repository::repo_path()?;
+    if !repo_path.is_dir() {
+        return Err(ItError::NotARepository);
+    }
+
+    let src = repository::worktree_path(&source)?;
+    let mut dst = repository::worktree_path(&destination)?;
+    let src_abs = worktree.join(&src);
+
+    if src.is_empty() || fs::symlink_metadata(&src_abs).is_err() {
+        return Err(ItError::Fatal(format!("bad source, source={source}, destination={destination}")));
+    }
+    // "it mv file dir" moves the file into dir, like mv(1)
+    if worktree.join(&dst).is_dir() {
+        let name = src.rsplit('/').next().unwrap_or(&src);
+        dst = if dst.is_empty() { name.to_string() } else { format!("{dst}/{name}") };
+    }
+    if dst == src || dst.starts_with(&format!("{src}/")) {
+        return Err(ItError::Fatal(format!("can not move directory into itself, source={src}, destination={dst}")));
+    }
+
+    let lock = lock_index(&repo_path)?;
+    let mut entries = read_index(&repo_path)?;
+    let is_moved = |path: &str| path == src || path.starts_with(&format!("{src}/"));
+    if !entries.iter().any(|e| is_moved(&e.path)) {
+        return Err(ItError::Fatal(format!("not under version control, source={src}, destination={dst}")));
+    }
+
+    let is_replaced = |path: &str| !is_moved(path) && (path == dst || path.starts_with(&format!("{dst}/")));
+    if !force && entries.iter().any(|e| is_replaced(&e.path)) {
+        return Err(ItError::Fatal(format!("destination exists in the index, source={src}, destination={dst}")));
+    }
+
+    let dst_abs = worktree.join(&dst);
+    if let Ok(meta) = fs::symlink_metadata(&dst_abs) {
+        if !force || meta.is_dir() {
+            return Err(ItError::Fatal(format!("destination exists, source={src}, destination={dst}")));
+        }
+        fs::remove_file(&dst_abs)?;
+    }
+
+    if !dst_abs.parent().is_some_and(Path::is_dir) {
+        return Err(ItError::Fatal(format!("destination directory does not exist, source={src}, destination={dst}")));
+    }
+    fs::rename(&src_abs, &dst_abs)?;
+
+    // the moved entries keep their sha, mode and stat data, a later status
+    // rehashes them once and notices the content is unchanged
+    entries.retain(|e| !is_replaced(&e.path));
+    for entry in entries.iter_mut().filter(|e| is_moved(&e.path)) {
+        entry.path = format!("{dst}{}", &entry.path[src.len()..]);
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    write_locked_index(lock, &repo_path, &entries)?;
+
+    println!("{} {} -> {}", "renamed:".green(), src, dst);
+    Ok(())
+}
@@ -0,0 +1,131 @@
+use crate::{
+    commands::hash_object::read_object,
+    error::ItError,
+    pack,
+    reachable::{loose_objects, reachable_objects},
+    repository,
+};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Objects this young are kept even when unreachable, so a blob just written
+// by a concurrent `it add` is not removed before it is staged.
+pub const DEFAULT_EXPIRE: &str = "2.weeks.ago";
+
+pub fn prune(expire: Option<String>, dry_run: bool) -> Result<(), ItError> {
+    let repo_path = repository::repo_path()?;
+    if !repo_path.is_dir() {
+        return Err(ItError::NotARepository);
+    }
+    let cutoff = parse_expire(expire.as_deref().unwrap_or(DEFAULT_EXPIRE))?;
+    prune_objects(&repo_path, cutoff, dry_run)
+}
+
+// Deletes loose objects that nothing reaches and that were last written at
+// or before `cutoff`; `None` keeps everything. Loose copies of packed objects
+// are redundant and go regardless. With `dry_run` the objects are only listed
+// as "<hash> <type>".
+pub(crate) fn prune_objects(repo_path: &Path, cutoff: Option<SystemTime>, dry_run: bool) -> Result<(), ItError> {
+    // the whole graph is marked before anything is deleted
+    let reachable = reachable_objects(repo_path)?;
+    let (objects, _) = loose_objects(repo_path)?;
+    let objects_dir = repo_path.join("objects");
+
+    let mut count = 0;
+    let mut freed = 0;
+    for object in objects {
+        let meta = fs::metadata(&object.path)?;
+        let expired = cutoff.is_some_and(|cutoff| meta.modified().is_ok_and(|m| m <= cutoff));
+        let wanted = reachable.contains_key(&object.hash) || !expired;
+        if wanted && !pack::contains(&objects_dir, &object.hash)? {
+            continue;
+        }
+        if dry_run {
+            let kind = read_object(&object.hash).map(|(kind, _)| kind).unwrap_or_else(|_| "unknown".to_string());
+            println!("{} {kind}", object.hash);
+        } else {
+            remove_loose(&object.path)?;
+        }
+        count += 1;
+        freed += meta.len();
+    }
+
+    let size = human_size(freed);
+    match (dry_run, count) {
+        (_, 0) => println!("Nothing to prune"),
+        (true, n) => println!("Would remove {n} unreachable object{}, freeing {size}", plural(n)),
+        (false, n) => println!("Removed {n} unreachable object{}, freed {size}", plural(n)),
+    }
+    Ok(())
+}
+
+// Accepts "now", "never", "<seconds since the epoch>" and relative dates like
+// "2.weeks.ago" or "3 days ago". `None` means nothing ever expires.
+pub(crate) fn parse_expire(value: &str) -> Result<Option<SystemTime>, ItError> {
+    let invalid = || ItError::Fatal(format!("invalid expiry date '{value}'"));
+    let now = SystemTime::now();
+    match value.trim() {
+        "never" | "false" => return Ok(None),
+        "now" | "all" => return Ok(Some(now)),
+        v if !v.is_empty() && v.chars().all(|c| c.is_ascii_digit()) => {
+            let secs = v.parse::<u64>().map_err(|_| invalid())?;
+            return Ok(Some(UNIX_EPOCH + Duration::from_secs(secs)));
+        }
+        _ => {}
+    }
+
+    let words: Vec<&str> = value.split(['.', ' ']).filter(|w| !w.is_empty()).collect();
+    let [amount, unit, "ago"] = words.as_slice() else {
+        return Err(invalid());
+    };
+    let amount = amount.parse::<u64>().map_err(|_| invalid())?;
+    let unit_secs = match unit.trim_end_matches('s') {
+        "second" => 1,
+        "minute" => 60,
+        "hour" => 3600,
+        "day" => 86400,
+        "week" => 7 * 86400,
+        "month" => 30 * 86400,
+        "year" => 365 * 86400,
+        _ => return Err(invalid()),
+    };
+    let ago = Duration::from_secs(amount.saturating_mul(unit_secs));
+    Ok(Some(now.checked_sub(ago).unwrap_or(UNIX_EPOCH)))
+}
+
+// Deletes the loose copies of objects that are also in a pack, returning how
+// many were removed.
+pub(crate) fn prune_packed(repo_path: &Path) -> Result<usize, ItError> {
+    let objects_dir = repo_path.join("objects");
+    let (objects, _) = loose_objects(repo_path)?;
+    let mut count = 0;
+    for object in objects {
+        if pack::contains(&objects_dir, &object.hash)? {
+            remove_loose(&object.path)?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+fn remove_loose(path: &Path) -> Result<(), ItError> {
+    fs::remove_file(path)?;
+    // the fan-out directory goes too once it is empty
+    if let Some(dir) = path.parent() {
+        let _ = fs::remove_dir(dir);
+    }
+    Ok(())
+}
+
+fn human_size(bytes: u64) -> String {
+    match bytes {
+        b if b < 1024 => format!("{b} bytes"),
+        b if b < 1024 * 1024 => format!("{:.2} KiB", b as f64 / 1024.0),
+        b => format!("{:.2} MiB", b as f64 / (1024.0 * 1024.0)),
+    }
+}
+
+fn plural(n: usize) -> &'static str {
+    if n == 1 { "" } else { "s" }
+}
@@ -0,0 +1,88 @@
+use crate::{
+    commands::remote::Remote,
+    error::ItError,
+    reachable::object_exists,
+    refs::{is_valid_ref_name, read_ref, read_symbolic_ref, resolve_rev, update_ref},
+    repository::{self, is_bare},
+    transport::{
+        self, add_shallow, check_format, copy_objects, is_fast_forward, short_ref_name, update_line, update_summary,
+    },
+};
+
+// `it push <remote> <branch>` copies the objects the remote lacks and moves
+// its refs/heads/<branch> to ours. `<src>:<dst>` pushes the revision `src` to
+// the remote branch `dst`.
+//
+// The remote ref must be an ancestor of what is pushed, so that no commits
+// are lost there; --force (or a leading `+`) skips that check. A remote that
+// has the branch checked out in a worktree refuses it, since its index and
+// files would no longer match HEAD. After a push to a named remote its
+// remote-tracking ref is moved as well.
+pub fn push(remote: String, branch: String, force: bool) -> Result<(), ItError> {
+    let repo_path = repository::repo_path()?;
+    let remote = Remote::lookup(&remote)?;
+    let dest = transport::open(&remote.url)?;
+    check_format(&dest)?;
+    let url = transport::url_path(&remote.url)?.to_string_lossy().to_string();
+
+    let (force, spec) = match branch.strip_prefix('+') {
+        Some(spec) => (true, spec),
+        None => (force, branch.as_str()),
+    };
+    let (src, dst) = spec.split_once(':').unwrap_or((spec, spec));
+    let new = resolve_rev(&repo_path, src)
+        .map_err(|_| ItError::Fatal(format!("src refspec {src} does not match any")))?;
+    let dst_ref = match dst.starts_with("refs/") {
+        true => dst.to_string(),
+        false if read_ref(&repo_path, &format!("refs/tags/{dst}"))?.is_some()
+            && read_ref(&repo_path, &format!("refs/heads/{dst}"))?.is_none() =>
+        {
+            format!("refs/tags/{dst}")
+        }
+        false => format!("refs/heads/{dst}"),
+    };
+    if !is_valid_ref_name(&dst_ref) {
+        return Err(ItError::Fatal(format!("invalid destination ref '{dst}'")));
+    }
+
+    let old = read_ref(&dest, &dst_ref)?;
+    println!("To {url}");
+    if old.as_deref() == Some(new.as_str()) {
+        println!("Everything up-to-date");
+        return Ok(());
+    }
+
+    let (from, to) = (short_ref_name(src), short_ref_name(&dst_ref));
+    let reject = |reason: &str| {
+        println!("{}", update_line('!', "[rejected]", from, to, &format!(" ({reason})")));
+        ItError::Fatal(format!("failed to push some refs to '{url}'"))
+    };
+    if !is_bare(&dest)? && read_symbolic_ref(&dest, "HEAD")?.as_deref() == Some(dst_ref.as_str()) {
+        return Err(reject("branch is currently checked out"));
+    }
+    let fast_forward = is_fast_forward(&repo_path, old.as_deref(), &new)?;
+    if !fast_forward && !force {
+        let reason = match &old {
+            Some(_) if dst_ref.starts_with("refs/tags/") => "already exists",
+            Some(old) if !object_exists(&repo_path, old)? => "fetch first",
+            _ => "non-fast-forward",
+        };
+        return Err(reject(reason));
+    }
+
+    let transfer = copy_objects(&repo_path, &dest, std::slice::from_ref(&new), None)?;
+    add_shallow(&dest, &transfer.shallow)?;
+    update_ref(&dest, &dst_ref, &new, Some(old.as_deref()))?;
+
+    let kind = if dst_ref.starts_with("refs/tags/") { "tag" } else { "branch" };
+    let (flag, summary) = update_summary(old.as_deref(), &new, !fast_forward, kind);
+    let note = if fast_forward { "" } else { " (forced update)" };
+    println!("{}", update_line(flag, &summary, from, to, note));
+
+    // only a named remote has remote-tracking refs
+    let tracking = remote.fetch.iter().find_map(|spec| spec.map(&dst_ref)).filter(|_| remote.name.is_some());
+    if let Some(local) = tracking {
+        update_ref(&repo_path, &local, &new, None)?;
+    }
+    Ok(())
+}
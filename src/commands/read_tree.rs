@@ -0,0 +1,174 @@
+use crate::{
+    error::ItError,
+    index::{IndexEntry, lock_index, read_index, write_index, write_locked_index},
+    object_id::ObjectId,
+    refs::resolve_object,
+    repository,
+    tree::{flatten_tree, peel_to_tree},
+};
+use std::collections::{BTreeMap, BTreeSet};
+
+type Blob = (u32, ObjectId);
+
+pub struct ReadTreeArgs {
+    pub trees: Vec<String>,
+    pub empty: bool,
+    pub merge: bool,
+    pub aggressive: bool,
+}
+
+// Loads trees into the index; the worktree is never read or written.
+//
+//      it read-tree <tree>             replace the index with <tree>
+//      it read-tree -m <H> <M>         move the index from H to M, keeping
+//                                      staged changes that do not collide
+//      it read-tree -m <O> <A> <B>     three-way merge, unresolved paths get
+//                                      stages 1 (base), 2 (ours), 3 (theirs)
+//
+// Entries that end up identical to what the index held keep their stat data,
+// so an unchanged worktree is not rehashed afterwards.
+pub fn read_tree(args: ReadTreeArgs) -> Result<(), ItError> {
+    let repo_path = repository::repo_path()?;
+    if !repo_path.is_dir() {
+        return Err(ItError::NotARepository);
+    }
+    match (args.empty, args.trees.len(), args.merge) {
+        (true, 0, false) => return write_index(&repo_path, &[]),
+        (true, _, _) => return Err(ItError::Fatal("--empty takes no trees and no -m".to_string())),
+        (false, 0, _) => return Err(ItError::Fatal("read-tree needs a tree, or --empty".to_string())),
+        (false, 2 | 3, false) => return Err(ItError::Fatal("merging several trees needs -m".to_string())),
+        (false, n, _) if n > 3 => return Err(ItError::Fatal("at most three trees can be merged".to_string())),
+        _ => {}
+    }
+
+    let mut trees = Vec::new();
+    for spec in &args.trees {
+        let tree = peel_to_tree(&resolve_object(&repo_path, spec)?)?;
+        let files: BTreeMap<String, Blob> = flatten_tree(&tree)?
+            .into_iter()
+            .map(|(path, (mode, sha))| (path, (u32::from_str_radix(&mode, 8).unwrap_or(0), sha)))
+            .collect();
+        trees.push(files);
+    }
+
+    let lock = lock_index(&repo_path)?;
+    let old_entries = read_index(&repo_path)?;
+    if args.merge && old_entries.iter().any(|e| e.stage() != 0) {
+        return Err(ItError::Fatal("you need to resolve your current index first".to_string()));
+    }
+    let index: BTreeMap<String, Blob> = old_entries
+        .iter()
+        .filter(|e| e.stage() == 0)
+        .map(|e| (e.path.clone(), (e.mode, e.sha)))
+        .collect();
+
+    // path -> blobs by stage, stage 0 alone for a merged path
+    let result: BTreeMap<String, Vec<(u16, Blob)>> = match trees.as_slice() {
+        [tree] => tree.iter().map(|(path, blob)| (path.clone(), vec![(0, *blob)])).collect(),
+        [head, merge] => two_way(&index, head, merge)?,
+        [base, ours, theirs] => three_way(&index, base, ours, theirs, args.aggressive)?,
+        _ => unreachable!(),
+    };
+
+    let mut entries = Vec::new();
+    for (path, stages) in result {
+        for (stage, (mode, sha)) in stages {
+            let stat = old_entries
+                .iter()
+                .find(|e| stage == 0 && e.stage() == 0 && e.path == path && e.mode == mode && e.sha == sha)
+                .map(|e| e.stat)
+                .unwrap_or_default();
+            entries.push(IndexEntry { stat, mode, sha, flags: stage << 12, path: path.clone() });
+        }
+    }
+    write_locked_index(lock, &repo_path, &entries)
+}
+
+fn would_overwrite(path: &str) -> ItError {
+    ItError::Fatal(format!("Entry '{path}' would be overwritten by merge. Cannot merge."))
+}
+
+// git's two-tree rules: a path takes M's version when the index still holds
+// H's, keeps the index when it already matches M or H and M agree, and the
+// merge fails when a staged change would be lost.
+fn two_way(
+    index: &BTreeMap<String, Blob>,
+    head: &BTreeMap<String, Blob>,
+    merge: &BTreeMap<String, Blob>,
+) -> Result<BTreeMap<String, Vec<(u16, Blob)>>, ItError> {
+    // reading into an empty index is an initial checkout and simply takes M
+    let initial = index.is_empty();
+    let paths: BTreeSet<&String> = index.keys().chain(head.keys()).chain(merge.keys()).collect();
+    let mut result = BTreeMap::new();
+
+    for path in paths {
+        let (i, h, m) = (index.get(path), head.get(path), merge.get(path));
+        let keep = match (i, h, m) {
+            (None, None, Some(_)) => m,
+            (None, Some(_), None) => None,
+            (None, Some(_), Some(_)) if initial => m,
+            (None, Some(h), Some(m)) if h == m => None,
+            (None, _, _) => return Err(would_overwrite(path)),
+            (Some(_), None, None) => i,
+            (Some(i), None, Some(m)) if i == m => Some(i),
+            (Some(i), Some(h), None) if i == h => None,
+            (Some(_), Some(h), Some(m)) if h == m => i,
+            (Some(i), Some(_), Some(m)) if i == m => Some(i),
+            (Some(i), Some(h), Some(_)) if i == h => m,
+            (Some(_), _, _) => return Err(would_overwrite(path)),
+        };
+        if let Some(blob) = keep {
+            result.insert(path.clone(), vec![(0, *blob)]);
+        }
+    }
+    Ok(result)
+}
+
+// Trivial three-way merging as git read-tree does it. The index has to match
+// ours (A) everywhere. A path is resolved when both sides hold the same
+// content, or when only one side changed it; with --aggressive also when a
+// side deleted an unchanged path or both deleted it. Anything else is left
+// as conflict stages for a porcelain to resolve.
+fn three_way(
+    index: &BTreeMap<String, Blob>,
+    base: &BTreeMap<String, Blob>,
+    ours: &BTreeMap<String, Blob>,
+    theirs: &BTreeMap<String, Blob>,
+    aggressive: bool,
+) -> Result<BTreeMap<String, Vec<(u16, Blob)>>, ItError> {
+    let paths: BTreeSet<&String> = index.keys().chain(base.keys()).chain(ours.keys()).chain(theirs.keys()).collect();
+    let mut result = BTreeMap::new();
+
+    for path in paths {
+        let (i, o, a, b) = (index.get(path), base.get(path), ours.get(path), theirs.get(path));
+        if i != a {
+            return Err(would_overwrite(path));
+        }
+
+        let resolved = if a == b && a.is_some() {
+            Some(a)
+        } else if o == a && b.is_some() {
+            Some(b)
+        } else if o == b && a.is_some() {
+            Some(a)
+        } else if aggressive && (a == b || (o == a && b.is_none()) || (o == b && a.is_none())) {
+            // every such case ends with the path deleted
+            Some(None)
+        } else {
+            None
+        };
+
+        match resolved {
+            Some(Some(blob)) => {
+                result.insert(path.clone(), vec![(0, *blob)]);
+            }
+            Some(None) => {}
+            None => {
+                let stages: Vec<(u16, Blob)> =
+                    [(1, o), (2, a), (3, b)].into_iter().filter_map(|(s, e)| e.map(|e| (s, *e))).collect();
+                result.insert(path.clone(), stages);
+            }
+        }
+    }
+    Ok(result)
+}
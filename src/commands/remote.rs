@@ -0,0 +1,124 @@
+use crate::{
+    config::{Config, expand_home, remove_section, set_value, unset_value},
+    error::ItError,
+    refs::is_valid_ref_name,
+    repository::{self, normalize},
+    transport::Refspec,
+};
+use std::fs;
+
+// A repository fetch and push talk to: one configured as
+//
+//      [remote "origin"]
+//          url = /srv/project
+//          fetch = +refs/heads/*:refs/remotes/origin/*
+//
+// or a path given directly on the command line, which has no name and so no
+// remote-tracking refs.
+pub struct Remote {
+    pub name: Option<String>,
+    pub url: String,
+    pub fetch: Vec<Refspec>,
+}
+
+impl Remote {
+    pub fn lookup(arg: &str) -> Result<Remote, ItError> {
+        let config = Config::load(&repository::repo_path()?)?;
+        match config.get(&format!("remote.{arg}.url")) {
+            Some(url) => {
+                let mut fetch = Vec::new();
+                for spec in config.get_all(&format!("remote.{arg}.fetch")) {
+                    fetch.push(Refspec::parse(spec)?);
+                }
+                if fetch.is_empty() {
+                    fetch.push(Refspec::parse(&default_fetch(arg))?);
+                }
+                Ok(Remote { name: Some(arg.to_string()), url: url.to_string(), fetch })
+            }
+            None => {
+                let url = match arg.starts_with("file://") {
+                    true => arg.to_string(),
+                    false => repository::user_path(std::path::Path::new(arg)).to_string_lossy().to_string(),
+                };
+                Ok(Remote { name: None, url, fetch: Vec::new() })
+            }
+        }
+    }
+}
+
+fn default_fetch(name: &str) -> String {
+    format!("+refs/heads/*:refs/remotes/{name}/*")
+}
+
+// Names of the configured remotes, in the order the config lists them.
+fn remote_names(config: &Config) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for entry in &config.entries {
+        let name = entry.key.strip_prefix("remote.").and_then(|rest| rest.strip_suffix(".url"));
+        if let Some(name) = name.filter(|n| !names.iter().any(|m| m == n)) {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+pub fn list(verbose: bool) -> Result<(), ItError> {
+    let config = Config::load(&repository::repo_path()?)?;
+    for name in remote_names(&config) {
+        if verbose {
+            let url = config.get(&format!("remote.{name}.url")).unwrap_or("");
+            println!("{name}\t{url} (fetch)");
+            println!("{name}\t{url} (push)");
+        } else {
+            println!("{name}");
+        }
+    }
+    Ok(())
+}
+
+// The URL is stored as an absolute path, so the remote is found again from
+// any directory.
+pub fn add(name: String, url: String) -> Result<(), ItError> {
+    let config_path = repository::repo_path()?.join("config");
+    if !is_valid_ref_name(&format!("refs/remotes/{name}")) {
+        return Err(ItError::Fatal(format!("'{name}' is not a valid remote name")));
+    }
+    let config = Config::load_file(&config_path)?;
+    if remote_names(&config).contains(&name) {
+        return Err(ItError::Fatal(format!("remote {name} already exists.")));
+    }
+
+    let path = expand_home(url.strip_prefix("file://").unwrap_or(&url));
+    let url = normalize(&std::env::current_dir()?.join(repository::user_path(&path)));
+    set_value(&config_path, &format!("remote.{name}.url"), &url.to_string_lossy())?;
+    set_value(&config_path, &format!("remote.{name}.fetch"), &default_fetch(&name))?;
+    Ok(())
+}
+
+// Forgets the remote along with its remote-tracking refs and any branch
+// settings that pointed at it.
+pub fn remove(name: String) -> Result<(), ItError> {
+    let repo_path = repository::repo_path()?;
+    let config_path = repo_path.join("config");
+    if !remove_section(&config_path, "remote", Some(&name))? {
+        return Err(ItError::Fatal(format!("No such remote: '{name}'")));
+    }
+
+    let config = Config::load_file(&config_path)?;
+    for entry in &config.entries {
+        let branch = entry.key.strip_prefix("branch.").and_then(|rest| rest.strip_suffix(".remote"));
+        if let Some(branch) = branch.filter(|_| entry.value.as_deref() == Some(name.as_str())) {
+            unset_value(&config_path, &format!("branch.{branch}.remote"))?;
+            unset_value(&config_path, &format!("branch.{branch}.merge"))?;
+            let rest = Config::load_file(&config_path)?;
+            if !rest.entries.iter().any(|e| e.key.starts_with(&format!("branch.{branch}."))) {
+                remove_section(&config_path, "branch", Some(branch))?;
+            }
+        }
+    }
+
+    match fs::remove_dir_all(repo_path.join("refs/remotes").join(&name)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,100 @@
+use crate::{
+    commands::{
+        hash_object::{build_object, compress_data, read_object, store_object},
+        prune::prune_packed,
+    },
+    bitmap::remove_stale_bitmaps,
+    config::Config,
+    error::ItError,
+    midx::midx_path,
+    object_id::ObjectFormat,
+    pack::{self, PackObject, PackOptions, read_entry, type_name, write_pack},
+    reachable::reachable_objects,
+    repository,
+};
+use std::fs;
+
+pub struct RepackArgs {
+    pub all: bool,
+    pub delete: bool,
+    pub window: usize,
+    pub depth: usize,
+}
+
+// Packs reachable objects into a new pack under objects/pack.
+//
+//      it repack           packs the reachable objects that are still loose
+//      it repack -a        packs everything reachable into one pack
+//      -d                  then removes what the new pack makes redundant:
+//                          loose copies, and with -a the old packs along
+//                          with the multi-pack-index over them
+//
+// Unreachable objects in old packs are written back as loose objects
+// carrying the pack's mtime, so `it prune` still applies its grace period
+// to them instead of them vanishing with the pack.
+pub fn repack(args: RepackArgs) -> Result<(), ItError> {
+    let repo_path = repository::repo_path()?;
+    if !repo_path.is_dir() {
+        return Err(ItError::NotARepository);
+    }
+    if ObjectFormat::current() != ObjectFormat::Sha1 {
+        return Err(ItError::Fatal("packs are not supported in sha256 repositories".to_string()));
+    }
+    let objects_dir = repo_path.join("objects");
+    let config = Config::load(&repo_path)?;
+    let options = PackOptions {
+        window: args.window,
+        depth: args.depth,
+        ofs_delta: config.get_bool("repack.useDeltaBaseOffset")?.unwrap_or(true),
+    };
+
+    let reachable = reachable_objects(&repo_path)?;
+    let old_packs = pack::packs(&objects_dir)?;
+    let mut objects = Vec::new();
+    for (hash, name_hash) in &reachable {
+        if !args.all && pack::contains(&objects_dir, hash)? {
+            continue;
+        }
+        let (kind, data) = read_object(hash)?;
+        objects.push(PackObject { hash: hash.clone(), kind, data, name_hash: *name_hash });
+    }
+    if objects.is_empty() {
+        println!("Nothing new to pack.");
+        return Ok(());
+    }
+
+    let summary = write_pack(&objects_dir, objects, &options)?;
+    println!("Total {} (delta {})", summary.objects, summary.deltas);
+    println!("pack-{}", summary.name);
+    if !args.delete {
+        return Ok(());
+    }
+
+    let mut removed_pack = false;
+    if args.all {
+        for old in old_packs.iter().filter(|p| !p.pack_path.ends_with(format!("pack-{}.pack", summary.name))) {
+            let mtime = fs::metadata(&old.pack_path)?.modified()?;
+            for (sha, offset) in old.hashes.iter().zip(&old.offsets) {
+                let hash = hex::encode(sha);
+                if reachable.contains_key(&hash) {
+                    continue;
+                }
+                let (kind, body) = read_entry(old, *offset)?;
+                let raw = build_object(&body, type_name(kind));
+                store_object(&hash, &compress_data(&raw)?)?;
+                let loose = objects_dir.join(&hash[..2]).join(&hash[2..]);
+                fs::File::options().write(true).open(loose)?.set_modified(mtime)?;
+            }
+            fs::remove_file(&old.idx_path)?;
+            fs::remove_file(&old.pack_path)?;
+            removed_pack = true;
+        }
+    }
+    // a multi-pack-index naming a deleted pack is useless, as are its bitmaps
+    if removed_pack {
+        let _ = fs::remove_file(midx_path(&objects_dir));
+        remove_stale_bitmaps(&objects_dir, None)?;
+    }
+    prune_packed(&repo_path)?;
+    Ok(())
+}
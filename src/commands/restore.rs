@@ -0,0 +1,133 @@
+use crate::{
+    commands::rm::remove_empty_parents,
+    config::Config,
+    error::ItError,
+    index::{IndexEntry, StatCheck, StatData, lock_index, read_index, worktree_matches, write_locked_index},
+    lfs::Lfs,
+    object_id::ObjectId,
+    pathspec::Pathspec,
+    refs::{head_commit, resolve_rev},
+    repository,
+    tree::{checkout_blob, flatten_tree, peel_to_tree},
+};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+pub struct RestoreArgs {
+    pub paths: Vec<String>,
+    pub staged: bool,
+    pub worktree: bool,
+    pub source: Option<String>,
+}
+
+// Without --staged or --worktree only the worktree is restored, from the
+// index. --staged restores index entries, from HEAD unless --source says
+// otherwise. Paths that are tracked but missing from the source are removed,
+// like git's default --no-overlay mode.
+pub fn restore(args: RestoreArgs) -> Result<(), ItError> {
+    let worktree = repository::worktree()?;
+    let repo_path = repository::repo_path()?;
+    if !repo_path.is_dir() {
+        return Err(ItError::NotARepository);
+    }
+    if args.paths.is_empty() {
+        return Err(ItError::Fatal("you must specify path(s) to restore".to_string()));
+    }
+    let to_worktree = args.worktree || !args.staged;
+    let pathspec = Pathspec::parse(&args.paths)?;
+    let lock = lock_index(&repo_path)?;
+    let mut entries = read_index(&repo_path)?;
+
+    // path -> (mode, sha) of the version being restored
+    let source: BTreeMap<String, (u32, ObjectId)> = match (&args.source, args.staged) {
+        (None, false) => entries.iter().map(|e| (e.path.clone(), (e.mode, e.sha))).collect(),
+        (rev, _) => {
+            let commit = match rev {
+                Some(rev) => Some(resolve_rev(&repo_path, rev)?),
+                None => head_commit(&repo_path)?,
+            };
+            match commit {
+                Some(commit) => flatten_tree(&peel_to_tree(&commit)?)?
+                    .into_iter()
+                    .map(|(path, (mode, sha))| (path, (u32::from_str_radix(&mode, 8).unwrap_or(0), sha)))
+                    .collect(),
+                // an unborn branch restores to nothing
+                None => BTreeMap::new(),
+            }
+        }
+    };
+
+    let tracked: BTreeSet<String> = entries.iter().map(|e| e.path.clone()).collect();
+    let known: BTreeSet<&String> = source.keys().chain(tracked.iter()).collect();
+    for item in pathspec.includes() {
+        if !known.iter().any(|p| item.matches(p)) {
+            return Err(ItError::Fatal(format!(
+                "pathspec '{}' did not match any file(s) known to it",
+                item.original
+            )));
+        }
+    }
+    let targets: Vec<&String> = known.into_iter().filter(|p| pathspec.matches(p)).collect();
+
+    if to_worktree {
+        let config = Config::load(&repo_path)?;
+        let check = StatCheck::load(&repo_path, &config)?;
+        let lfs = Lfs::load(&worktree, &repo_path, &config)?;
+        for path in &targets {
+            let file = worktree.join(path);
+            match source.get(*path) {
+                // leave files that already hold the wanted content untouched
+                Some((mode, sha)) if is_current(&file, &entries, path, *mode, sha, &check, &lfs)? => {}
+                Some((mode, sha)) => checkout_blob(&hex::encode(sha), *mode, &file, &lfs)?,
+                // only tracked files are ever deleted, never untracked work
+                None if tracked.contains(*path) => match fs::remove_file(&file) {
+                    Ok(()) => remove_empty_parents(&worktree, &file),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e.into()),
+                },
+                None => {}
+            }
+        }
+    }
+
+    if args.staged {
+        entries.retain(|e| !targets.contains(&&e.path));
+        for path in &targets {
+            let Some((mode, sha)) = source.get(*path) else {
+                continue;
+            };
+            // when the worktree was restored too its stat data is current,
+            // otherwise the next status rehashes the file once
+            let stat = match to_worktree {
+                true => fs::symlink_metadata(worktree.join(path))
+                    .map(|m| StatData::from_metadata(&m))
+                    .unwrap_or_default(),
+                false => StatData::default(),
+            };
+            entries.push(IndexEntry { stat, mode: *mode, sha: *sha, flags: 0, path: path.to_string() });
+        }
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        write_locked_index(lock, &repo_path, &entries)?;
+    }
+
+    Ok(())
+}
+
+fn is_current(
+    file: &Path,
+    entries: &[IndexEntry],
+    path: &str,
+    mode: u32,
+    sha: &ObjectId,
+    check: &StatCheck,
+    lfs: &Lfs,
+) -> Result<bool, ItError> {
+    let Some(entry) = entries.iter().find(|e| e.path == path && e.mode == mode && &e.sha == sha) else {
+        return Ok(false);
+    };
+    if fs::symlink_metadata(file).is_err() {
+        return Ok(false);
+    }
+    Ok(worktree_matches(file, entry, check, lfs)?)
+}
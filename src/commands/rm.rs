@@ -0,0 +1,137 @@
+use crate::{
+    commands::commit_tree::get_parent,
+    config::Config,
+    error::ItError,
+    index::{StatCheck, lock_index, read_index, worktree_matches, write_locked_index},
+    lfs::Lfs,
+    repository,
+    tree::{commit_tree_hash, flatten_tree},
+};
+use colored::Colorize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+pub fn rm(paths: Vec<String>, cached: bool, recursive: bool, force: bool) -> Result<(), ItError> {
+    let worktree = repository::worktree()?;
+    let repo_path = repository::repo_path()?;
+    if !repo_path.is_dir() {
+        return Err(ItError::NotARepository);
+    }
+
+    let lock = lock_index(&repo_path)?;
+    let mut entries = read_index(&repo_path)?;
+    let mut targets = BTreeSet::new();
+    for path_str in &paths {
+        let rel = repository::worktree_path(path_str)?;
+        let matched: Vec<&str> = entries
+            .iter()
+            .map(|e| e.path.as_str())
+            .filter(|p| rel.is_empty() || *p == rel || p.starts_with(&format!("{rel}/")))
+            .collect();
+        if matched.is_empty() {
+            return Err(ItError::Fatal(format!("pathspec '{path_str}' did not match any files")));
+        }
+        if !recursive && !matched.contains(&rel.as_str()) {
+            return Err(ItError::Fatal(format!("not removing '{path_str}' recursively without -r")));
+        }
+        targets.extend(matched.into_iter().map(str::to_string));
+    }
+
+    if !force {
+        check_removable(&worktree, &repo_path, &entries, &targets, cached)?;
+    }
+
+    entries.retain(|e| !targets.contains(&e.path));
+    write_locked_index(lock, &repo_path, &entries)?;
+
+    for path in &targets {
+        println!("{} '{}'", "rm".red(), path);
+        if cached {
+            continue;
+        }
+        let file = worktree.join(path);
+        match fs::remove_file(&file) {
+            Ok(()) => remove_empty_parents(&worktree, &file),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(())
+}
+
+// git's safety rules: without --cached the file must match both the index
+// and HEAD so nothing is lost; with --cached the staged content must at
+// least survive in either HEAD or the worktree.
+fn check_removable(
+    worktree: &Path,
+    repo_path: &Path,
+    entries: &[crate::index::IndexEntry],
+    targets: &BTreeSet<String>,
+    cached: bool,
+) -> Result<(), ItError> {
+    let head_files = match get_parent()? {
+        Some(commit) => flatten_tree(&commit_tree_hash(&commit)?)?,
+        None => BTreeMap::new(),
+    };
+    let config = Config::load(repo_path)?;
+    let check = StatCheck::load(repo_path, &config)?;
+    let lfs = Lfs::load(worktree, repo_path, &config)?;
+
+    let mut staged_differs = Vec::new();
+    let mut local_changes = Vec::new();
+    let mut both = Vec::new();
+
+    for entry in entries.iter().filter(|e| targets.contains(&e.path)) {
+        let matches_head = head_files
+            .get(&entry.path)
+            .is_some_and(|(mode, sha)| *sha == entry.sha && u32::from_str_radix(mode, 8).ok() == Some(entry.mode));
+        let file = worktree.join(&entry.path);
+        let matches_worktree = match fs::symlink_metadata(&file) {
+            Ok(_) => worktree_matches(&file, entry, &check, &lfs)?,
+            // already gone from disk, nothing to lose there
+            Err(_) => true,
+        };
+
+        if !matches_head && !matches_worktree {
+            both.push(entry.path.clone());
+        } else if !cached && !matches_head {
+            staged_differs.push(entry.path.clone());
+        } else if !cached && !matches_worktree {
+            local_changes.push(entry.path.clone());
+        }
+    }
+
+    let list = |paths: &[String]| paths.iter().map(|p| format!("    {p}")).collect::<Vec<_>>().join("\n");
+    if !both.is_empty() {
+        return Err(ItError::Fatal(format!(
+            "the following files have staged content different from both the\nfile and the HEAD:\n{}\n(use -f to force removal)",
+            list(&both)
+        )));
+    }
+    if !staged_differs.is_empty() {
+        return Err(ItError::Fatal(format!(
+            "the following files have changes staged in the index:\n{}\n(use --cached to keep the file, or -f to force removal)",
+            list(&staged_differs)
+        )));
+    }
+    if !local_changes.is_empty() {
+        return Err(ItError::Fatal(format!(
+            "the following files have local modifications:\n{}\n(use --cached to keep the file, or -f to force removal)",
+            list(&local_changes)
+        )));
+    }
+    Ok(())
+}
+
+// drops directories left empty by a removal, stopping at the worktree root
+pub(crate) fn remove_empty_parents(worktree: &Path, file: &Path) {
+    let mut dir = file.parent();
+    while let Some(d) = dir {
+        if d == worktree || fs::remove_dir(d).is_err() {
+            break;
+        }
+        dir = d.parent();
+    }
+}
@@ -0,0 +1,175 @@
+use crate::{
+    commands::hash_object::read_object,
+    diff::{print_patch, print_stat, tree_changes},
+    error::ItError,
+    refs::resolve_object,
+    repository,
+    tree::{commit_tree_hash, flatten_tree, read_tree},
+};
+use colored::Colorize;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+pub struct ShowArgs {
+    pub objects: Vec<String>,
+    pub stat: bool,
+    pub name_only: bool,
+}
+
+pub fn show(args: ShowArgs) -> Result<(), ItError> {
+    let repo_path = repository::repo_path()?;
+    if !repo_path.is_dir() {
+        return Err(ItError::NotARepository);
+    }
+    let objects = if args.objects.is_empty() { vec!["HEAD".to_string()] } else { args.objects.clone() };
+
+    for (i, spec) in objects.iter().enumerate() {
+        let hash = resolve_object(&repo_path, spec)?;
+        if i > 0 {
+            println!();
+        }
+        show_object(spec, &hash, &args)?;
+    }
+    Ok(())
+}
+
+fn show_object(spec: &str, hash: &str, args: &ShowArgs) -> Result<(), ItError> {
+    let (obj_type, body) = read_object(hash)?;
+    match obj_type.as_str() {
+        "commit" => show_commit(hash, &body, args),
+        "tag" => {
+            let target = show_tag(&body)?;
+            println!();
+            show_object(spec, &target, args)
+        }
+        "tree" => {
+            println!("{}", format!("tree {spec}").yellow());
+            println!();
+            for entry in read_tree(hash)? {
+                let suffix = if entry.is_tree() { "/" } else { "" };
+                println!("{}{}", entry.name, suffix);
+            }
+            Ok(())
+        }
+        // blobs are dumped as they are, binary or not
+        _ => {
+            let mut out = std::io::stdout().lock();
+            out.write_all(&body)?;
+            out.flush()?;
+            Ok(())
+        }
+    }
+}
+
+fn show_commit(hash: &str, body: &[u8], args: &ShowArgs) -> Result<(), ItError> {
+    let text = String::from_utf8_lossy(body);
+    let (headers, message) = text.split_once("\n\n").unwrap_or((&text, ""));
+    let parents: Vec<&str> = headers.lines().filter_map(|l| l.strip_prefix("parent ")).collect();
+
+    println!("{}", format!("commit {hash}").yellow());
+    if parents.len() > 1 {
+        let short: Vec<&str> = parents.iter().map(|p| &p[..7.min(p.len())]).collect();
+        println!("Merge: {}", short.join(" "));
+    }
+    if let Some(author) = headers.lines().find_map(|l| l.strip_prefix("author ")) {
+        print_signature("Author:", author);
+    }
+    println!();
+    for line in message.trim_end().lines() {
+        println!("    {line}");
+    }
+
+    // root commits are compared against the empty tree, merges against their first parent
+    let old = match parents.first() {
+        Some(parent) => flatten_tree(&commit_tree_hash(parent)?)?,
+        None => BTreeMap::new(),
+    };
+    let new = flatten_tree(&commit_tree_hash(hash)?)?;
+    let changes = tree_changes(&old, &new);
+    if changes.is_empty() {
+        return Ok(());
+    }
+
+    println!();
+    if args.name_only {
+        for change in &changes {
+            println!("{}", change.path);
+        }
+    } else if args.stat {
+        print_stat(&changes)?;
+    } else {
+        for change in &changes {
+            print_patch(change)?;
+        }
+    }
+    Ok(())
+}
+
+// Prints the tag header and message, returns the tagged object.
+fn show_tag(body: &[u8]) -> Result<String, ItError> {
+    let text = String::from_utf8_lossy(body);
+    let (headers, message) = text.split_once("\n\n").unwrap_or((&text, ""));
+    let field = |name: &str| headers.lines().find_map(|l| l.strip_prefix(name)).map(str::trim);
+
+    println!("{}", format!("tag {}", field("tag ").unwrap_or("")).yellow());
+    if let Some(tagger) = field("tagger ") {
+        print_signature("Tagger:", tagger);
+    }
+    println!();
+    print!("{message}");
+    field("object ")
+        .map(str::to_string)
+        .ok_or_else(|| ItError::CorruptObject("tag has no object".to_string()))
+}
+
+// "Name <email> 1700000000 +0530" as "Author: Name <email>" and a Date line
+fn print_signature(label: &str, signature: &str) {
+    let mut parts = signature.rsplitn(3, ' ');
+    let (tz, time, ident) = (parts.next(), parts.next(), parts.next());
+    match (ident, time.and_then(|t| t.parse::<i64>().ok()), tz) {
+        (Some(ident), Some(time), Some(tz)) => {
+            println!("{label} {ident}");
+            println!("Date:   {}", format_date(time, tz));
+        }
+        _ => println!("{label} {signature}"),
+    }
+}
+
+// git's default date format, in the signature's own timezone:
+// "Thu Oct 19 14:03:12 2026 +0530"
+fn format_date(timestamp: i64, tz: &str) -> String {
+    let sign = if tz.starts_with('-') { -1 } else { 1 };
+    let digits = tz.trim_start_matches(['+', '-']);
+    let offset = match (digits.get(..2), digits.get(2..4)) {
+        (Some(h), Some(m)) => sign * (h.parse::<i64>().unwrap_or(0) * 3600 + m.parse::<i64>().unwrap_or(0) * 60),
+        _ => 0,
+    };
+    let local = timestamp + offset;
+    let days = local.div_euclid(86400);
+    let secs = local.rem_euclid(86400);
+
+    // days since 1970-01-01 to a civil date (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    format!(
+        "{} {} {} {:02}:{:02}:{:02} {} {}",
+        WEEKDAYS[days.rem_euclid(7) as usize],
+        MONTHS[(month - 1) as usize],
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60,
+        year,
+        tz
+    )
+}
@@ -0,0 +1,202 @@
+use crate::{
+    commands::commit_tree::get_parent,
+    config::Config,
+    error::ItError,
+    ignore::Ignore,
+    index::{MODE_SYMLINK, StatCheck, StatData, effective_mode, hash_worktree_blob, lock_index, read_index, write_locked_index},
+    lfs::Lfs,
+    object_id::ObjectId,
+    repository,
+    tree::{commit_tree_hash, flatten_tree},
+};
+use colored::Colorize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+
+pub fn status() -> Result<(), ItError> {
+    let worktree = repository::worktree()?;
+    let repo_path = repository::repo_path()?;
+    if !repo_path.is_dir() {
+        return Err(ItError::NotARepository);
+    }
+
+    let head_content = fs::read_to_string(repo_path.join("HEAD"))?;
+    match head_content.trim().strip_prefix("ref: refs/heads/") {
+        Some(branch) => println!("On branch {}", branch.cyan().bold()),
+        None => println!("{} {}", "HEAD detached at".red(), head_content.trim().get(..8).unwrap_or("").yellow()),
+    }
+
+    let head_files = match get_parent()? {
+        Some(commit) => flatten_tree(&commit_tree_hash(&commit)?)?,
+        None => BTreeMap::new(),
+    };
+    // the refresh below is only an optimisation, so another command holding
+    // the index lock means it is skipped rather than an error
+    let lock = match lock_index(&repo_path) {
+        Ok(lock) => Some(lock),
+        Err(ItError::Locked(_)) => None,
+        Err(e) => return Err(e),
+    };
+    let mut entries = read_index(&repo_path)?;
+
+    // conflict stages left by a merge, path -> stages present
+    let mut unmerged: BTreeMap<String, Vec<u16>> = BTreeMap::new();
+    for entry in entries.iter().filter(|e| e.stage() != 0) {
+        unmerged.entry(entry.path.clone()).or_default().push(entry.stage());
+    }
+
+    let mut staged = Vec::new();
+    for entry in entries.iter().filter(|e| e.stage() == 0) {
+        match head_files.get(&entry.path) {
+            None => staged.push(("new file:".to_string(), entry.path.clone())),
+            Some((mode, sha)) => {
+                let head_mode = u32::from_str_radix(mode, 8).unwrap_or(0);
+                if let Some(change) = describe_change(head_mode, sha, entry.mode, &entry.sha, &entry.path) {
+                    staged.push(change);
+                }
+            }
+        }
+    }
+    let indexed: BTreeSet<&str> = entries.iter().map(|e| e.path.as_str()).collect();
+    for path in head_files.keys() {
+        if !indexed.contains(path.as_str()) {
+            staged.push(("deleted:".to_string(), path.clone()));
+        }
+    }
+    staged.sort_by(|a, b| a.1.cmp(&b.1));
+
+    let config = Config::load(&repo_path)?;
+    let check = StatCheck::load(&repo_path, &config)?;
+    let lfs = Lfs::load(&worktree, &repo_path, &config)?;
+
+    let mut unstaged = Vec::new();
+    // entries whose stat data was stale but whose content turned out unchanged
+    let mut refreshed = Vec::new();
+    for (i, entry) in entries.iter().enumerate().filter(|(_, e)| e.stage() == 0) {
+        let file = worktree.join(&entry.path);
+        let meta = match fs::symlink_metadata(&file) {
+            Ok(meta) if !meta.is_dir() => meta,
+            _ => {
+                unstaged.push(("deleted:".to_string(), entry.path.clone()));
+                continue;
+            }
+        };
+        if check.is_unchanged(entry, &meta) {
+            continue;
+        }
+
+        let (sha, disk_mode) = hash_worktree_blob(&file, &lfs, false)?;
+        let mode = effective_mode(disk_mode, Some(entry.mode), check.trust_filemode);
+        match describe_change(entry.mode, &entry.sha, mode, &sha, &entry.path) {
+            Some(change) => unstaged.push(change),
+            None => refreshed.push((i, StatData::from_metadata(&meta))),
+        }
+    }
+
+    let mut ignore = Ignore::load(&worktree, &repo_path, &config)?;
+    let untracked_files: Vec<String> = ignore
+        .walk("", false)?
+        .into_iter()
+        .map(|f| f.path)
+        .filter(|p| !indexed.contains(p.as_str()))
+        .collect();
+    let untracked = collapse_untracked(&untracked_files, &indexed);
+
+    // like git, opportunistically record fresh stat data so the next run can skip hashing
+    if let Some(lock) = lock.filter(|_| !refreshed.is_empty()) {
+        for (i, stat) in refreshed {
+            entries[i].stat = stat;
+        }
+        write_locked_index(lock, &repo_path, &entries)?;
+    }
+
+    if !staged.is_empty() {
+        println!("\nChanges to be committed:");
+        for (label, path) in &staged {
+            println!("        {}", format!("{label:<12}{path}").green());
+        }
+    }
+    if !unmerged.is_empty() {
+        println!("\nUnmerged paths:");
+        for (path, stages) in &unmerged {
+            let label = match stages.as_slice() {
+                [1] => "both deleted:",
+                [2] => "added by us:",
+                [3] => "added by them:",
+                [1, 2] => "deleted by them:",
+                [1, 3] => "deleted by us:",
+                [2, 3] => "both added:",
+                _ => "both modified:",
+            };
+            println!("        {}", format!("{label:<17}{path}").red());
+        }
+    }
+    if !unstaged.is_empty() {
+        println!("\nChanges not staged for commit:");
+        for (label, path) in &unstaged {
+            println!("        {}", format!("{label:<12}{path}").red());
+        }
+    }
+    if !untracked.is_empty() {
+        println!("\nUntracked files:");
+        for path in &untracked {
+            println!("        {}", path.red());
+        }
+    }
+    if staged.is_empty() && unmerged.is_empty() && unstaged.is_empty() && untracked.is_empty() {
+        println!("nothing to commit, working tree clean");
+    }
+
+    Ok(())
+}
+
+// A file turning into a symlink (or back) is a "typechange", an executable bit
+// flip is a modification that also shows the old and new mode.
+fn describe_change(
+    old_mode: u32,
+    old_sha: &ObjectId,
+    new_mode: u32,
+    new_sha: &ObjectId,
+    path: &str,
+) -> Option<(String, String)> {
+    if (old_mode == MODE_SYMLINK) != (new_mode == MODE_SYMLINK) {
+        return Some(("typechange:".to_string(), path.to_string()));
+    }
+    if old_mode != new_mode {
+        return Some(("modified:".to_string(), format!("{path} ({old_mode:o} -> {new_mode:o})")));
+    }
+    if old_sha != new_sha {
+        return Some(("modified:".to_string(), path.to_string()));
+    }
+    None
+}
+
+// Like git, an untracked directory holding no tracked files is shown once
+// as "dir/" instead of listing everything inside it.
+pub(crate) fn collapse_untracked(untracked: &[String], tracked: &BTreeSet<&str>) -> Vec<String> {
+    let mut tracked_dirs = BTreeSet::new();
+    for path in tracked {
+        let mut dir = *path;
+        while let Some((parent, _)) = dir.rsplit_once('/') {
+            tracked_dirs.insert(parent);
+            dir = parent;
+        }
+    }
+
+    let mut out = BTreeSet::new();
+    for path in untracked {
+        let mut shown = path.clone();
+        let mut end = 0;
+        while let Some(offset) = path[end..].find('/') {
+            end += offset;
+            let dir = &path[..end];
+            if !tracked_dirs.contains(dir) {
+                shown = format!("{dir}/");
+                break;
+            }
+            end += 1;
+        }
+        out.insert(shown);
+    }
+    out.into_iter().collect()
+}
@@ -0,0 +1,43 @@
+use crate::{
+    error::ItError,
+    lockfile::write_atomic,
+    refs::{is_valid_ref_name, read_symbolic_ref},
+    repository,
+};
+
+// `it symbolic-ref <name>` prints the ref <name> points at (--short drops
+// "refs/heads/"); `it symbolic-ref <name> <ref>` points it somewhere else.
+pub fn symbolic_ref(name: String, target: Option<String>, short: bool, quiet: bool) -> Result<(), ItError> {
+    let repo_path = repository::repo_path()?;
+    if !repo_path.is_dir() {
+        return Err(ItError::NotARepository);
+    }
+    if !is_valid_ref_name(&name) {
+        return Err(ItError::Fatal(format!("invalid ref name '{name}'")));
+    }
+
+    let Some(target) = target else {
+        return match read_symbolic_ref(&repo_path, &name)? {
+            Some(current) if short => {
+                let short_name = ["refs/heads/", "refs/tags/", "refs/remotes/", "refs/"]
+                    .iter()
+                    .find_map(|p| current.strip_prefix(p))
+                    .unwrap_or(&current);
+                println!("{short_name}");
+                Ok(())
+            }
+            Some(current) => {
+                println!("{current}");
+                Ok(())
+            }
+            // -q only silences the message, the failure stays
+            None if quiet => std::process::exit(1),
+            None => Err(ItError::Fatal(format!("ref {name} is not a symbolic ref"))),
+        };
+    };
+
+    if target == "HEAD" || !target.starts_with("refs/") || !is_valid_ref_name(&target) {
+        return Err(ItError::Fatal(format!("refusing to point {name} outside of refs/: {target}")));
+    }
+    write_atomic(&repo_path.join(&name), format!("ref: {target}\n").as_bytes())
+}
@@ -0,0 +1,44 @@
+use crate::{
+    error::ItError,
+    object_id::ObjectFormat,
+    refs::{delete_ref, is_valid_ref_name, read_symbolic_ref, resolve_rev, update_ref as write_ref},
+    repository,
+};
+use std::path::Path;
+
+// `it update-ref <ref> <new> [<old>]` and `it update-ref -d <ref> [<old>]`.
+// With <old> the update only happens if the ref still points there; an old
+// value of all zeros (or "") means the ref must not exist yet. A symbolic
+// ref such as HEAD is followed to the branch it names.
+pub fn update_ref(name: String, new: Option<String>, old: Option<String>, delete: bool) -> Result<(), ItError> {
+    let repo_path = repository::repo_path()?;
+    if !repo_path.is_dir() {
+        return Err(ItError::NotARepository);
+    }
+    if !is_valid_ref_name(&name) {
+        return Err(ItError::Fatal(format!("invalid ref name '{name}'")));
+    }
+    let target = read_symbolic_ref(&repo_path, &name)?.unwrap_or(name);
+
+    // with -d the only value given is the expected old one
+    let (new, old) = if delete { (None, new) } else { (new, old) };
+    let expected = match old.as_deref() {
+        None => None,
+        Some("") => Some(None),
+        Some(old) if old == ObjectFormat::current().null_id().to_hex() => Some(None),
+        Some(old) => Some(Some(resolve_rev(&repo_path, old)?)),
+    };
+
+    if delete {
+        return match expected {
+            Some(None) => Err(ItError::Fatal(format!("cannot delete '{target}': expected it not to exist"))),
+            Some(Some(old)) => delete_ref(&repo_path, &target, Some(&old)).map(|_| ()),
+            None => delete_ref(&repo_path, &target, None).map(|_| ()),
+        };
+    }
+
+    let new = new.ok_or_else(|| ItError::Fatal("update-ref needs a new value".to_string()))?;
+    let new_hash = resolve_rev(&repo_path, &new)?;
+    let expected = expected.as_ref().map(|e| e.as_deref());
+    write_ref(Path::new(&repo_path), &target, &new_hash, expected)
+}
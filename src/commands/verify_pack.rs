@@ -0,0 +1,90 @@
+use crate::{
+    error::ItError,
+    pack::{PackIndex, parse_pack, resolve_entries, type_name},
+};
+use sha1::{Digest, Sha1};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+// Checks packs against their indexes: both trailing checksums, that the
+// index names the pack it sits beside, and that every entry resolves to the
+// hash, offset and CRC the index records. With -v every object is listed as
+//
+//      <sha> <type> <size> <size in pack> <offset> [<depth> <base sha>]
+//
+// followed by a histogram of delta chain lengths, like git.
+pub fn verify_pack(paths: Vec<PathBuf>, verbose: bool) -> Result<(), ItError> {
+    let mut failed = false;
+    for path in paths {
+        let errors = match verify_one(&path.with_extension("idx"), verbose) {
+            Ok(errors) => errors,
+            Err(e) => vec![e.to_string()],
+        };
+        for error in &errors {
+            eprintln!("error: {}: {error}", path.display());
+        }
+        if verbose {
+            let status = if errors.is_empty() { "ok" } else { "bad" };
+            println!("{}: {status}", path.with_extension("pack").display());
+        }
+        failed |= !errors.is_empty();
+    }
+    if failed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn verify_one(idx_path: &std::path::Path, verbose: bool) -> Result<Vec<String>, ItError> {
+    let mut errors = Vec::new();
+    let index = PackIndex::load(idx_path)?;
+    let idx_data = fs::read(idx_path)?;
+    let (body, trailer) = idx_data.split_at(idx_data.len() - 20);
+    if Sha1::digest(body)[..] != *trailer {
+        errors.push("index checksum mismatch".to_string());
+    }
+
+    let (entries, checksum) = parse_pack(&fs::read(&index.pack_path)?)?;
+    if checksum != index.pack_checksum {
+        errors.push("pack checksum does not match its index".to_string());
+    }
+    if entries.len() != index.hashes.len() {
+        errors.push(format!("index has {} objects, pack has {}", index.hashes.len(), entries.len()));
+    }
+    let resolved = resolve_entries(&entries)?;
+
+    let mut chains: BTreeMap<usize, usize> = BTreeMap::new();
+    for (entry, object) in entries.iter().zip(&resolved) {
+        let hash = hex::encode(object.sha);
+        match index.find(&object.sha) {
+            None => errors.push(format!("{hash} is missing from the index")),
+            Some(i) if index.offsets[i] != entry.offset => {
+                errors.push(format!("{hash} is at offset {}, the index says {}", entry.offset, index.offsets[i]))
+            }
+            Some(i) if index.crcs[i] != entry.crc => errors.push(format!("CRC mismatch for {hash}")),
+            Some(_) => {}
+        }
+        *chains.entry(object.depth).or_default() += 1;
+
+        if verbose {
+            let kind = type_name(object.kind);
+            print!("{hash} {kind:<6} {} {} {}", entry.data.len(), entry.packed_len, entry.offset);
+            match object.base {
+                Some(base) => println!(" {} {}", object.depth, hex::encode(resolved[base].sha)),
+                None => println!(),
+            }
+        }
+    }
+
+    if verbose {
+        for (depth, count) in chains {
+            let plural = if count == 1 { "" } else { "s" };
+            match depth {
+                0 => println!("non delta: {count} object{plural}"),
+                d => println!("chain length = {d}: {count} object{plural}"),
+            }
+        }
+    }
+    Ok(errors)
+}
@@ -1,25 +1,36 @@
 use crate::commands::hash_object::{
-    build_object, compress_data, compute_hash, hex_to_sha_bytes, store_object,
+    build_object, compress_data, compute_hash, store_object,
 };
 use crate::error::ItError;
+use crate::repository;
 use crate::index::{IndexEntry, read_index};
 use std::collections::BTreeMap;
 use std::path::Path;
 use colored::*;
 pub fn write_tree() -> Result<String, ItError> {
-    let repo_path = Path::new(".it");
-    let entries = read_index(repo_path)?;
+    let repo_path = repository::repo_path()?;
+    let entries = read_index(&repo_path)?;
 
     if entries.is_empty() {
         return Err(ItError::NothingToCommit);
     }
 
-    let hash = build_tree(&entries, "")?;
+    let hash = write_index_tree(&repo_path)?;
     println!("{}", hash.blue());
 
     Ok(hash)
 }
 
+// Plumbing side of write_tree: writes the index as trees without printing.
+// An empty index gives the empty tree, an unmerged one is refused.
+pub fn write_index_tree(repo_path: &Path) -> Result<String, ItError> {
+    let entries = read_index(repo_path)?;
+    if let Some(entry) = entries.iter().find(|e| e.stage() != 0) {
+        return Err(ItError::Fatal(format!("{}: unmerged (stage {})", entry.path, entry.stage())));
+    }
+    build_tree(&entries, "")
+}
+
 //      prefix="" and path="src/main.rs" -> component="src", rest="main.rs"
 //      prefix="" and path="README.md"   -> component="README.md", rest=""
 fn build_tree(entries: &[IndexEntry], prefix: &str) -> Result<String, ItError> {
@@ -36,25 +47,24 @@ fn build_tree(entries: &[IndexEntry], prefix: &str) -> Result<String, ItError> {
             }
         };
 
-        let name = rel_path.split('/').next().unwrap().to_string();
-        entries_by_name.entry(name).or_default().push(entry);
+        // git orders a subtree as if its name ended in '/', so keying
+        // directories as "name/" sorts "a.txt" before the directory "a"
+        let key = match rel_path.split_once('/') {
+            Some((dir, _)) => format!("{dir}/"),
+            None => rel_path.to_string(),
+        };
+        entries_by_name.entry(key).or_default().push(entry);
     }
 
-    for (name, group) in entries_by_name {
-        let has_subdirs = group.iter().any(|e| {
-            let rel = if prefix.is_empty() {
-                &e.path
-            } else {
-                e.path.strip_prefix(&format!("{}/", prefix)).unwrap()
-            };
-            rel.contains('/')
-        });
+    for (key, group) in entries_by_name {
+        let has_subdirs = key.ends_with('/');
+        let name = key.trim_end_matches('/').to_string();
 
         if !has_subdirs {
             let entry = group[0];
-            let header = format!("100644 {}\0", name);
+            let header = format!("{:o} {}\0", entry.mode, name);
             tree_content.extend_from_slice(header.as_bytes());
-            tree_content.extend_from_slice(&entry.sha);
+            tree_content.extend_from_slice(entry.sha.as_bytes());
         } else {
             let sub_prefix = if prefix.is_empty() {
                 name.clone()
@@ -62,9 +72,9 @@ fn build_tree(entries: &[IndexEntry], prefix: &str) -> Result<String, ItError> {
                 format!("{}/{}", prefix, name)
             };
             let sub_hash = build_tree(entries, &sub_prefix)?;
-            let header = format!("040000 {}\0", name);
+            let header = format!("40000 {}\0", name);
             tree_content.extend_from_slice(header.as_bytes());
-            tree_content.extend_from_slice(&hex_to_sha_bytes(&sub_hash));
+            tree_content.extend_from_slice(&hex::decode(&sub_hash).expect("tree ids are hex"));
         }
     }
 
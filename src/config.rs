@@ -0,0 +1,747 @@
+use crate::error::ItError;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Config files use git's INI dialect:
+//
+//      [core]
+//          bare = false
+//      [remote "origin"]
+//          url = ../upstream
+//      [include]
+//          path = ~/.itconfig.local
+//
+// Section and key names are case-insensitive, subsection names are not.
+// Keys are exposed in their canonical "section.subsection.key" form.
+
+#[derive(Debug, Clone)]
+pub struct ConfigEntry {
+    pub key: String,
+    // a bare `key` line with no `=` has no value and reads as boolean true
+    pub value: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Config {
+    pub entries: Vec<ConfigEntry>,
+}
+
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+impl Config {
+    // Layers are read lowest precedence first so that a later entry for the
+    // same key wins: ~/.itconfig, then .it/config, then IT_CONFIG_* variables.
+    pub fn load(repo_path: &Path) -> Result<Config, ItError> {
+        let mut config = Config::default();
+
+        if let Some(global) = global_config_path() {
+            config.read_file(&global, 0)?;
+        }
+        config.read_file(&repo_path.join("config"), 0)?;
+        config.read_env()?;
+
+        if let Some(version) = config.get_int("core.repositoryformatversion")?.filter(|&v| v > 1) {
+            return Err(ItError::InvalidConfig(format!(
+                "expected repository format version <= 1, found {version}"
+            )));
+        }
+
+        Ok(config)
+    }
+
+    pub fn load_file(path: &Path) -> Result<Config, ItError> {
+        let mut config = Config::default();
+        config.read_file(path, 0)?;
+        Ok(config)
+    }
+
+    fn read_file(&mut self, path: &Path, depth: usize) -> Result<(), ItError> {
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(ItError::InvalidConfig(format!(
+                "exceeded maximum include depth while reading {}",
+                path.display()
+            )));
+        }
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        for line in parse(&text).map_err(|e| e.in_file(path))? {
+            if let Line::Entry { key, value, .. } = line {
+                if key == "include.path" {
+                    if let Some(include) = &value {
+                        let include_path = resolve_include(path, include);
+                        self.read_file(&include_path, depth + 1)?;
+                    }
+                    continue;
+                }
+                self.entries.push(ConfigEntry { key, value });
+            }
+        }
+        Ok(())
+    }
+
+    // IT_CONFIG_COUNT=<n> with IT_CONFIG_KEY_<i> / IT_CONFIG_VALUE_<i> pairs,
+    // the same scheme git uses for GIT_CONFIG_COUNT
+    fn read_env(&mut self) -> Result<(), ItError> {
+        let count = match std::env::var("IT_CONFIG_COUNT") {
+            Ok(count) => count
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| ItError::InvalidConfig(format!("bogus IT_CONFIG_COUNT '{count}'")))?,
+            Err(_) => return Ok(()),
+        };
+
+        for i in 0..count {
+            let key = std::env::var(format!("IT_CONFIG_KEY_{i}")).map_err(|_| {
+                ItError::InvalidConfig(format!("missing config key IT_CONFIG_KEY_{i}"))
+            })?;
+            let value = std::env::var(format!("IT_CONFIG_VALUE_{i}")).map_err(|_| {
+                ItError::InvalidConfig(format!("missing config value IT_CONFIG_VALUE_{i}"))
+            })?;
+            self.entries.push(ConfigEntry {
+                key: canonical_key(&key)?,
+                value: Some(value),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        let key = canonical_key(key).ok()?;
+        self.entries
+            .iter()
+            .rev()
+            .find(|e| e.key == key)
+            .map(|e| e.value.as_deref().unwrap_or("true"))
+    }
+
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        let Ok(key) = canonical_key(key) else {
+            return vec![];
+        };
+        self.entries
+            .iter()
+            .filter(|e| e.key == key)
+            .map(|e| e.value.as_deref().unwrap_or("true"))
+            .collect()
+    }
+
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>, ItError> {
+        match self.get(key) {
+            Some(value) => parse_bool(value)
+                .map(Some)
+                .ok_or_else(|| ItError::InvalidConfig(format!("bad boolean config value '{value}' for '{key}'"))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_int(&self, key: &str) -> Result<Option<i64>, ItError> {
+        match self.get(key) {
+            Some(value) => parse_int(value)
+                .map(Some)
+                .ok_or_else(|| ItError::InvalidConfig(format!("bad numeric config value '{value}' for '{key}'"))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_path(&self, key: &str) -> Option<PathBuf> {
+        self.get(key).map(expand_home)
+    }
+}
+
+pub fn global_config_path() -> Option<PathBuf> {
+    if let Ok(path) = std::env::var("IT_CONFIG_GLOBAL") {
+        return Some(PathBuf::from(path));
+    }
+    home_dir().map(|home| home.join(".itconfig"))
+}
+
+fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .filter(|h| !h.is_empty())
+        .map(PathBuf::from)
+}
+
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn resolve_include(including_file: &Path, include: &str) -> PathBuf {
+    let path = expand_home(include);
+    if path.is_absolute() {
+        return path;
+    }
+    including_file
+        .parent()
+        .map(|dir| dir.join(&path))
+        .unwrap_or(path)
+}
+
+pub fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" | "" => Some(false),
+        _ => None,
+    }
+}
+
+// accepts git's k/m/g unit suffixes
+pub fn parse_int(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (digits, factor) = match value.chars().last()?.to_ascii_lowercase() {
+        'k' => (&value[..value.len() - 1], 1024),
+        'm' => (&value[..value.len() - 1], 1024 * 1024),
+        'g' => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+        _ => (value, 1),
+    };
+    digits.parse::<i64>().ok()?.checked_mul(factor)
+}
+
+// "Section.Sub.Section.Key" -> "section.Sub.Section.key"
+pub fn canonical_key(key: &str) -> Result<String, ItError> {
+    let (section, rest) = key
+        .split_once('.')
+        .ok_or_else(|| ItError::InvalidConfig(format!("key does not contain a section: {key}")))?;
+    let (subsection, name) = match rest.rsplit_once('.') {
+        Some((sub, name)) => (Some(sub), name),
+        None => (None, rest),
+    };
+
+    let valid_section = !section.is_empty()
+        && section.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.');
+    let valid_name = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    if !valid_section || !valid_name {
+        return Err(ItError::InvalidConfig(format!("invalid key: {key}")));
+    }
+
+    Ok(match subsection {
+        Some(sub) => format!("{}.{}.{}", section.to_ascii_lowercase(), sub, name.to_ascii_lowercase()),
+        None => format!("{}.{}", section.to_ascii_lowercase(), name.to_ascii_lowercase()),
+    })
+}
+
+#[derive(Debug)]
+struct ParseError {
+    line: usize,
+    reason: String,
+}
+
+impl ParseError {
+    fn in_file(self, path: &Path) -> ItError {
+        ItError::InvalidConfig(format!(
+            "bad config line {} in file {}: {}",
+            self.line,
+            path.display(),
+            self.reason
+        ))
+    }
+}
+
+#[derive(Debug)]
+enum Line {
+    Section {
+        section: String,
+        subsection: Option<String>,
+        // 0-based physical line of the header
+        line: usize,
+    },
+    Entry {
+        key: String,
+        value: Option<String>,
+        // 0-based [first, last] physical lines, values may continue with `\`
+        first: usize,
+        last: usize,
+    },
+}
+
+fn parse(text: &str) -> Result<Vec<Line>, ParseError> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut out = Vec::new();
+    let mut section: Option<(String, Option<String>)> = None;
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line_no = i;
+        let trimmed = lines[i].trim();
+        i += 1;
+
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with(';') {
+            continue;
+        }
+
+        if trimmed.starts_with('[') {
+            let (sec, sub) = parse_section_header(trimmed).map_err(|reason| ParseError {
+                line: line_no + 1,
+                reason,
+            })?;
+            out.push(Line::Section {
+                section: sec.clone(),
+                subsection: sub.clone(),
+                line: line_no,
+            });
+            section = Some((sec, sub));
+            continue;
+        }
+
+        let Some((sec, sub)) = &section else {
+            return Err(ParseError {
+                line: line_no + 1,
+                reason: "key outside of any section".to_string(),
+            });
+        };
+
+        let name_len = trimmed
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+            .unwrap_or(trimmed.len());
+        let name = &trimmed[..name_len];
+        if name.is_empty() || !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return Err(ParseError {
+                line: line_no + 1,
+                reason: format!("invalid key name '{trimmed}'"),
+            });
+        }
+
+        let rest = trimmed[name_len..].trim_start();
+        let value = if rest.is_empty() || rest.starts_with('#') || rest.starts_with(';') {
+            None
+        } else if let Some(raw) = rest.strip_prefix('=') {
+            let mut raw = raw.to_string();
+            // a trailing unescaped backslash joins the next physical line
+            while ends_with_continuation(&raw) && i < lines.len() {
+                raw.pop();
+                raw.push_str(lines[i]);
+                i += 1;
+            }
+            Some(parse_value(&raw).map_err(|reason| ParseError {
+                line: line_no + 1,
+                reason,
+            })?)
+        } else {
+            return Err(ParseError {
+                line: line_no + 1,
+                reason: format!("expected '=' after '{name}'"),
+            });
+        };
+
+        let key = match sub {
+            Some(sub) => format!("{sec}.{sub}.{}", name.to_ascii_lowercase()),
+            None => format!("{sec}.{}", name.to_ascii_lowercase()),
+        };
+        out.push(Line::Entry {
+            key,
+            value,
+            first: line_no,
+            last: i - 1,
+        });
+    }
+
+    Ok(out)
+}
+
+fn ends_with_continuation(raw: &str) -> bool {
+    let trailing = raw.chars().rev().take_while(|&c| c == '\\').count();
+    trailing % 2 == 1
+}
+
+// [section], [section "subsection"] or the deprecated [section.subsection]
+fn parse_section_header(line: &str) -> Result<(String, Option<String>), String> {
+    let close = line.rfind(']').ok_or("missing ']' in section header")?;
+    let trailing = line[close + 1..].trim();
+    if !(trailing.is_empty() || trailing.starts_with('#') || trailing.starts_with(';')) {
+        return Err(format!("unexpected text after section header '{line}'"));
+    }
+    let inner = &line[1..close];
+
+    if let Some(quote) = inner.find('"') {
+        let name = inner[..quote].trim();
+        let quoted = inner[quote..].trim_end();
+        if !quoted.ends_with('"') || quoted.len() < 2 {
+            return Err(format!("unterminated subsection name in '{line}'"));
+        }
+        let mut sub = String::new();
+        let mut chars = quoted[1..quoted.len() - 1].chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => sub.extend(chars.next()),
+                '"' => return Err(format!("unescaped quote in subsection name '{line}'")),
+                c => sub.push(c),
+            }
+        }
+        check_section_name(name)?;
+        return Ok((name.to_ascii_lowercase(), Some(sub)));
+    }
+
+    let inner = inner.trim();
+    match inner.split_once('.') {
+        Some((name, sub)) => {
+            check_section_name(name)?;
+            Ok((name.to_ascii_lowercase(), Some(sub.to_ascii_lowercase())))
+        }
+        None => {
+            check_section_name(inner)?;
+            Ok((inner.to_ascii_lowercase(), None))
+        }
+    }
+}
+
+fn check_section_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.') {
+        return Err(format!("invalid section name '{name}'"));
+    }
+    Ok(())
+}
+
+// Strips surrounding whitespace and comments, honours "quoted strings" and
+// the \n \t \b \" \\ escapes. Whitespace inside quotes is kept verbatim.
+fn parse_value(raw: &str) -> Result<String, String> {
+    let mut value = String::new();
+    let mut in_quotes = false;
+    // length of `value` up to the last character that must survive trimming
+    let mut keep = 0;
+    let mut chars = raw.trim_start().chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                keep = value.len();
+            }
+            '\\' => {
+                let escaped = match chars.next() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('b') => '\u{8}',
+                    Some('"') => '"',
+                    Some('\\') => '\\',
+                    Some(other) => return Err(format!("invalid escape sequence '\\{other}'")),
+                    None => return Err("trailing backslash".to_string()),
+                };
+                value.push(escaped);
+                keep = value.len();
+            }
+            '#' | ';' if !in_quotes => break,
+            c => {
+                value.push(c);
+                if in_quotes || !c.is_whitespace() {
+                    keep = value.len();
+                }
+            }
+        }
+    }
+
+    if in_quotes {
+        return Err("unterminated quoted value".to_string());
+    }
+    value.truncate(keep);
+    Ok(value)
+}
+
+fn quote_value(value: &str) -> String {
+    let needs_quotes = value.starts_with(char::is_whitespace)
+        || value.ends_with(char::is_whitespace)
+        || value.contains(['#', ';']);
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    if needs_quotes {
+        format!("\"{escaped}\"")
+    } else {
+        escaped
+    }
+}
+
+fn split_key(key: &str) -> (&str, Option<&str>, &str) {
+    let (section, rest) = key.split_once('.').unwrap_or((key, ""));
+    match rest.rsplit_once('.') {
+        Some((sub, name)) => (section, Some(sub), name),
+        None => (section, None, rest),
+    }
+}
+
+fn section_header(section: &str, subsection: Option<&str>) -> String {
+    match subsection {
+        Some(sub) => format!(
+            "[{section} \"{}\"]",
+            sub.replace('\\', "\\\\").replace('"', "\\\"")
+        ),
+        None => format!("[{section}]"),
+    }
+}
+
+// Rewrites a single config file in place, touching only the lines that hold
+// `key` so comments and formatting elsewhere survive.
+pub fn set_value(path: &Path, key: &str, value: &str) -> Result<(), ItError> {
+    let key = canonical_key(key)?;
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let parsed = parse(&text).map_err(|e| e.in_file(path))?;
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+
+    let (section, subsection, name) = split_key(&key);
+    let new_line = format!("\t{name} = {}", quote_value(value));
+
+    // replace the last existing assignment, git's behaviour for a single-valued set
+    let existing = parsed.iter().rev().find_map(|l| match l {
+        Line::Entry { key: k, first, last, .. } if *k == key => Some((*first, *last)),
+        _ => None,
+    });
+    if let Some((first, last)) = existing {
+        lines.splice(first..=last, [new_line]);
+    } else if let Some(insert_at) = section_end(&parsed, section, subsection) {
+        lines.insert(insert_at, new_line);
+    } else {
+        lines.push(section_header(section, subsection));
+        lines.push(new_line);
+    }
+
+    write_lines(path, &lines)
+}
+
+// Returns the number of entries removed.
+pub fn unset_value(path: &Path, key: &str) -> Result<usize, ItError> {
+    let key = canonical_key(key)?;
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let parsed = parse(&text).map_err(|e| e.in_file(path))?;
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+
+    let mut removed = 0;
+    for line in parsed.iter().rev() {
+        match line {
+            Line::Entry { key: k, first, last, .. } if *k == key => {
+                lines.drain(*first..=*last);
+                removed += 1;
+            }
+            _ => {}
+        }
+    }
+
+    if removed > 0 {
+        write_lines(path, &lines)?;
+    }
+    Ok(removed)
+}
+
+// Drops every `[section "subsection"]` block, header and all, along with the
+// comments inside it. Returns false when there was no such section.
+pub fn remove_section(path: &Path, section: &str, subsection: Option<&str>) -> Result<bool, ItError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+    let parsed = parse(&text).map_err(|e| e.in_file(path))?;
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+
+    // each block runs from its header to the next one
+    let section = section.to_ascii_lowercase();
+    let headers: Vec<(usize, bool)> = parsed
+        .iter()
+        .filter_map(|l| match l {
+            Line::Section { section: s, subsection: sub, line } => {
+                Some((*line, *s == section && sub.as_deref() == subsection))
+            }
+            Line::Entry { .. } => None,
+        })
+        .collect();
+    let mut found = false;
+    for (i, &(start, matches)) in headers.iter().enumerate().rev() {
+        if matches {
+            let end = headers.get(i + 1).map_or(lines.len(), |&(next, _)| next);
+            lines.drain(start..end);
+            found = true;
+        }
+    }
+
+    if found {
+        write_lines(path, &lines)?;
+    }
+    Ok(found)
+}
+
+fn section_end(parsed: &[Line], section: &str, subsection: Option<&str>) -> Option<usize> {
+    let (start, header_line) = parsed.iter().enumerate().rev().find_map(|(i, l)| match l {
+        Line::Section { section: s, subsection: sub, line } if s == section && sub.as_deref() == subsection => {
+            Some((i, *line))
+        }
+        _ => None,
+    })?;
+    let last_entry = parsed[start + 1..]
+        .iter()
+        .map_while(|l| match l {
+            Line::Entry { last, .. } => Some(*last),
+            Line::Section { .. } => None,
+        })
+        .last();
+    Some(last_entry.unwrap_or(header_line) + 1)
+}
+
+fn write_lines(path: &Path, lines: &[String]) -> Result<(), ItError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut out = lines.join("\n");
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    fs::write(path, out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(text: &str) -> Vec<(String, Option<String>)> {
+        parse(text)
+            .unwrap()
+            .into_iter()
+            .filter_map(|l| match l {
+                Line::Entry { key, value, .. } => Some((key, value)),
+                Line::Section { .. } => None,
+            })
+            .collect()
+    }
+
+    // a scratch file per test, since tests run in parallel
+    fn scratch(name: &str, text: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("it-config-test-{}-{name}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config");
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn finish(path: &Path) -> String {
+        let text = fs::read_to_string(path).unwrap();
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        text
+    }
+
+    #[test]
+    fn parse_values() {
+        let text = "# leading comment\n\
+            [Core]\n\
+            \tBare = false ; trailing comment\n\
+            \tfilemode\n\
+            [remote \"Origin\"]\n\
+            \turl = \"  spaced # not a comment \"\n\
+            \tfetch = a\\tb\\\\c\\\"d\n\
+            [branch.Main]\n\
+            \tmerge = one \\\n\
+            two\n";
+        let some = |v: &str| Some(v.to_string());
+        assert_eq!(
+            entries(text),
+            vec![
+                ("core.bare".to_string(), some("false")),
+                ("core.filemode".to_string(), None),
+                ("remote.Origin.url".to_string(), some("  spaced # not a comment ")),
+                ("remote.Origin.fetch".to_string(), some("a\tb\\c\"d")),
+                // the deprecated [section.subsection] form lowercases the subsection
+                ("branch.main.merge".to_string(), some("one two")),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        for (text, line) in [
+            ("key = value\n", 1),
+            ("[core]\n\tbare = \"open\n", 2),
+            ("[core]\n\tbare = \\q\n", 2),
+            ("[core\n", 1),
+            ("[core] junk\n", 1),
+            ("[core]\n\t1key = x\n", 2),
+            ("[core]\n\tkey value\n", 2),
+        ] {
+            assert_eq!(parse(text).unwrap_err().line, line, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn canonical_keys() {
+        assert_eq!(canonical_key("Core.Bare").unwrap(), "core.bare");
+        assert_eq!(canonical_key("Remote.Up.Stream.URL").unwrap(), "remote.Up.Stream.url");
+        for bad in ["nosection", ".key", "core.1key", "core.", "co re.key"] {
+            assert!(canonical_key(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn parse_bools_and_ints() {
+        assert_eq!(parse_bool("Yes"), Some(true));
+        assert_eq!(parse_bool(""), Some(false));
+        assert_eq!(parse_bool("maybe"), None);
+        assert_eq!(parse_int(" 2k"), Some(2048));
+        assert_eq!(parse_int("3M"), Some(3 * 1024 * 1024));
+        assert_eq!(parse_int("k"), None);
+        assert_eq!(parse_int("9999999999g"), None);
+    }
+
+    #[test]
+    fn set_value_keeps_the_rest_of_the_file() {
+        let path = scratch(
+            "set",
+            "# mine\n[core]\n\tbare = false\n\tbare = true # last wins\n[user]\n\tname = a\n",
+        );
+        set_value(&path, "Core.Bare", "false").unwrap();
+        set_value(&path, "core.editor", " vi; # odd").unwrap();
+        set_value(&path, "remote.Up \"q\".url", "x").unwrap();
+        let config = Config::load_file(&path).unwrap();
+        assert_eq!(config.get("core.editor"), Some(" vi; # odd"));
+        assert_eq!(config.get("remote.Up \"q\".url"), Some("x"));
+        assert_eq!(
+            finish(&path),
+            "# mine\n[core]\n\tbare = false\n\tbare = false\n\teditor = \" vi; # odd\"\n[user]\n\tname = a\n\
+             [remote \"Up \\\"q\\\"\"]\n\turl = x\n"
+        );
+    }
+
+    #[test]
+    fn unset_value_drops_every_assignment() {
+        let path = scratch("unset", "[core]\n\tbare = a \\\ncontinued\n\tfilemode = true\n\tBARE = b\n");
+        assert_eq!(unset_value(&path, "core.bare").unwrap(), 2);
+        assert_eq!(unset_value(&path, "core.bare").unwrap(), 0);
+        assert_eq!(unset_value(&path, "core.nothing").unwrap(), 0);
+        assert_eq!(finish(&path), "[core]\n\tfilemode = true\n");
+    }
+
+    #[test]
+    fn remove_section_matches_subsections_exactly() {
+        let path = scratch(
+            "remove",
+            "[remote \"a\"]\n\turl = 1\n# part of the block above\n[remote \"A\"]\n\turl = 2\n[Remote \"a\"]\n\tfetch = 3\n[core]\n\tbare = false\n",
+        );
+        assert!(remove_section(&path, "remote", Some("a")).unwrap());
+        assert!(!remove_section(&path, "remote", Some("b")).unwrap());
+        assert_eq!(finish(&path), "[remote \"A\"]\n\turl = 2\n[core]\n\tbare = false\n");
+    }
+
+    #[test]
+    fn includes_are_read_in_place() {
+        let path = scratch("include", "[user]\n\tname = first\n[include]\n\tpath = extra\n[core]\n\tbare = true\n");
+        fs::write(path.with_file_name("extra"), "[user]\n\tname = second\n\temail = e\n").unwrap();
+        let config = Config::load_file(&path).unwrap();
+        assert_eq!(config.get_all("user.name"), vec!["first", "second"]);
+        assert_eq!(config.get("user.email"), Some("e"));
+        assert_eq!(config.get_bool("core.bare").unwrap(), Some(true));
+        assert!(config.get_int("user.name").is_err());
+        finish(&path);
+    }
+}
@@ -0,0 +1,563 @@
+use crate::commands::hash_object::read_object;
+use crate::error::ItError;
+use crate::object_id::ObjectId;
+use colored::Colorize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+// Line diffs between blobs, printed as git-style unified patches or a
+// --stat summary. The line matching is Myers' O(ND) algorithm.
+
+const CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffOp {
+    // old line index, new line index
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+// One path that differs between two trees. `old` is None for an added file,
+// `new` None for a deleted one.
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub path: String,
+    pub old: Option<(u32, ObjectId)>,
+    pub new: Option<(u32, ObjectId)>,
+}
+
+// Compares two flattened trees (see tree::flatten_tree), sorted by path.
+pub fn tree_changes(
+    old: &BTreeMap<String, (String, ObjectId)>,
+    new: &BTreeMap<String, (String, ObjectId)>,
+) -> Vec<Change> {
+    let mode = |(m, sha): &(String, ObjectId)| (u32::from_str_radix(m, 8).unwrap_or(0), *sha);
+    let paths: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let (o, n) = (old.get(path).map(mode), new.get(path).map(mode));
+            (o != n).then(|| Change { path: path.clone(), old: o, new: n })
+        })
+        .collect()
+}
+
+// Lines including their trailing newline, so a missing newline at the end of
+// a file shows up as a change.
+pub fn split_lines(data: &[u8]) -> Vec<&[u8]> {
+    data.split_inclusive(|&b| b == b'\n').collect()
+}
+
+// Same heuristic as git: a NUL byte in the first 8000 bytes means binary.
+pub fn is_binary(data: &[u8]) -> bool {
+    data.iter().take(8000).any(|&b| b == 0)
+}
+
+// Myers' linear space refinement: find the middle snake of a shortest edit
+// script, then diff what comes before and after it the same way. Only two V
+// arrays are held, reused at every level, rather than one per edit. Lines are
+// numbered first so that comparing two is a single integer comparison.
+pub fn diff_lines(old: &[&[u8]], new: &[&[u8]]) -> Vec<DiffOp> {
+    let mut ids: HashMap<&[u8], usize> = HashMap::new();
+    let numbered: Vec<usize> = old
+        .iter()
+        .chain(new)
+        .map(|&line| {
+            let next = ids.len();
+            *ids.entry(line).or_insert(next)
+        })
+        .collect();
+    let (old, new) = numbered.split_at(old.len());
+
+    // furthest diagonal either search can look at, plus one on each side
+    let max = (old.len() + new.len()).div_ceil(2) + 1;
+    let mut forward = vec![0isize; 2 * max + 1];
+    let mut backward = vec![0isize; 2 * max + 1];
+    let mut ops = Vec::new();
+    diff_range(old, new, (0, 0), &mut forward, &mut backward, &mut ops);
+    ops
+}
+
+// Appends the ops turning `old` into `new`, which start at line `base` of the
+// whole files.
+fn diff_range(
+    mut old: &[usize],
+    mut new: &[usize],
+    base: (usize, usize),
+    forward: &mut [isize],
+    backward: &mut [isize],
+    ops: &mut Vec<DiffOp>,
+) {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    ops.extend((0..prefix).map(|i| DiffOp::Equal(base.0 + i, base.1 + i)));
+    let (x0, y0) = (base.0 + prefix, base.1 + prefix);
+    (old, new) = (&old[prefix..], &new[prefix..]);
+    let suffix = old.iter().rev().zip(new.iter().rev()).take_while(|(a, b)| a == b).count();
+    (old, new) = (&old[..old.len() - suffix], &new[..new.len() - suffix]);
+
+    if old.is_empty() || new.is_empty() {
+        ops.extend((x0..x0 + old.len()).map(DiffOp::Delete));
+        ops.extend((y0..y0 + new.len()).map(DiffOp::Insert));
+    } else {
+        let (x, y, u, w) = middle_snake(old, new, forward, backward);
+        diff_range(&old[..x], &new[..y], (x0, y0), forward, backward, ops);
+        ops.extend((0..u - x).map(|i| DiffOp::Equal(x0 + x + i, y0 + y + i)));
+        diff_range(&old[u..], &new[w..], (x0 + u, y0 + w), forward, backward, ops);
+    }
+    let (x1, y1) = (x0 + old.len(), y0 + new.len());
+    ops.extend((0..suffix).map(|i| DiffOp::Equal(x1 + i, y1 + i)));
+}
+
+// Runs the search from both corners at once, one edit at a time, until the
+// two overlap. The snake where they meet, as (x, y) to (u, w), lies on a
+// shortest path and splits the edits between its two sides about evenly.
+// `forward` holds the furthest x reached on each diagonal k = x - y from the
+// start, `backward` the furthest distance reached on each diagonal from the
+// end.
+fn middle_snake(
+    old: &[usize],
+    new: &[usize],
+    forward: &mut [isize],
+    backward: &mut [isize],
+) -> (usize, usize, usize, usize) {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let delta = n - m;
+    let offset = (forward.len() / 2) as isize;
+    let at = |k: isize| (k + offset) as usize;
+    forward[at(1)] = 0;
+    backward[at(1)] = 0;
+
+    let mut d = 0;
+    loop {
+        for k in (-d..=d).step_by(2) {
+            let mut x = match k == -d || (k != d && forward[at(k - 1)] < forward[at(k + 1)]) {
+                true => forward[at(k + 1)],
+                false => forward[at(k - 1)] + 1,
+            };
+            let start = (x, x - k);
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[at(k)] = x;
+            // the backward search has made d - 1 edits so far
+            let c = delta - k;
+            if delta % 2 != 0 && c.abs() < d && x + backward[at(c)] >= n {
+                return (start.0 as usize, start.1 as usize, x as usize, y as usize);
+            }
+        }
+        for c in (-d..=d).step_by(2) {
+            let mut x = match c == -d || (c != d && backward[at(c - 1)] < backward[at(c + 1)]) {
+                true => backward[at(c + 1)],
+                false => backward[at(c - 1)] + 1,
+            };
+            let start = (x, x - c);
+            let mut y = x - c;
+            while x < n && y < m && old[(n - x - 1) as usize] == new[(m - y - 1) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[at(c)] = x;
+            let k = delta - c;
+            if delta % 2 == 0 && k.abs() <= d && forward[at(k)] + x >= n {
+                return ((n - x) as usize, (m - y) as usize, (n - start.0) as usize, (m - start.1) as usize);
+            }
+        }
+        d += 1;
+    }
+}
+
+fn blob(entry: Option<(u32, ObjectId)>) -> Result<Vec<u8>, ItError> {
+    match entry {
+        Some((_, sha)) if !sha.is_null() => Ok(read_object(&sha.to_hex())?.1),
+        _ => Ok(Vec::new()),
+    }
+}
+
+fn abbrev(entry: Option<(u32, ObjectId)>) -> String {
+    match entry {
+        Some((_, sha)) => sha.to_hex()[..7].to_string(),
+        None => "0".repeat(7),
+    }
+}
+
+// "@@ -a,b +c,d @@" ranges: a zero-length range names the line before it,
+// a single line drops the count.
+fn range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, count),
+    }
+}
+
+pub fn print_patch(change: &Change) -> Result<(), ItError> {
+    let path = &change.path;
+    println!("{}", format!("diff --git a/{path} b/{path}").bold());
+    match (change.old, change.new) {
+        (None, Some((mode, _))) => println!("{}", format!("new file mode {mode:06o}").bold()),
+        (Some((mode, _)), None) => println!("{}", format!("deleted file mode {mode:06o}").bold()),
+        (Some((old, _)), Some((new, _))) if old != new => {
+            println!("{}", format!("old mode {old:06o}").bold());
+            println!("{}", format!("new mode {new:06o}").bold());
+        }
+        _ => {}
+    }
+
+    let same_content = change.old.map(|(_, sha)| sha) == change.new.map(|(_, sha)| sha);
+    if same_content {
+        return Ok(());
+    }
+    let index = format!("index {}..{}", abbrev(change.old), abbrev(change.new));
+    match (change.old, change.new) {
+        (Some((old, _)), Some((new, _))) if old == new => println!("{}", format!("{index} {old:06o}").bold()),
+        _ => println!("{}", index.bold()),
+    }
+
+    let old_name = if change.old.is_some() { format!("a/{path}") } else { "/dev/null".to_string() };
+    let new_name = if change.new.is_some() { format!("b/{path}") } else { "/dev/null".to_string() };
+    let (old_data, new_data) = (blob(change.old)?, blob(change.new)?);
+    if is_binary(&old_data) || is_binary(&new_data) {
+        println!("Binary files {old_name} and {new_name} differ");
+        return Ok(());
+    }
+    println!("{}", format!("--- {old_name}").bold());
+    println!("{}", format!("+++ {new_name}").bold());
+
+    let (old_lines, new_lines) = (split_lines(&old_data), split_lines(&new_data));
+    let ops = diff_lines(&old_lines, &new_lines);
+
+    // line positions on both sides before each op
+    let mut positions = Vec::with_capacity(ops.len());
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for op in &ops {
+        positions.push((old_pos, new_pos));
+        match op {
+            DiffOp::Equal(..) => (old_pos, new_pos) = (old_pos + 1, new_pos + 1),
+            DiffOp::Delete(_) => old_pos += 1,
+            DiffOp::Insert(_) => new_pos += 1,
+        }
+    }
+
+    for (start, end) in hunks(&ops) {
+        let hunk = &ops[start..end];
+        let old_count = hunk.iter().filter(|op| !matches!(op, DiffOp::Insert(_))).count();
+        let new_count = hunk.iter().filter(|op| !matches!(op, DiffOp::Delete(_))).count();
+        let (old_start, new_start) = positions[start];
+        let header = format!("@@ -{} +{} @@", range(old_start, old_count), range(new_start, new_count));
+        match function_context(&old_lines[..old_start]) {
+            Some(context) => println!("{} {context}", header.cyan()),
+            None => println!("{}", header.cyan()),
+        }
+        print_lines(hunk, &old_lines, &new_lines);
+    }
+    Ok(())
+}
+
+// git's default hunk header context: the closest line above the hunk that
+// starts with a letter, `_` or `$`, cut to 80 bytes.
+fn function_context(before: &[&[u8]]) -> Option<String> {
+    let line = before.iter().rev().find(|l| l.first().is_some_and(|&c| c.is_ascii_alphabetic() || c == b'_' || c == b'$'))?;
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    Some(String::from_utf8_lossy(&line[..line.len().min(80)]).trim_end().to_string())
+}
+
+// Index ranges into `ops`, each a run of changes padded with up to CONTEXT
+// equal lines; runs closer than 2 * CONTEXT are merged.
+fn hunks(ops: &[DiffOp]) -> Vec<(usize, usize)> {
+    let changed = (0..ops.len()).filter(|&i| !matches!(ops[i], DiffOp::Equal(..)));
+    let mut out: Vec<(usize, usize)> = Vec::new();
+    for i in changed {
+        let start = i.saturating_sub(CONTEXT);
+        let end = (i + 1 + CONTEXT).min(ops.len());
+        match out.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => out.push((start, end)),
+        }
+    }
+    out
+}
+
+fn print_lines(ops: &[DiffOp], old: &[&[u8]], new: &[&[u8]]) {
+    for op in ops {
+        let (sign, line) = match *op {
+            DiffOp::Equal(o, _) => (' ', old[o]),
+            DiffOp::Delete(o) => ('-', old[o]),
+            DiffOp::Insert(n) => ('+', new[n]),
+        };
+        let text = String::from_utf8_lossy(line.strip_suffix(b"\n").unwrap_or(line)).to_string();
+        let text = format!("{sign}{text}");
+        match sign {
+            '-' => println!("{}", text.red()),
+            '+' => println!("{}", text.green()),
+            _ => println!("{text}"),
+        }
+        if !line.ends_with(b"\n") {
+            println!("\\ No newline at end of file");
+        }
+    }
+}
+
+// Added and removed line counts, None for binary files.
+pub fn line_counts(change: &Change) -> Result<Option<(usize, usize)>, ItError> {
+    let (old_data, new_data) = (blob(change.old)?, blob(change.new)?);
+    if is_binary(&old_data) || is_binary(&new_data) {
+        return Ok(None);
+    }
+    let ops = diff_lines(&split_lines(&old_data), &split_lines(&new_data));
+    let added = ops.iter().filter(|op| matches!(op, DiffOp::Insert(_))).count();
+    let removed = ops.iter().filter(|op| matches!(op, DiffOp::Delete(_))).count();
+    Ok(Some((added, removed)))
+}
+
+// git's --stat layout for an 80 column line: names get what the counts and
+// graph leave over, and the graph is scaled down only when it does not fit.
+pub fn print_stat(changes: &[Change]) -> Result<(), ItError> {
+    const WIDTH: usize = 80;
+    let mut rows = Vec::new();
+    for change in changes {
+        let counts = line_counts(change)?;
+        let sizes = (blob(change.old)?.len(), blob(change.new)?.len());
+        rows.push((change.path.as_str(), counts, sizes));
+    }
+
+    let max_len = rows.iter().map(|r| r.0.len()).max().unwrap_or(0);
+    let max_change = rows.iter().filter_map(|r| r.1).map(|(a, d)| a + d).max().unwrap_or(0);
+    let bin_width = rows
+        .iter()
+        .filter(|r| r.1.is_none())
+        .map(|r| format!("Bin {} -> {} bytes", r.2.0, r.2.1).len())
+        .max()
+        .unwrap_or(0);
+    let mut number_width = max_change.to_string().len();
+    if bin_width > 0 {
+        number_width = number_width.max(3);
+    }
+
+    let mut graph_width = if max_change + 4 > bin_width { max_change } else { bin_width - 4 };
+    let mut name_width = max_len;
+    if name_width + number_width + 6 + graph_width > WIDTH {
+        if graph_width > (WIDTH * 3 / 8).saturating_sub(number_width + 6) {
+            graph_width = (WIDTH * 3 / 8).saturating_sub(number_width + 6).max(6);
+        }
+        if name_width > WIDTH.saturating_sub(number_width + 6 + graph_width) {
+            name_width = WIDTH.saturating_sub(number_width + 6 + graph_width);
+        } else {
+            graph_width = WIDTH - number_width - 6 - name_width;
+        }
+    }
+    let scale = |n: usize| match n {
+        0 => 0,
+        _ if graph_width <= 1 => 1,
+        _ => 1 + n * (graph_width - 1) / max_change,
+    };
+
+    let (mut insertions, mut deletions) = (0, 0);
+    for (path, counts, (old_size, new_size)) in &rows {
+        let name = truncate_name(path, name_width);
+        let Some((added, removed)) = *counts else {
+            println!(" {name:<name_width$} | {:>number_width$} {old_size} -> {new_size} bytes", "Bin");
+            continue;
+        };
+        insertions += added;
+        deletions += removed;
+
+        let (mut plus, mut minus) = (added, removed);
+        if graph_width <= max_change {
+            let mut total = scale(added + removed);
+            if total < 2 && added > 0 && removed > 0 {
+                total = 2;
+            }
+            if added < removed {
+                plus = scale(added);
+                minus = total - plus;
+            } else {
+                minus = scale(removed);
+                plus = total - minus;
+            }
+        }
+        println!(
+            " {name:<name_width$} | {:>number_width$}{}{}{}",
+            added + removed,
+            if added + removed > 0 { " " } else { "" },
+            "+".repeat(plus).green(),
+            "-".repeat(minus).red()
+        );
+    }
+
+    let plural = |n: usize, one: &str, many: &str| format!("{n} {}", if n == 1 { one } else { many });
+    let mut summary = format!(" {}", plural(rows.len(), "file changed", "files changed"));
+    if insertions > 0 || deletions == 0 {
+        summary.push_str(&format!(", {}", plural(insertions, "insertion(+)", "insertions(+)")));
+    }
+    if deletions > 0 || insertions == 0 {
+        summary.push_str(&format!(", {}", plural(deletions, "deletion(-)", "deletions(-)")));
+    }
+    println!("{summary}");
+    Ok(())
+}
+
+// Long names keep their tail, cut at a directory boundary: ".../dir/file"
+fn truncate_name(path: &str, width: usize) -> String {
+    if path.len() <= width {
+        return path.to_string();
+    }
+    let keep = width.saturating_sub(3);
+    let tail = &path[path.len() - keep..];
+    match tail.find('/') {
+        Some(slash) => format!("...{}", &tail[slash..]),
+        None => format!("...{tail}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // checks that `ops` walks both sides in order and turns `old` into `new`,
+    // returning the number of inserted and deleted lines
+    fn check(old: &[&[u8]], new: &[&[u8]], ops: &[DiffOp]) -> usize {
+        let (mut x, mut y, mut edits) = (0, 0, 0);
+        for op in ops {
+            match *op {
+                DiffOp::Equal(o, n) => {
+                    assert_eq!((o, n), (x, y));
+                    assert_eq!(old[o], new[n]);
+                    (x, y) = (x + 1, y + 1);
+                }
+                DiffOp::Delete(o) => {
+                    assert_eq!(o, x);
+                    x += 1;
+                    edits += 1;
+                }
+                DiffOp::Insert(n) => {
+                    assert_eq!(n, y);
+                    y += 1;
+                    edits += 1;
+                }
+            }
+        }
+        assert_eq!((x, y), (old.len(), new.len()));
+        edits
+    }
+
+    // the shortest edit script via the quadratic LCS table
+    fn shortest(old: &[&[u8]], new: &[&[u8]]) -> usize {
+        let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+        for i in (0..old.len()).rev() {
+            for j in (0..new.len()).rev() {
+                lcs[i][j] = match old[i] == new[j] {
+                    true => lcs[i + 1][j + 1] + 1,
+                    false => lcs[i + 1][j].max(lcs[i][j + 1]),
+                };
+            }
+        }
+        old.len() + new.len() - 2 * lcs[0][0]
+    }
+
+    fn lines(text: &str) -> Vec<&[u8]> {
+        text.as_bytes().chunks(1).collect()
+    }
+
+    #[test]
+    fn myers_paper_example() {
+        // the example from Myers' paper, where D = 5
+        let (old, new) = (lines("abcabba"), lines("cbabac"));
+        let ops = diff_lines(&old, &new);
+        assert_eq!(check(&old, &new, &ops), 5);
+
+        let ids = |s: &str| s.bytes().map(usize::from).collect::<Vec<_>>();
+        let (a, b) = (ids("abcabba"), ids("cbabac"));
+        let max = (a.len() + b.len()).div_ceil(2) + 1;
+        let (mut forward, mut backward) = (vec![0; 2 * max + 1], vec![0; 2 * max + 1]);
+        let (x, y, u, w) = middle_snake(&a, &b, &mut forward, &mut backward);
+        assert_eq!(u - x, w - y);
+        assert!(a[x..u] == b[y..w]);
+        // the edits before and after the snake add up to D
+        let before = shortest(&lines(&"abcabba"[..x]), &lines(&"cbabac"[..y]));
+        let after = shortest(&lines(&"abcabba"[u..]), &lines(&"cbabac"[w..]));
+        assert_eq!(before + after, 5);
+    }
+
+    #[test]
+    fn edge_cases() {
+        let empty: Vec<&[u8]> = Vec::new();
+        assert!(diff_lines(&empty, &empty).is_empty());
+        let some = lines("abc");
+        assert_eq!(diff_lines(&empty, &some), vec![DiffOp::Insert(0), DiffOp::Insert(1), DiffOp::Insert(2)]);
+        assert_eq!(diff_lines(&some, &empty), vec![DiffOp::Delete(0), DiffOp::Delete(1), DiffOp::Delete(2)]);
+        assert_eq!(
+            diff_lines(&some, &some),
+            vec![DiffOp::Equal(0, 0), DiffOp::Equal(1, 1), DiffOp::Equal(2, 2)]
+        );
+        let (old, new) = (lines("abxcd"), lines("abycd"));
+        assert_eq!(
+            diff_lines(&old, &new),
+            vec![
+                DiffOp::Equal(0, 0),
+                DiffOp::Equal(1, 1),
+                DiffOp::Delete(2),
+                DiffOp::Insert(2),
+                DiffOp::Equal(3, 3),
+                DiffOp::Equal(4, 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn random_diffs_are_minimal() {
+        // xorshift, so every run sees the same inputs; a three-letter
+        // alphabet gives plenty of competing matches and deep recursion
+        let mut seed = 0x9e37_79b9_7f4a_7c15u64;
+        let mut next = |bound: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % bound
+        };
+        for _ in 0..500 {
+            let old: String = (0..next(40)).map(|_| (b'a' + next(3) as u8) as char).collect();
+            let new: String = (0..next(40)).map(|_| (b'a' + next(3) as u8) as char).collect();
+            let (old, new) = (lines(&old), lines(&new));
+            let ops = diff_lines(&old, &new);
+            assert_eq!(check(&old, &new, &ops), shortest(&old, &new), "{old:?} {new:?}");
+        }
+    }
+
+    #[test]
+    fn large_sparse_diff() {
+        let text: Vec<String> = (0..20_000).map(|i| format!("line {i}\n")).collect();
+        let old: Vec<&[u8]> = text.iter().map(|l| l.as_bytes()).collect();
+        let mut new = old.clone();
+        for i in (0..new.len()).step_by(1000).rev() {
+            new[i] = b"changed\n";
+            new.insert(i + 500, b"inserted\n");
+        }
+        let ops = diff_lines(&old, &new);
+        assert_eq!(check(&old, &new, &ops), 60);
+    }
+
+    #[test]
+    fn hunks_merge_close_changes() {
+        let mut ops: Vec<DiffOp> = (0..30).map(|i| DiffOp::Equal(i, i)).collect();
+        ops[5] = DiffOp::Delete(5);
+        ops[11] = DiffOp::Insert(11);
+        ops[20] = DiffOp::Delete(20);
+        // 5 and 11 share context, 20 is more than 2 * CONTEXT further
+        assert_eq!(hunks(&ops), vec![(2, 15), (17, 24)]);
+        assert!(hunks(&ops[..3]).is_empty());
+    }
+
+    #[test]
+    fn lines_and_context() {
+        assert_eq!(split_lines(b"a\nb\nc"), vec![&b"a\n"[..], b"b\n", b"c"]);
+        assert!(split_lines(b"").is_empty());
+        assert!(is_binary(b"text\0more"));
+        assert!(!is_binary(&[b'x'; 9000]));
+        let before: Vec<&[u8]> = vec![b"fn main() {\r\n", b"    let x = 1;\n", b"\n"];
+        assert_eq!(function_context(&before).as_deref(), Some("fn main() {"));
+        assert_eq!(function_context(&before[1..]), None);
+    }
+}
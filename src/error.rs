@@ -16,6 +16,22 @@ pub enum ItError {
     Io(io::Error),
 
     NothingToCommit,
+
+    InvalidConfig(String),
+
+    CorruptObject(String),
+
+    Fatal(String),
+
+    CorruptIndex { reason: String },
+
+    Locked(PathBuf),
+
+    RefChanged {
+        name: String,
+        expected: Option<String>,
+        actual: Option<String>,
+    },
 }
 impl fmt::Display for ItError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -40,6 +56,39 @@ impl fmt::Display for ItError {
             ItError::NothingToCommit => {
                 write!(f, "{}","NothingToCommit".red().bold())
             }
+            ItError::InvalidConfig(reason) => {
+                write!(f, "{}", format!("fatal: {reason}").red().bold())
+            }
+            ItError::CorruptObject(reason) => {
+                write!(f, "{}", format!("fatal: corrupt object {reason}").red().bold())
+            }
+            ItError::Fatal(msg) => write!(f, "{}", format!("fatal: {msg}").red().bold()),
+            ItError::CorruptIndex { reason } => {
+                write!(f, "{}", format!("fatal: index file corrupt: {reason}").red().bold())
+            }
+            ItError::Locked(path) => {
+                write!(
+                    f,
+                    "{}",
+                    format!(
+                        "fatal: Unable to create '{}': File exists.\n\nAnother it process seems to be running in this repository. \
+                         If it still fails, a process may have crashed earlier: remove the file manually to continue.",
+                        path.display()
+                    )
+                    .red()
+                    .bold()
+                )
+            }
+            ItError::RefChanged { name, expected, actual } => {
+                let show = |v: &Option<String>| v.clone().unwrap_or_else(|| "(nothing)".to_string());
+                write!(
+                    f,
+                    "{}",
+                    format!("fatal: cannot lock ref '{name}': is at {} but expected {}", show(actual), show(expected))
+                        .red()
+                        .bold()
+                )
+            }
         }
     }
 }
@@ -0,0 +1,589 @@
+use crate::config::{Config, expand_home};
+use crate::error::ItError;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// gitignore-compatible exclude rules. Patterns come from, highest precedence
+// first: the deepest `.itignore` up to the worktree root, `.it/info/exclude`,
+// then `core.excludesFile` (default ~/.config/it/ignore). Within one source
+// the last matching line wins, and a `!pattern` re-includes a path.
+
+#[derive(Debug, Clone)]
+pub struct IgnorePattern {
+    // the line as written in its source file
+    pub original: String,
+    // pattern text without the leading `!` / `/` or trailing `/`
+    pub pattern: String,
+    pub negated: bool,
+    pub dir_only: bool,
+    // no slash in the pattern: match the basename at any depth
+    pub basename_only: bool,
+    // directory of the .itignore the pattern came from, "" for the worktree root
+    pub base: String,
+    pub source: String,
+    pub line: usize,
+}
+
+impl IgnorePattern {
+    fn parse(line: &str, base: &str, source: &str, line_no: usize) -> Option<IgnorePattern> {
+        let line = trim_trailing_spaces(line);
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        // `\#` and `\!` escape a leading character that would otherwise be special
+        let (negated, mut pattern) = if line.starts_with("\\#") || line.starts_with("\\!") {
+            (false, &line[1..])
+        } else {
+            match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            }
+        };
+        let dir_only = pattern.ends_with('/') && pattern.len() > 1;
+        if dir_only {
+            pattern = &pattern[..pattern.len() - 1];
+        }
+        let basename_only = !pattern.contains('/');
+        let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+        if pattern.is_empty() {
+            return None;
+        }
+
+        Some(IgnorePattern {
+            original: line.to_string(),
+            pattern: pattern.to_string(),
+            negated,
+            dir_only,
+            basename_only,
+            base: base.to_string(),
+            source: source.to_string(),
+            line: line_no,
+        })
+    }
+
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let rel = if self.base.is_empty() {
+            path
+        } else {
+            match path.strip_prefix(&self.base).and_then(|p| p.strip_prefix('/')) {
+                Some(rel) => rel,
+                None => return false,
+            }
+        };
+        if self.basename_only {
+            let name = rel.rsplit('/').next().unwrap_or(rel);
+            wildmatch(&self.pattern, name, true)
+        } else {
+            wildmatch(&self.pattern, rel, true)
+        }
+    }
+}
+
+fn trim_trailing_spaces(line: &str) -> &str {
+    let mut end = line.len();
+    let bytes = line.as_bytes();
+    while end > 0 && bytes[end - 1] == b' ' {
+        if end >= 2 && bytes[end - 2] == b'\\' {
+            break;
+        }
+        end -= 1;
+    }
+    &line[..end]
+}
+
+pub struct Ignore {
+    worktree: PathBuf,
+    global: Vec<IgnorePattern>,
+    info: Vec<IgnorePattern>,
+    // .itignore patterns keyed by worktree-relative directory, read lazily
+    per_dir: HashMap<String, Vec<IgnorePattern>>,
+}
+
+#[derive(Debug)]
+pub struct WalkEntry {
+    pub path: String,
+    pub ignored: bool,
+}
+
+impl Ignore {
+    pub fn load(worktree: &Path, repo_path: &Path, config: &Config) -> Result<Ignore, ItError> {
+        let global_path = match config.get_path("core.excludesfile") {
+            Some(path) => Some(path),
+            None => std::env::var_os("XDG_CONFIG_HOME")
+                .filter(|x| !x.is_empty())
+                .map(|x| PathBuf::from(x).join("it/ignore"))
+                .or_else(|| Some(expand_home("~/.config/it/ignore"))),
+        };
+        let global = match global_path {
+            Some(path) => read_patterns(&path, "", &path.display().to_string())?,
+            None => vec![],
+        };
+        let info = read_patterns(&repo_path.join("info/exclude"), "", ".it/info/exclude")?;
+
+        Ok(Ignore {
+            worktree: worktree.to_path_buf(),
+            global,
+            info,
+            per_dir: HashMap::new(),
+        })
+    }
+
+    fn dir_patterns(&mut self, dir: &str) -> Result<&[IgnorePattern], ItError> {
+        if !self.per_dir.contains_key(dir) {
+            let (file, source) = if dir.is_empty() {
+                (self.worktree.join(".itignore"), ".itignore".to_string())
+            } else {
+                (self.worktree.join(dir).join(".itignore"), format!("{dir}/.itignore"))
+            };
+            let patterns = read_patterns(&file, dir, &source)?;
+            self.per_dir.insert(dir.to_string(), patterns);
+        }
+        Ok(&self.per_dir[dir])
+    }
+
+    // The pattern that decides `path`, looking only at the path itself and not
+    // at whether one of its parent directories is excluded.
+    pub fn matching(&mut self, path: &str, is_dir: bool) -> Result<Option<IgnorePattern>, ItError> {
+        let mut dirs = vec![String::new()];
+        if let Some((parent, _)) = path.rsplit_once('/') {
+            let mut acc = String::new();
+            for component in parent.split('/') {
+                if !acc.is_empty() {
+                    acc.push('/');
+                }
+                acc.push_str(component);
+                dirs.push(acc.clone());
+            }
+        }
+
+        for dir in dirs.iter().rev() {
+            if let Some(p) = self.dir_patterns(dir)?.iter().rev().find(|p| p.matches(path, is_dir)) {
+                return Ok(Some(p.clone()));
+            }
+        }
+        for source in [&self.info, &self.global] {
+            if let Some(p) = source.iter().rev().find(|p| p.matches(path, is_dir)) {
+                return Ok(Some(p.clone()));
+            }
+        }
+        Ok(None)
+    }
+
+    // Like `matching`, but a path inside an excluded directory is reported as
+    // excluded by that directory's pattern: git never looks inside one.
+    pub fn check(&mut self, path: &str, is_dir: bool) -> Result<Option<IgnorePattern>, ItError> {
+        let components: Vec<&str> = path.split('/').collect();
+        for i in 1..components.len() {
+            let dir = components[..i].join("/");
+            if let Some(p) = self.matching(&dir, true)?.filter(|p| !p.negated) {
+                return Ok(Some(p));
+            }
+        }
+        self.matching(path, is_dir)
+    }
+
+    pub fn is_ignored(&mut self, path: &str, is_dir: bool) -> Result<bool, ItError> {
+        Ok(self.check(path, is_dir)?.is_some_and(|p| !p.negated))
+    }
+
+    // Recursively lists files below the worktree-relative `dir`, never
+    // entering `.it`. Ignored files are skipped unless `include_ignored`.
+    pub fn walk(&mut self, dir: &str, include_ignored: bool) -> Result<Vec<WalkEntry>, ItError> {
+        let mut out = Vec::new();
+        self.walk_into(dir, false, include_ignored, &mut out)?;
+        out.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(out)
+    }
+
+    fn walk_into(
+        &mut self,
+        dir: &str,
+        parent_ignored: bool,
+        include_ignored: bool,
+        out: &mut Vec<WalkEntry>,
+    ) -> Result<(), ItError> {
+        let abs = if dir.is_empty() {
+            self.worktree.clone()
+        } else {
+            self.worktree.join(dir)
+        };
+        for entry in fs::read_dir(&abs)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name == ".it" {
+                continue;
+            }
+            let path = if dir.is_empty() {
+                name
+            } else {
+                format!("{dir}/{name}")
+            };
+            let is_dir = entry.file_type()?.is_dir();
+            let ignored = parent_ignored
+                || self.matching(&path, is_dir)?.is_some_and(|p| !p.negated);
+            if ignored && !include_ignored {
+                continue;
+            }
+            if is_dir {
+                self.walk_into(&path, ignored, include_ignored, out)?;
+            } else {
+                out.push(WalkEntry { path, ignored });
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_patterns(file: &Path, base: &str, source: &str) -> Result<Vec<IgnorePattern>, ItError> {
+    let text = match fs::read_to_string(file) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        // a directory named like the file, unreadable global file etc.
+        Err(_) if !file.is_file() => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(text
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| IgnorePattern::parse(line, base, source, i + 1))
+        .collect())
+}
+
+#[derive(PartialEq)]
+enum Wild {
+    Matched,
+    NoMatch,
+    // the text ran out, no shorter/longer star expansion can help
+    AbortAll,
+    // a single `*` hit a `/`, only an enclosing `**` may retry
+    AbortToStarStar,
+}
+
+// git's wildmatch. With `pathname` (WM_PATHNAME) `*` and `?` stop at `/` and
+// only `**` between slashes spans directories; without it every `*` does.
+// `[...]` supports ranges, `!`/`^` negation and [:class:] names.
+pub fn wildmatch(pattern: &str, text: &str, pathname: bool) -> bool {
+    dowild(pattern.as_bytes(), text.as_bytes(), pathname) == Wild::Matched
+}
+
+fn dowild(p: &[u8], text: &[u8], pathname: bool) -> Wild {
+    let mut pi = 0;
+    let mut ti = 0;
+
+    while pi < p.len() {
+        let p_ch = p[pi];
+        if ti >= text.len() && p_ch != b'*' {
+            return Wild::AbortAll;
+        }
+        let t_ch = text.get(ti).copied().unwrap_or(0);
+
+        match p_ch {
+            b'\\' => {
+                pi += 1;
+                if pi >= p.len() || text[ti] != p[pi] {
+                    return Wild::NoMatch;
+                }
+            }
+            b'?' => {
+                if pathname && t_ch == b'/' {
+                    return Wild::NoMatch;
+                }
+            }
+            b'*' => {
+                pi += 1;
+                let match_slash;
+                if pi < p.len() && p[pi] == b'*' {
+                    let prev_is_boundary = pi < 2 || p[pi - 2] == b'/';
+                    while pi < p.len() && p[pi] == b'*' {
+                        pi += 1;
+                    }
+                    let next_is_boundary = pi >= p.len()
+                        || p[pi] == b'/'
+                        || (p[pi] == b'\\' && p.get(pi + 1) == Some(&b'/'));
+                    if !pathname {
+                        match_slash = true;
+                    } else if prev_is_boundary && next_is_boundary {
+                        // "**/" may also match zero directories
+                        if pi < p.len() && p[pi] == b'/' && dowild(&p[pi + 1..], &text[ti..], pathname) == Wild::Matched {
+                            return Wild::Matched;
+                        }
+                        match_slash = true;
+                    } else {
+                        match_slash = false;
+                    }
+                } else {
+                    match_slash = !pathname;
+                }
+
+                if pi >= p.len() {
+                    if !match_slash && text[ti..].contains(&b'/') {
+                        return Wild::AbortToStarStar;
+                    }
+                    return Wild::Matched;
+                }
+                if !match_slash && p[pi] == b'/' {
+                    // a single star followed by a slash consumes one path component
+                    match text[ti..].iter().position(|&b| b == b'/') {
+                        Some(offset) => {
+                            ti += offset + 1;
+                            pi += 1;
+                            continue;
+                        }
+                        None => return Wild::AbortAll,
+                    }
+                }
+
+                while ti < text.len() {
+                    match dowild(&p[pi..], &text[ti..], pathname) {
+                        Wild::NoMatch => {
+                            if !match_slash && text[ti] == b'/' {
+                                return Wild::AbortToStarStar;
+                            }
+                        }
+                        Wild::AbortToStarStar if match_slash => {}
+                        other => return other,
+                    }
+                    ti += 1;
+                }
+                return Wild::AbortAll;
+            }
+            b'[' => {
+                pi += 1;
+                let negated = matches!(p.get(pi), Some(b'!') | Some(b'^'));
+                if negated {
+                    pi += 1;
+                }
+                let mut matched = false;
+                let mut prev_ch: u8 = 0;
+                let mut first = true;
+                loop {
+                    let Some(&c) = p.get(pi) else {
+                        return Wild::AbortAll;
+                    };
+                    if c == b']' && !first {
+                        break;
+                    }
+                    first = false;
+                    let mut this_ch = c;
+                    if c == b'\\' {
+                        pi += 1;
+                        let Some(&escaped) = p.get(pi) else {
+                            return Wild::AbortAll;
+                        };
+                        this_ch = escaped;
+                        if t_ch == escaped {
+                            matched = true;
+                        }
+                    } else if c == b'-' && prev_ch != 0 && p.get(pi + 1).is_some_and(|&n| n != b']') {
+                        pi += 1;
+                        let mut hi = p[pi];
+                        if hi == b'\\' {
+                            pi += 1;
+                            let Some(&escaped) = p.get(pi) else {
+                                return Wild::AbortAll;
+                            };
+                            hi = escaped;
+                        }
+                        if t_ch >= prev_ch && t_ch <= hi {
+                            matched = true;
+                        }
+                        this_ch = 0;
+                    } else if c == b'[' && p.get(pi + 1) == Some(&b':') {
+                        let start = pi + 2;
+                        let end = p[start..].windows(2).position(|w| w == b":]");
+                        match end {
+                            Some(len) => {
+                                let class = &p[start..start + len];
+                                match char_class(class, t_ch) {
+                                    Some(true) => matched = true,
+                                    Some(false) => {}
+                                    None => return Wild::AbortAll,
+                                }
+                                pi = start + len + 1;
+                                this_ch = 0;
+                            }
+                            None => {
+                                // no ":]", treat the '[' literally
+                                if t_ch == b'[' {
+                                    matched = true;
+                                }
+                            }
+                        }
+                    } else if t_ch == c {
+                        matched = true;
+                    }
+                    prev_ch = this_ch;
+                    pi += 1;
+                }
+                if matched == negated || (pathname && t_ch == b'/') {
+                    return Wild::NoMatch;
+                }
+            }
+            _ => {
+                if t_ch != p_ch {
+                    return Wild::NoMatch;
+                }
+            }
+        }
+        pi += 1;
+        ti += 1;
+    }
+
+    if ti >= text.len() {
+        Wild::Matched
+    } else {
+        Wild::NoMatch
+    }
+}
+
+fn char_class(class: &[u8], c: u8) -> Option<bool> {
+    Some(match class {
+        b"alnum" => c.is_ascii_alphanumeric(),
+        b"alpha" => c.is_ascii_alphabetic(),
+        b"blank" => c == b' ' || c == b'\t',
+        b"cntrl" => c.is_ascii_control(),
+        b"digit" => c.is_ascii_digit(),
+        b"graph" => c.is_ascii_graphic(),
+        b"lower" => c.is_ascii_lowercase(),
+        b"print" => c.is_ascii_graphic() || c == b' ',
+        b"punct" => c.is_ascii_punctuation(),
+        b"space" => c.is_ascii_whitespace(),
+        b"upper" => c.is_ascii_uppercase(),
+        b"xdigit" => c.is_ascii_hexdigit(),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigEntry;
+
+    #[test]
+    fn wildmatch_cases() {
+        // (pattern, text, with WM_PATHNAME, without)
+        for (pattern, text, pathname, plain) in [
+            ("foo", "foo", true, true),
+            ("foo", "bar", false, false),
+            ("", "", true, true),
+            ("???", "foo", true, true),
+            ("*f", "foo", false, false),
+            ("*", "foo/bar", false, true),
+            ("foo*bar", "foo/baz/bar", false, true),
+            ("foo?bar", "foo/bar", false, true),
+            ("**/foo", "foo", true, false),
+            ("**/foo", "a/b/foo", true, true),
+            ("**/foo", "xfoo", false, false),
+            ("foo/**", "foo/a/b", true, true),
+            ("foo/**", "foo", false, false),
+            ("a/**/b", "a/b", true, false),
+            ("a/**/b", "a/x/y/b", true, true),
+            ("a/**b", "a/x/b", false, true),
+            ("[a-c]x", "bx", true, true),
+            ("[!a-c]x", "bx", false, false),
+            ("[^a-c]x", "dx", true, true),
+            ("[]]", "]", true, true),
+            ("[[:digit:]]*", "7up", true, true),
+            ("[[:upper:][:space:]]", "a", false, false),
+            ("[[:nope:]]", "a", false, false),
+            ("\\*", "*", true, true),
+            ("\\*", "x", false, false),
+            ("[a-", "a", false, false),
+            ("foo/*", "foo/bar/baz", false, true),
+            ("*.c", "dir/x.c", false, true),
+        ] {
+            assert_eq!(wildmatch(pattern, text, true), pathname, "{pattern:?} {text:?} pathname");
+            assert_eq!(wildmatch(pattern, text, false), plain, "{pattern:?} {text:?}");
+        }
+    }
+
+    #[test]
+    fn pattern_lines() {
+        let parse = |line: &str| IgnorePattern::parse(line, "", "test", 1);
+        assert!(parse("# comment").is_none());
+        assert!(parse("   ").is_none());
+        assert!(parse("/").is_none());
+
+        let p = parse("!/build/  ").unwrap();
+        assert_eq!((p.pattern.as_str(), p.negated, p.dir_only, p.basename_only), ("build", true, true, false));
+        assert_eq!(p.original, "!/build/");
+        let p = parse("\\#hash").unwrap();
+        assert_eq!((p.pattern.as_str(), p.negated), ("#hash", false));
+        let p = parse("\\!bang").unwrap();
+        assert_eq!((p.pattern.as_str(), p.negated), ("!bang", false));
+        // an escaped trailing space is kept
+        assert_eq!(parse("name\\ ").unwrap().pattern, "name\\ ");
+        assert!(parse("*.o").unwrap().basename_only);
+        assert!(!parse("doc/*.txt").unwrap().basename_only);
+    }
+
+    #[test]
+    fn negation_and_directories() {
+        let root = std::env::temp_dir().join(format!("it-ignore-test-{}", std::process::id()));
+        let repo = root.join(".it");
+        for dir in ["src/gen", "logs", "build/keep", ".it/info"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join(".itignore"), "*.log\n!important.log\nbuild/\n!build/keep/\ndoc/*.txt\n").unwrap();
+        fs::write(root.join("src/.itignore"), "gen\n!*.log\n").unwrap();
+        fs::write(repo.join("info/exclude"), "*.tmp\n!keep.log\n").unwrap();
+        for file in ["a.log", "important.log", "logs/x.log", "src/a.log", "src/gen/g.rs", "build/keep/k", "x.tmp"] {
+            fs::write(root.join(file), "").unwrap();
+        }
+
+        let config = Config {
+            entries: vec![ConfigEntry {
+                key: "core.excludesfile".to_string(),
+                value: Some(root.join("missing").display().to_string()),
+            }],
+        };
+        let mut ignore = Ignore::load(&root, &repo, &config).unwrap();
+        let mut ignored = |path: &str, is_dir: bool| ignore.is_ignored(path, is_dir).unwrap();
+        assert!(ignored("a.log", false));
+        assert!(!ignored("important.log", false));
+        assert!(ignored("logs/x.log", false));
+        // the deeper .itignore wins over the root one
+        assert!(!ignored("src/a.log", false));
+        assert!(ignored("src/gen", true));
+        assert!(ignored("src/gen/g.rs", false));
+        // dir-only patterns leave a file of that name alone
+        assert!(ignored("build", true));
+        assert!(!ignored("build", false));
+        // nothing inside an excluded directory can be re-included
+        assert!(ignored("build/keep/k", false));
+        // a slash anchors the pattern to the .itignore's directory
+        assert!(ignored("doc/a.txt", false));
+        assert!(!ignored("sub/doc/a.txt", false));
+        // .itignore patterns outrank info/exclude
+        assert!(ignored("keep.log", false));
+        assert!(ignored("x.tmp", false));
+
+        let p = ignore.check("src/gen/g.rs", false).unwrap().unwrap();
+        assert_eq!((p.source.as_str(), p.line, p.base.as_str()), ("src/.itignore", 1, "src"));
+
+        let walked = |ignore: &mut Ignore, all: bool| -> Vec<(String, bool)> {
+            ignore.walk("", all).unwrap().into_iter().map(|e| (e.path, e.ignored)).collect()
+        };
+        let entry = |path: &str, ignored: bool| (path.to_string(), ignored);
+        let visible = walked(&mut ignore, false);
+        let all = walked(&mut ignore, true);
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(
+            visible,
+            vec![
+                entry(".itignore", false),
+                entry("important.log", false),
+                entry("src/.itignore", false),
+                entry("src/a.log", false),
+            ]
+        );
+        assert_eq!(all.len(), 9);
+        assert!(all.contains(&entry("build/keep/k", true)));
+        assert!(all.contains(&entry("x.tmp", true)));
+    }
+}
@@ -1,15 +1,174 @@
-use colored::Colorize;
-use sha1::{Digest, Sha1};
+use crate::commands::hash_object::{build_object, compress_data, hash_file, store_object};
+use crate::error::ItError;
+use crate::lfs::Lfs;
+use crate::lockfile::{LockFile, TempFile};
+use crate::object_id::{ObjectFormat, ObjectId};
 use std::fs;
+use std::io::{self, Read};
 use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const MODE_FILE: u32 = 0o100644;
+pub const MODE_EXECUTABLE: u32 = 0o100755;
+pub const MODE_SYMLINK: u32 = 0o120000;
+
+// The cached lstat() fields git keeps per entry. Truncated to 32 bits as in
+// the on-disk format, which is fine because they are only compared for equality.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StatData {
+    pub ctime_sec: u32,
+    pub ctime_nsec: u32,
+    pub mtime_sec: u32,
+    pub mtime_nsec: u32,
+    pub dev: u32,
+    pub ino: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+}
+
+impl StatData {
+    pub fn from_metadata(meta: &fs::Metadata) -> StatData {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            StatData {
+                ctime_sec: meta.ctime() as u32,
+                ctime_nsec: meta.ctime_nsec() as u32,
+                mtime_sec: meta.mtime() as u32,
+                mtime_nsec: meta.mtime_nsec() as u32,
+                dev: meta.dev() as u32,
+                ino: meta.ino() as u32,
+                uid: meta.uid(),
+                gid: meta.gid(),
+                size: meta.size() as u32,
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            let (mtime_sec, mtime_nsec) = meta.modified().map(split_time).unwrap_or((0, 0));
+            StatData {
+                mtime_sec,
+                mtime_nsec,
+                size: meta.len() as u32,
+                ..StatData::default()
+            }
+        }
+    }
+
+    fn read(data: &[u8]) -> StatData {
+        let field = |i: usize| u32::from_be_bytes(data[i * 4..i * 4 + 4].try_into().unwrap());
+        // ctime, mtime, dev, ino, (mode), uid, gid, size
+        StatData {
+            ctime_sec: field(0),
+            ctime_nsec: field(1),
+            mtime_sec: field(2),
+            mtime_nsec: field(3),
+            dev: field(4),
+            ino: field(5),
+            uid: field(7),
+            gid: field(8),
+            size: field(9),
+        }
+    }
+}
+
+fn split_time(time: SystemTime) -> (u32, u32) {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    (since.as_secs() as u32, since.subsec_nanos())
+}
+
+// Decides whether a worktree file can be assumed unchanged from its cached
+// stat data without rehashing it.
+pub struct StatCheck {
+    index_mtime: Option<(u32, u32)>,
+    trust_ctime: bool,
+    pub trust_filemode: bool,
+    // core.checkStat=minimal: only mtime seconds, size and mode
+    minimal: bool,
+}
+
+impl StatCheck {
+    pub fn load(repo_path: &Path, config: &crate::config::Config) -> Result<StatCheck, crate::error::ItError> {
+        let index_mtime = fs::metadata(repo_path.join("index"))
+            .and_then(|m| m.modified())
+            .ok()
+            .map(split_time);
+        Ok(StatCheck {
+            index_mtime,
+            trust_ctime: config.get_bool("core.trustctime")?.unwrap_or(true),
+            trust_filemode: config.get_bool("core.filemode")?.unwrap_or(true),
+            minimal: config.get("core.checkstat").is_some_and(|v| v.eq_ignore_ascii_case("minimal")),
+        })
+    }
+
+    pub fn is_unchanged(&self, entry: &IndexEntry, meta: &fs::Metadata) -> bool {
+        let disk = StatData::from_metadata(meta);
+        let cached = &entry.stat;
+
+        // "Racily clean": a file modified in the same timestamp granule as the
+        // index was written may have changed after it was hashed, so its stat
+        // data proves nothing and the content has to be compared.
+        match self.index_mtime {
+            Some(index_mtime) if (cached.mtime_sec, cached.mtime_nsec) >= index_mtime => return false,
+            None => return false,
+            _ => {}
+        }
+
+        let mode = effective_mode(file_mode(meta), Some(entry.mode), self.trust_filemode);
+        if mode != entry.mode || cached.mtime_sec != disk.mtime_sec || cached.size != disk.size {
+            return false;
+        }
+        if self.minimal {
+            return true;
+        }
+        if self.trust_ctime && (cached.ctime_sec, cached.ctime_nsec) != (disk.ctime_sec, disk.ctime_nsec) {
+            return false;
+        }
+        cached.mtime_nsec == disk.mtime_nsec
+            && cached.dev == disk.dev
+            && cached.ino == disk.ino
+            && cached.uid == disk.uid
+            && cached.gid == disk.gid
+    }
+}
 
 pub struct IndexEntry {
-    pub sha: [u8; 20],
+    pub stat: StatData,
+    pub mode: u32,
+    pub sha: ObjectId,
     pub flags: u16,
     pub path: String,
 }
 
-pub fn read_index(repo_path: &Path) -> Result<Vec<IndexEntry>, std::io::Error> {
+impl IndexEntry {
+    // 0 for a normal entry, 1-3 for the base, ours and theirs side of a conflict
+    pub fn stage(&self) -> u16 {
+        (self.flags >> 12) & 0x3
+    }
+}
+
+// stat data, mode etc. (40 bytes), the id, then a 2-byte flags word
+fn entry_fixed_len(format: ObjectFormat) -> usize {
+    40 + format.raw_len() + 2
+}
+const NAME_MASK: u16 = 0x0FFF;
+const FLAG_EXTENDED: u16 = 0x4000;
+
+fn corrupt(reason: impl Into<String>) -> ItError {
+    ItError::CorruptIndex {
+        reason: reason.into(),
+    }
+}
+
+// Reads `.it/index`, versions 2 through 4. The layout is
+//
+//      "DIRC" | version | entry count | entries... | extensions... | checksum
+//
+// Ids and the checksum use the repository's object format.
+// v3 adds an optional second flags word per entry, v4 drops the entry padding
+// and prefix-compresses each path against the previous one.
+pub fn read_index(repo_path: &Path) -> Result<Vec<IndexEntry>, ItError> {
     let index_path = repo_path.join("index");
     if !index_path.exists() {
         return Ok(vec![]);
@@ -19,67 +178,525 @@ pub fn read_index(repo_path: &Path) -> Result<Vec<IndexEntry>, std::io::Error> {
     if data.is_empty() {
         return Ok(vec![]);
     }
+    decode_index(&data, ObjectFormat::current())
+}
+
+fn decode_index(data: &[u8], format: ObjectFormat) -> Result<Vec<IndexEntry>, ItError> {
+    let hash_len = format.raw_len();
+    let fixed_len = entry_fixed_len(format);
+    if data.len() < 12 + hash_len {
+        return Err(corrupt("file too short"));
+    }
+    if &data[0..4] != b"DIRC" {
+        return Err(corrupt("bad signature"));
+    }
+    let version = u32::from_be_bytes(data[4..8].try_into().unwrap());
+    if !(2..=4).contains(&version) {
+        return Err(corrupt(format!("unsupported version {version}")));
+    }
 
-    if data.len() < 12 || &data[0..4] != b"DIRC" {
-        println!("{}","Invalid Object Format".red());
+    let (content, checksum) = data.split_at(data.len() - hash_len);
+    // an all-zero trailer is what git writes with index.skipHash
+    if checksum.iter().any(|&b| b != 0) && format.digest(content).as_bytes() != checksum {
+        return Err(corrupt("checksum mismatch"));
     }
 
     let entry_count = u32::from_be_bytes(data[8..12].try_into().unwrap()) as usize;
-    let mut entries = Vec::with_capacity(entry_count);
+    let mut entries: Vec<IndexEntry> = Vec::with_capacity(entry_count.min(content.len() / fixed_len));
     let mut pos = 12;
 
-    for _ in 0..entry_count {
-        if pos + 62 > data.len() {
-            println!("{}","Invalid Object Len".red());
+    for i in 0..entry_count {
+        if pos + fixed_len > content.len() {
+            return Err(corrupt(format!("entry {i} truncated")));
         }
 
-        let sha: [u8; 20] = data[pos + 40..pos + 60].try_into().unwrap();
-        let flags = u16::from_be_bytes(data[pos + 60..pos + 62].try_into().unwrap());
+        let stat = StatData::read(&content[pos..pos + 40]);
+        let mode = u32::from_be_bytes(content[pos + 24..pos + 28].try_into().unwrap());
+        let sha = ObjectId::from_bytes(&content[pos + 40..pos + 40 + hash_len]).unwrap();
+        let flags_at = pos + 40 + hash_len;
+        let flags = u16::from_be_bytes(content[flags_at..flags_at + 2].try_into().unwrap());
 
-        let path_start = pos + 62;
-        let null_offset = data[path_start..].iter().position(|&b| b == 0).unwrap();
+        let mut path_start = pos + fixed_len;
+        if flags & FLAG_EXTENDED != 0 {
+            if version < 3 {
+                return Err(corrupt(format!("entry {i} uses extended flags in a version {version} index")));
+            }
+            // skip-worktree / intent-to-add live here; neither is supported, so they are dropped
+            path_start += 2;
+        }
+
+        let (path_bytes, entry_end) = if version == 4 {
+            let (strip, varint_len) = decode_varint(&content[path_start.min(content.len())..])
+                .ok_or_else(|| corrupt(format!("entry {i} has a truncated path prefix")))?;
+            let previous = entries.last().map(|e| e.path.as_bytes()).unwrap_or(b"");
+            if strip > previous.len() {
+                return Err(corrupt(format!("entry {i} strips more than the previous path")));
+            }
+            let suffix_start = path_start + varint_len;
+            let suffix_len = find_nul(content, suffix_start)
+                .ok_or_else(|| corrupt(format!("entry {i} path is not terminated")))?;
+            let mut path = previous[..previous.len() - strip].to_vec();
+            path.extend_from_slice(&content[suffix_start..suffix_start + suffix_len]);
+            (path, suffix_start + suffix_len + 1)
+        } else {
+            // the 12-bit length saturates at 0xFFF, longer names are found by their NUL
+            let name_len = (flags & NAME_MASK) as usize;
+            let path_len = if name_len < NAME_MASK as usize {
+                if content.get(path_start + name_len) != Some(&0) {
+                    return Err(corrupt(format!("entry {i} path length does not match its flags")));
+                }
+                name_len
+            } else {
+                find_nul(content, path_start).ok_or_else(|| corrupt(format!("entry {i} path is not terminated")))?
+            };
+            let entry_len = path_start - pos + path_len + 1;
+            let padding = (8 - (entry_len % 8)) % 8;
+            (content[path_start..path_start + path_len].to_vec(), pos + entry_len + padding)
+        };
 
-        let path = String::from_utf8(data[path_start..path_start + null_offset].to_vec()).unwrap();
+        if entry_end > content.len() {
+            return Err(corrupt(format!("entry {i} truncated")));
+        }
+        let path = String::from_utf8(path_bytes).map_err(|_| corrupt(format!("entry {i} has a non-UTF-8 path")))?;
 
-        entries.push(IndexEntry { sha, flags, path });
+        entries.push(IndexEntry {
+            stat,
+            mode,
+            sha,
+            flags: flags & !FLAG_EXTENDED,
+            path,
+        });
+        pos = entry_end;
+    }
 
-        let entry_len = 62 + null_offset + 1; // fixed + path + null
-        let padding = (8 - (entry_len % 8)) % 8;
-        pos += entry_len + padding;
+    // extensions: 4 byte signature, 4 byte length, payload
+    while pos < content.len() {
+        if pos + 8 > content.len() {
+            return Err(corrupt("extension header truncated"));
+        }
+        let signature = &content[pos..pos + 4];
+        let size = u32::from_be_bytes(content[pos + 4..pos + 8].try_into().unwrap()) as usize;
+        if pos + 8 + size > content.len() {
+            return Err(corrupt(format!("extension {} truncated", String::from_utf8_lossy(signature))));
+        }
+        // an uppercase first letter marks an extension that is safe to ignore
+        if !signature[0].is_ascii_uppercase() {
+            return Err(corrupt(format!(
+                "unsupported required extension {}",
+                String::from_utf8_lossy(signature)
+            )));
+        }
+        pos += 8 + size;
     }
 
     Ok(entries)
 }
 
-pub fn write_index(repo_path: &Path, entries: &[IndexEntry]) -> Result<(), std::io::Error> {
+fn find_nul(data: &[u8], start: usize) -> Option<usize> {
+    data.get(start..)?.iter().position(|&b| b == 0)
+}
+
+// The offset encoding git uses for v4 path prefixes and OFS_DELTA bases: each
+// continuation byte adds one before shifting, so there is only one encoding per value.
+pub fn decode_varint(data: &[u8]) -> Option<(usize, usize)> {
+    let mut i = 0;
+    let mut byte = *data.first()?;
+    let mut value = (byte & 0x7f) as usize;
+    while byte & 0x80 != 0 {
+        i += 1;
+        byte = *data.get(i)?;
+        value = ((value + 1) << 7) | (byte & 0x7f) as usize;
+    }
+    Some((value, i + 1))
+}
+
+pub fn encode_varint(mut value: usize) -> Vec<u8> {
+    let mut out = vec![(value & 0x7f) as u8];
+    value >>= 7;
+    while value != 0 {
+        value -= 1;
+        out.push(0x80 | (value & 0x7f) as u8);
+        value >>= 7;
+    }
+    out.reverse();
+    out
+}
+
+// Takes index.lock for a read-modify-write of the index, as git's
+// repo_hold_locked_index does. It must be held from before read_index until
+// write_locked_index, or two commands could both read the old index and the
+// later write would drop the other's changes; a second command fails with
+// ItError::Locked instead.
+pub fn lock_index(repo_path: &Path) -> Result<LockFile, ItError> {
+    LockFile::acquire(&repo_path.join("index"))
+}
+
+// Writes the index through the lock taken by lock_index, as version 2 unless
+// `index.version` asks for 3 or 4.
+pub fn write_locked_index(mut lock: LockFile, repo_path: &Path, entries: &[IndexEntry]) -> Result<(), ItError> {
+    let version = crate::config::Config::load(repo_path)?
+        .get_int("index.version")?
+        .map(|v| v.clamp(2, 4) as u32)
+        .unwrap_or(2);
+    lock.write_all(&encode_index(entries, version, ObjectFormat::current()))?;
+    lock.commit()?;
+    Ok(())
+}
+
+// Replaces the index with `entries` without looking at what it held.
+pub fn write_index(repo_path: &Path, entries: &[IndexEntry]) -> Result<(), ItError> {
+    write_locked_index(lock_index(repo_path)?, repo_path, entries)
+}
+
+fn encode_index(entries: &[IndexEntry], version: u32, format: ObjectFormat) -> Vec<u8> {
     let mut buf: Vec<u8> = Vec::new();
 
     buf.extend_from_slice(b"DIRC");
-    buf.extend_from_slice(&2u32.to_be_bytes());
+    buf.extend_from_slice(&version.to_be_bytes());
     buf.extend_from_slice(&(entries.len() as u32).to_be_bytes());
 
+    let now = split_time(SystemTime::now());
+    let mut previous_path: &str = "";
+
     for entry in entries {
-        buf.extend_from_slice(&[0u8; 8]); // ctime
-        buf.extend_from_slice(&[0u8; 8]); // mtime
-        buf.extend_from_slice(&[0u8; 4]); // dev
-        buf.extend_from_slice(&[0u8; 4]); // ino
-        buf.extend_from_slice(&0o100644u32.to_be_bytes()); // mode
-        buf.extend_from_slice(&[0u8; 4]); // uid
-        buf.extend_from_slice(&[0u8; 4]); // gid
-        buf.extend_from_slice(&[0u8; 4]); // size
-        buf.extend_from_slice(&entry.sha); // sha1 (20 bytes)
-        buf.extend_from_slice(&entry.flags.to_be_bytes());
-        buf.extend_from_slice(entry.path.as_bytes());
-        buf.push(0);
-
-        let entry_len = 62 + entry.path.len() + 1;
-        let padding = (8 - (entry_len % 8)) % 8;
-        buf.extend_from_slice(&vec![0u8; padding]);
-    }
-
-    let checksum = Sha1::digest(&buf);
-    buf.extend_from_slice(&checksum);
-
-    fs::write(repo_path.join("index"), buf)?;
-    Ok(())
+        let stat = &entry.stat;
+        // Smudge entries that are racily clean relative to this write: a zero
+        // size never matches a non-empty file, so a later read, when the index
+        // is no longer newer than the file, still rehashes it.
+        let size = if (stat.mtime_sec, stat.mtime_nsec) >= (now.0, 0) { 0 } else { stat.size };
+        let name_len = entry.path.len().min(NAME_MASK as usize) as u16;
+        let flags = (entry.flags & !(NAME_MASK | FLAG_EXTENDED)) | name_len;
+
+        buf.extend_from_slice(&stat.ctime_sec.to_be_bytes()); // ctime
+        buf.extend_from_slice(&stat.ctime_nsec.to_be_bytes());
+        buf.extend_from_slice(&stat.mtime_sec.to_be_bytes()); // mtime
+        buf.extend_from_slice(&stat.mtime_nsec.to_be_bytes());
+        buf.extend_from_slice(&stat.dev.to_be_bytes()); // dev
+        buf.extend_from_slice(&stat.ino.to_be_bytes()); // ino
+        buf.extend_from_slice(&entry.mode.to_be_bytes()); // mode
+        buf.extend_from_slice(&stat.uid.to_be_bytes()); // uid
+        buf.extend_from_slice(&stat.gid.to_be_bytes()); // gid
+        buf.extend_from_slice(&size.to_be_bytes()); // size
+        buf.extend_from_slice(entry.sha.as_bytes()); // object id
+        buf.extend_from_slice(&flags.to_be_bytes());
+
+        if version == 4 {
+            let common = previous_path
+                .bytes()
+                .zip(entry.path.bytes())
+                .take_while(|(a, b)| a == b)
+                .count();
+            buf.extend_from_slice(&encode_varint(previous_path.len() - common));
+            buf.extend_from_slice(&entry.path.as_bytes()[common..]);
+            buf.push(0);
+            previous_path = &entry.path;
+        } else {
+            buf.extend_from_slice(entry.path.as_bytes());
+            buf.push(0);
+
+            let entry_len = entry_fixed_len(format) + entry.path.len() + 1;
+            let padding = (8 - (entry_len % 8)) % 8;
+            buf.extend_from_slice(&vec![0u8; padding]);
+        }
+    }
+
+    let checksum = format.digest(&buf);
+    buf.extend_from_slice(checksum.as_bytes());
+    buf
+}
+
+// Whether the worktree file at `path` still holds what `entry` records, content
+// and mode, trusting the cached stat data before falling back to rehashing.
+pub fn worktree_matches(path: &Path, entry: &IndexEntry, check: &StatCheck, lfs: &Lfs) -> io::Result<bool> {
+    let meta = fs::symlink_metadata(path)?;
+    if check.is_unchanged(entry, &meta) {
+        return Ok(true);
+    }
+    let (hash, disk_mode) = hash_worktree_blob(path, lfs, false)?;
+    let mode = effective_mode(disk_mode, Some(entry.mode), check.trust_filemode);
+    Ok(mode == entry.mode && hash == entry.sha)
+}
+
+// Index paths are '/'-separated and relative to the worktree root, so "./src//a.rs"
+// and "src/a.rs" name the same entry. An empty string means the root itself.
+pub fn index_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|c| match c {
+            std::path::Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+            std::path::Component::ParentDir => Some("..".to_string()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// Reads a worktree path the way it is stored as a blob: a symlink's content is
+// its target, not the file it points to.
+pub fn read_worktree_blob(path: &Path) -> io::Result<(Vec<u8>, u32)> {
+    let meta = fs::symlink_metadata(path)?;
+    if meta.file_type().is_symlink() {
+        let target = fs::read_link(path)?;
+        return Ok((target.to_string_lossy().replace('\\', "/").into_bytes(), MODE_SYMLINK));
+    }
+    Ok((fs::read(path)?, file_mode(&meta)))
+}
+
+// The blob hash and mode of a worktree path, with `store` also writing the
+// blob. Files are streamed rather than read whole, so their size does not
+// matter; files tracked by lfs are hashed as their pointer.
+pub fn hash_worktree_blob(path: &Path, lfs: &Lfs, store: bool) -> io::Result<(ObjectId, u32)> {
+    let meta = fs::symlink_metadata(path)?;
+    let (data, mode) = if meta.file_type().is_symlink() {
+        read_worktree_blob(path)?
+    } else if lfs.tracks(path) {
+        (lfs.clean(path, store)?, file_mode(&meta))
+    } else {
+        return Ok((hash_file(path, store)?, file_mode(&meta)));
+    };
+    let object = build_object(&data, "blob");
+    let id = ObjectFormat::current().digest(&object);
+    if store {
+        store_object(&id.to_hex(), &compress_data(&object)?)?;
+    }
+    Ok((id, mode))
+}
+
+pub fn file_mode(meta: &fs::Metadata) -> u32 {
+    if meta.file_type().is_symlink() {
+        return MODE_SYMLINK;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if meta.permissions().mode() & 0o111 != 0 {
+            return MODE_EXECUTABLE;
+        }
+    }
+    MODE_FILE
+}
+
+// With core.filemode=false the executable bit on disk is not trusted, so a
+// regular file keeps whichever of 100644/100755 the index already recorded.
+pub fn effective_mode(disk_mode: u32, index_mode: Option<u32>, trust_filemode: bool) -> u32 {
+    let regular = |m: u32| m == MODE_FILE || m == MODE_EXECUTABLE;
+    match index_mode {
+        Some(old) if !trust_filemode && regular(old) && regular(disk_mode) => old,
+        None if !trust_filemode && regular(disk_mode) => MODE_FILE,
+        _ => disk_mode,
+    }
+}
+
+// Writes blob content back to the worktree as a file, executable or symlink,
+// replacing whatever is at `path`. File content is copied from `content` as
+// it is read, into a temporary file that is then renamed over `path`.
+pub fn write_worktree_file(path: &Path, mode: u32, content: &mut dyn Read) -> io::Result<()> {
+    let replace = |meta: fs::Metadata| meta.file_type().is_symlink() || (mode == MODE_SYMLINK && !meta.is_dir());
+    if fs::symlink_metadata(path).is_ok_and(replace) {
+        fs::remove_file(path)?;
+    }
+
+    if mode == MODE_SYMLINK {
+        let mut data = Vec::new();
+        content.read_to_end(&mut data)?;
+        let target = String::from_utf8_lossy(&data).to_string();
+        #[cfg(unix)]
+        return std::os::unix::fs::symlink(target, path);
+        // no portable symlinks, check the target out as a plain file like git does
+        #[cfg(not(unix))]
+        return fs::write(path, target);
+    }
+
+    // a read that fails partway must not leave a truncated file behind, so
+    // the content goes to a temporary file beside `path` first
+    let dir = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut temp = TempFile::create(dir, ".it_tmp_")?;
+    io::copy(content, &mut temp)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = temp.as_file().metadata()?.permissions();
+        let exec = if mode == MODE_EXECUTABLE { 0o111 } else { 0 };
+        // keep the umask's read/write bits, only toggle the executable bits
+        perms.set_mode((perms.mode() & 0o666) | (exec & ((perms.mode() & 0o444) >> 2)));
+        temp.as_file().set_permissions(perms)?;
+    }
+    temp.replace(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, mode: u32, stage: u16, format: ObjectFormat) -> IndexEntry {
+        IndexEntry {
+            stat: StatData {
+                ctime_sec: 1_600_000_000,
+                ctime_nsec: 1,
+                mtime_sec: 1_600_000_000,
+                mtime_nsec: 2,
+                dev: 3,
+                ino: 4,
+                uid: 5,
+                gid: 6,
+                size: 7,
+            },
+            mode,
+            sha: format.digest(path.as_bytes()),
+            flags: stage << 12,
+            path: path.to_string(),
+        }
+    }
+
+    // shared prefixes for v4, a conflict and a path too long for the 12-bit
+    // name length
+    fn sample(format: ObjectFormat) -> Vec<IndexEntry> {
+        let long = format!("deep/{}", "x".repeat(5000));
+        vec![
+            entry(".itignore", MODE_FILE, 0, format),
+            entry(&long, MODE_FILE, 0, format),
+            entry("src/index.rs", MODE_FILE, 0, format),
+            entry("src/index_pack.rs", MODE_EXECUTABLE, 0, format),
+            entry("src/link", MODE_SYMLINK, 0, format),
+            entry("z", MODE_FILE, 1, format),
+            entry("z", MODE_FILE, 2, format),
+            entry("z", MODE_FILE, 3, format),
+        ]
+    }
+
+    fn assert_same(read: &[IndexEntry], written: &[IndexEntry]) {
+        assert_eq!(read.len(), written.len());
+        for (a, b) in read.iter().zip(written) {
+            assert_eq!((&a.path, a.mode, a.sha, a.stage(), a.stat), (&b.path, b.mode, b.sha, b.stage(), b.stat));
+        }
+    }
+
+    #[test]
+    fn index_round_trip_each_version() {
+        let entries = sample(ObjectFormat::Sha1);
+        let mut sizes = Vec::new();
+        for version in 2..=4 {
+            let data = encode_index(&entries, version, ObjectFormat::Sha1);
+            assert_eq!(data[4..8], version.to_be_bytes());
+            assert_same(&decode_index(&data, ObjectFormat::Sha1).unwrap(), &entries);
+            sizes.push(data.len());
+        }
+        assert!(sizes[2] < sizes[0], "v4 did not compress paths: {sizes:?}");
+    }
+
+    #[test]
+    fn index_extended_flags_need_version_3() {
+        let format = ObjectFormat::Sha1;
+        let mut data = b"DIRC".to_vec();
+        data.extend_from_slice(&3u32.to_be_bytes());
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(&[0; 24]);
+        data.extend_from_slice(&MODE_FILE.to_be_bytes());
+        data.extend_from_slice(&[0; 12]);
+        data.extend_from_slice(format.digest(b"a").as_bytes());
+        data.extend_from_slice(&(FLAG_EXTENDED | 1).to_be_bytes());
+        // the second flags word, then the path padded to a multiple of 8
+        data.extend_from_slice(&[0x20, 0]);
+        data.extend_from_slice(b"a\0\0\0\0\0\0\0");
+        let checksum = format.digest(&data);
+        data.extend_from_slice(checksum.as_bytes());
+
+        let entries = decode_index(&data, format).unwrap();
+        assert_eq!((entries[0].path.as_str(), entries[0].flags & FLAG_EXTENDED), ("a", 0));
+
+        data[7] = 2;
+        let len = data.len() - 20;
+        let checksum = format.digest(&data[..len]);
+        data[len..].copy_from_slice(checksum.as_bytes());
+        assert!(decode_index(&data, format).is_err());
+    }
+
+    #[test]
+    fn index_rejects_damage() {
+        let data = encode_index(&sample(ObjectFormat::Sha1), 2, ObjectFormat::Sha1);
+        let mut flipped = data.clone();
+        flipped[100] ^= 1;
+        assert!(decode_index(&flipped, ObjectFormat::Sha1).is_err());
+        assert!(decode_index(&data[..data.len() / 2], ObjectFormat::Sha1).is_err());
+    }
+
+    #[test]
+    fn sha256_index_round_trip() {
+        let entries = sample(ObjectFormat::Sha256);
+        assert_eq!(entries[0].sha.as_bytes().len(), 32);
+        for version in 2..=4 {
+            let data = encode_index(&entries, version, ObjectFormat::Sha256);
+            assert_same(&decode_index(&data, ObjectFormat::Sha256).unwrap(), &entries);
+            // the longer ids and trailer do not parse as SHA-1
+            assert!(decode_index(&data, ObjectFormat::Sha1).is_err());
+        }
+    }
+
+    #[test]
+    fn stat_check_trusts_only_settled_entries() {
+        let dir = std::env::temp_dir().join(format!("it-index-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("file");
+        fs::write(&file, "content").unwrap();
+        let meta = fs::symlink_metadata(&file).unwrap();
+        fs::write(dir.join("index"), "").unwrap();
+        let config = crate::config::Config {
+            entries: ["core.checkstat=Minimal", "core.trustctime=false", "core.filemode=false"]
+                .iter()
+                .map(|kv| {
+                    let (key, value) = kv.split_once('=').unwrap();
+                    crate::config::ConfigEntry { key: key.to_string(), value: Some(value.to_string()) }
+                })
+                .collect(),
+        };
+        let loaded = StatCheck::load(&dir, &config).unwrap();
+        let unwritten = StatCheck::load(&dir.join("missing"), &crate::config::Config::default()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(loaded.index_mtime.is_some() && loaded.minimal && !loaded.trust_ctime && !loaded.trust_filemode);
+        assert!(unwritten.index_mtime.is_none() && !unwritten.minimal && unwritten.trust_ctime);
+
+        let stat = StatData::from_metadata(&meta);
+        let cached = |change: &dyn Fn(&mut IndexEntry)| {
+            let mut entry = entry("file", file_mode(&meta), 0, ObjectFormat::Sha1);
+            entry.stat = stat;
+            change(&mut entry);
+            entry
+        };
+        let check = |index_mtime: Option<(u32, u32)>, trust_ctime: bool, minimal: bool| StatCheck {
+            index_mtime,
+            trust_ctime,
+            trust_filemode: true,
+            minimal,
+        };
+        let settled = check(Some((stat.mtime_sec + 1, 0)), true, false);
+        assert!(settled.is_unchanged(&cached(&|_| {}), &meta));
+
+        // racily clean: written in the same granule as the index, or no index at all
+        let same_granule = check(Some((stat.mtime_sec, stat.mtime_nsec)), true, false);
+        assert!(!same_granule.is_unchanged(&cached(&|_| {}), &meta));
+        assert!(!check(None, true, false).is_unchanged(&cached(&|_| {}), &meta));
+
+        assert!(!settled.is_unchanged(&cached(&|e| e.stat.size += 1), &meta));
+        assert!(!settled.is_unchanged(&cached(&|e| e.stat.mtime_sec -= 1), &meta));
+        assert!(!settled.is_unchanged(&cached(&|e| e.mode = MODE_SYMLINK), &meta));
+        assert!(!settled.is_unchanged(&cached(&|e| e.stat.ino ^= 1), &meta));
+
+        // core.trustctime=false ignores ctime, core.checkStat=minimal ignores
+        // everything but mtime seconds, size and mode
+        let ctime_changed = cached(&|e| e.stat.ctime_sec ^= 1);
+        assert!(!settled.is_unchanged(&ctime_changed, &meta));
+        assert!(check(Some((stat.mtime_sec + 1, 0)), false, false).is_unchanged(&ctime_changed, &meta));
+        let minimal = check(Some((stat.mtime_sec + 1, 0)), true, true);
+        assert!(minimal.is_unchanged(&cached(&|e| {
+            e.stat.ctime_sec ^= 1;
+            e.stat.mtime_nsec ^= 1;
+            e.stat.ino ^= 1;
+        }), &meta));
+        assert!(!minimal.is_unchanged(&cached(&|e| e.stat.size += 1), &meta));
+
+        // without core.filemode an executable bit flip is not a change
+        let flipped = cached(&|e| e.mode = if e.mode == MODE_FILE { MODE_EXECUTABLE } else { MODE_FILE });
+        assert!(!settled.is_unchanged(&flipped, &meta));
+        let no_filemode = StatCheck { trust_filemode: false, ..check(Some((stat.mtime_sec + 1, 0)), true, false) };
+        assert!(no_filemode.is_unchanged(&flipped, &meta));
+    }
 }
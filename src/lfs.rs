@@ -0,0 +1,244 @@
+// Large-file storage, compatible with git-lfs. A file whose path has the
+// attribute `filter=lfs` is stored in blobs and trees as a small pointer:
+//
+//      version https://git-lfs.github.com/spec/v1
+//      oid sha256:<SHA-256 of the content>
+//      size <content length>
+//
+// while the content itself goes to .it/lfs/objects/<oid[0..2]>/<oid[2..4]>/<oid>.
+// Checking such a path out writes the content back in place of the pointer.
+//
+// `lfs.url` names a directory (a path or file:// URL) laid out like
+// .it/lfs/objects that serves as the remote store: `it lfs push` copies
+// objects there and objects missing locally are fetched from it.
+use crate::{
+    attributes::{AttrValue, Attributes},
+    commands::hash_object::{CHUNK_SIZE, open_object, read_object},
+    config::Config,
+    error::ItError,
+    index::index_path,
+    lockfile::TempFile,
+    tree::find_path,
+};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+pub const POINTER_VERSION: &str = "https://git-lfs.github.com/spec/v1";
+// pointers are a few lines of text; larger blobs are never parsed as one
+pub const POINTER_MAX_SIZE: u64 = 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pointer {
+    pub oid: String,
+    pub size: u64,
+}
+
+impl Pointer {
+    pub fn parse(data: &[u8]) -> Option<Pointer> {
+        if data.len() as u64 > POINTER_MAX_SIZE {
+            return None;
+        }
+        let text = std::str::from_utf8(data).ok()?;
+        let mut lines = text.lines();
+        if lines.next()? != format!("version {POINTER_VERSION}") {
+            return None;
+        }
+        let (mut oid, mut size) = (None, None);
+        for line in lines {
+            let (key, value) = line.split_once(' ')?;
+            match key {
+                "oid" => oid = value.strip_prefix("sha256:").filter(|o| is_oid(o)).map(str::to_string),
+                "size" => size = value.parse().ok(),
+                _ => {}
+            }
+        }
+        Some(Pointer { oid: oid?, size: size? })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        format!("version {POINTER_VERSION}\noid sha256:{}\nsize {}\n", self.oid, self.size).into_bytes()
+    }
+}
+
+pub struct Lfs {
+    worktree: PathBuf,
+    repo_path: PathBuf,
+    pub attributes: Attributes,
+    remote: Option<PathBuf>,
+}
+
+impl Lfs {
+    pub fn load(worktree: &Path, repo_path: &Path, config: &Config) -> Result<Lfs, ItError> {
+        let remote = config.get("lfs.url").map(|url| {
+            let path = url.strip_prefix("file://").unwrap_or(url);
+            crate::config::expand_home(path)
+        });
+        Ok(Lfs {
+            worktree: worktree.to_path_buf(),
+            repo_path: repo_path.to_path_buf(),
+            attributes: Attributes::load(worktree, repo_path)?,
+            remote,
+        })
+    }
+
+    // Switches to the rules of the `.itattributes` in the tree about to be
+    // checked out, so its files are smudged by the rules they were committed
+    // with rather than those of the current worktree.
+    pub fn use_tree_attributes(&mut self, tree_hash: &str) -> Result<(), ItError> {
+        match find_path(tree_hash, ".itattributes")? {
+            Some((mode, hash)) if mode != "40000" && mode != "040000" => {
+                let (_, body) = read_object(&hash)?;
+                self.attributes.set_worktree_rules(&String::from_utf8_lossy(&body));
+            }
+            _ => self.attributes.set_worktree_rules(""),
+        }
+        Ok(())
+    }
+
+    pub fn remote(&self) -> Option<&Path> {
+        self.remote.as_deref()
+    }
+
+    // Whether the file at `path`, absolute or relative to the worktree, is
+    // stored as a pointer.
+    pub fn tracks(&self, path: &Path) -> bool {
+        let rel = path.strip_prefix(&self.worktree).unwrap_or(path);
+        self.tracks_path(&index_path(rel))
+    }
+
+    pub fn tracks_path(&self, path: &str) -> bool {
+        self.attributes.get(path, "filter") == AttrValue::Value("lfs".to_string())
+    }
+
+    pub fn object_path(&self, oid: &str) -> PathBuf {
+        object_path(&self.repo_path.join("lfs/objects"), oid)
+    }
+
+    pub fn has_object(&self, oid: &str) -> bool {
+        self.object_path(oid).is_file()
+    }
+
+    // The pointer blob for the file at `path`, with `store` also moving its
+    // content into the local store. The file is streamed, never read whole.
+    // A file that already holds a pointer, as left by a checkout whose
+    // object was missing, stays a pointer.
+    pub fn clean(&self, path: &Path, store: bool) -> io::Result<Vec<u8>> {
+        let mut file = fs::File::open(path)?;
+        if file.metadata()?.len() <= POINTER_MAX_SIZE {
+            let mut data = Vec::new();
+            file.read_to_end(&mut data)?;
+            if Pointer::parse(&data).is_some() {
+                return Ok(data);
+            }
+            file = fs::File::open(path)?;
+        }
+
+        let mut temp = match store {
+            true => Some(TempFile::create(&self.repo_path.join("lfs/tmp"), "")?),
+            false => None,
+        };
+        let (oid, size) = copy_hashed(&mut file, temp.as_mut().map(|t| t as &mut dyn Write))?;
+        if let Some(temp) = temp {
+            temp.install(&self.object_path(&oid))?;
+        }
+        Ok(Pointer { oid, size }.to_bytes())
+    }
+
+    // Opens the content `pointer` stands for, fetching it from the remote
+    // store when it is not available locally. None when neither has it.
+    pub fn smudge(&self, pointer: &Pointer) -> Result<Option<fs::File>, ItError> {
+        if !self.has_object(&pointer.oid) && !self.fetch(&pointer.oid)? {
+            return Ok(None);
+        }
+        Ok(Some(fs::File::open(self.object_path(&pointer.oid))?))
+    }
+
+    // Copies an object from the remote store into the local one, checking
+    // its hash on the way. False when there is no remote or it lacks the
+    // object.
+    pub fn fetch(&self, oid: &str) -> Result<bool, ItError> {
+        let Some(remote) = &self.remote else {
+            return Ok(false);
+        };
+        let source = object_path(remote, oid);
+        if !source.is_file() {
+            return Ok(false);
+        }
+        copy_object(&source, oid, &self.object_path(oid), &self.repo_path.join("lfs/tmp"))?;
+        Ok(true)
+    }
+
+    // Copies a local object to the remote store unless it is already there.
+    // Returns whether anything was copied.
+    pub fn push(&self, oid: &str) -> Result<bool, ItError> {
+        let Some(remote) = &self.remote else {
+            return Err(ItError::Fatal("no lfs remote store; set lfs.url".to_string()));
+        };
+        let dest = object_path(remote, oid);
+        if dest.is_file() {
+            return Ok(false);
+        }
+        copy_object(&self.object_path(oid), oid, &dest, &remote.join("tmp"))?;
+        Ok(true)
+    }
+
+    // Whether the local copy of an object still hashes to its oid.
+    pub fn verify(&self, pointer: &Pointer) -> io::Result<bool> {
+        let mut file = fs::File::open(self.object_path(&pointer.oid))?;
+        let (oid, size) = copy_hashed(&mut file, None)?;
+        Ok(oid == pointer.oid && size == pointer.size)
+    }
+}
+
+// The pointer a blob holds, if it is one.
+pub fn read_pointer(hash: &str) -> Result<Option<Pointer>, ItError> {
+    let (obj_type, size, mut content) = open_object(hash)?;
+    if obj_type != "blob" || size > POINTER_MAX_SIZE {
+        return Ok(None);
+    }
+    let mut data = Vec::new();
+    content.read_to_end(&mut data)?;
+    Ok(Pointer::parse(&data))
+}
+
+pub fn object_path(store: &Path, oid: &str) -> PathBuf {
+    store.join(&oid[0..2]).join(&oid[2..4]).join(oid)
+}
+
+fn is_oid(s: &str) -> bool {
+    s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase())
+}
+
+// Streams `source` through SHA-256, copying it to `out` when given, and
+// returns the hex digest and the length.
+fn copy_hashed(source: &mut dyn Read, mut out: Option<&mut dyn Write>) -> io::Result<(String, u64)> {
+    let mut hasher = Sha256::new();
+    let mut size = 0u64;
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        let n = source.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        if let Some(out) = out.as_mut() {
+            out.write_all(&buf[..n])?;
+        }
+        size += n as u64;
+    }
+    Ok((hex::encode(hasher.finalize()), size))
+}
+
+// Copies one store's object into another through a temporary file, refusing
+// content that does not match its oid.
+fn copy_object(source: &Path, oid: &str, dest: &Path, tmp_dir: &Path) -> Result<(), ItError> {
+    let mut temp = TempFile::create(tmp_dir, "")?;
+    let (actual, _) = copy_hashed(&mut fs::File::open(source)?, Some(&mut temp))?;
+    if actual != oid {
+        return Err(ItError::CorruptObject(format!("{}: lfs object hashes to {actual}", source.display())));
+    }
+    temp.install(dest)?;
+    Ok(())
+}
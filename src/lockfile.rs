@@ -0,0 +1,237 @@
+use crate::error::ItError;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// git-style lock: `<path>.lock` is created exclusively, filled, fsynced and
+// then renamed over `<path>`. Whoever holds the .lock owns the file; dropping
+// the lock without committing removes it and leaves `<path>` untouched.
+pub struct LockFile {
+    path: PathBuf,
+    lock_path: PathBuf,
+    file: Option<File>,
+}
+
+impl LockFile {
+    pub fn acquire(path: &Path) -> Result<LockFile, ItError> {
+        let mut lock_name = path.file_name().unwrap_or_default().to_os_string();
+        lock_name.push(".lock");
+        let lock_path = path.with_file_name(lock_name);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = match OpenOptions::new().write(true).create_new(true).open(&lock_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(ItError::Locked(lock_path));
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(LockFile {
+            path: path.to_path_buf(),
+            lock_path,
+            file: Some(file),
+        })
+    }
+
+    pub fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        self.file.as_mut().expect("lock already committed").write_all(data)
+    }
+
+    pub fn commit(mut self) -> io::Result<()> {
+        // on a failed sync the lock is still held and Drop cleans it up
+        self.file.as_ref().expect("lock already committed").sync_all()?;
+        self.file = None;
+        let result = fs::rename(&self.lock_path, &self.path);
+        if result.is_err() {
+            let _ = fs::remove_file(&self.lock_path);
+        }
+        result
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.lock_path);
+        }
+    }
+}
+
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<(), ItError> {
+    let mut lock = LockFile::acquire(path)?;
+    lock.write_all(data)?;
+    lock.commit()?;
+    Ok(())
+}
+
+// A temporary file that is renamed into place once complete, or removed
+// again when dropped, so a reader never sees a partial file.
+pub struct TempFile {
+    path: PathBuf,
+    file: File,
+    installed: bool,
+}
+
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+impl TempFile {
+    // A new file named `<prefix><pid>_<n>` in `dir`.
+    pub fn create(dir: &Path, prefix: &str) -> io::Result<TempFile> {
+        fs::create_dir_all(dir)?;
+        loop {
+            let n = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+            let path = dir.join(format!("{prefix}{}_{n}", std::process::id()));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => return Ok(TempFile { path, file, installed: false }),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub fn as_file(&self) -> &File {
+        &self.file
+    }
+
+    // For content-addressed stores: an existing file at `dest` is kept, as
+    // the same name means the same content. The data is synced first.
+    pub fn install(mut self, dest: &Path) -> io::Result<()> {
+        if dest.exists() {
+            return Ok(());
+        }
+        self.file.sync_all()?;
+        self.rename(dest)
+    }
+
+    // Replaces whatever is at `dest`.
+    pub fn replace(mut self, dest: &Path) -> io::Result<()> {
+        self.rename(dest)
+    }
+
+    fn rename(&mut self, dest: &Path) -> io::Result<()> {
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(&self.path, dest)?;
+        self.installed = true;
+        Ok(())
+    }
+}
+
+impl Write for TempFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.installed {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("it-lockfile-test-{}-{name}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn lock_is_exclusive_and_commit_replaces() {
+        let dir = scratch("lock");
+        let path = dir.join("sub/file");
+        let mut lock = LockFile::acquire(&path).unwrap();
+        assert!(dir.join("sub/file.lock").exists());
+        assert!(matches!(LockFile::acquire(&path), Err(ItError::Locked(p)) if p == dir.join("sub/file.lock")));
+        assert!(matches!(write_atomic(&path, b"other"), Err(ItError::Locked(_))));
+
+        lock.write_all(b"new").unwrap();
+        assert!(!path.exists());
+        lock.commit().unwrap();
+        let content = fs::read(&path).unwrap();
+        let lock_left = dir.join("sub/file.lock").exists();
+        write_atomic(&path, b"again").unwrap();
+        let again = fs::read(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(content, b"new");
+        assert!(!lock_left);
+        assert_eq!(again, b"again");
+    }
+
+    #[test]
+    fn dropped_lock_leaves_the_file_alone() {
+        let dir = scratch("drop");
+        let path = dir.join("file");
+        fs::write(&path, "old").unwrap();
+        {
+            let mut lock = LockFile::acquire(&path).unwrap();
+            lock.write_all(b"half written").unwrap();
+        }
+        let content = fs::read(&path).unwrap();
+        let lock_left = dir.join("file.lock").exists();
+        // the lock is free again
+        let relocked = LockFile::acquire(&path).is_ok();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(content, b"old");
+        assert!(!lock_left);
+        assert!(relocked);
+    }
+
+    #[test]
+    fn failed_rename_releases_the_lock() {
+        let dir = scratch("rename");
+        // a non-empty directory cannot be replaced by a file
+        let path = dir.join("target");
+        fs::create_dir_all(path.join("inside")).unwrap();
+        let lock = LockFile::acquire(&path).unwrap();
+        let result = lock.commit();
+        let lock_left = dir.join("target.lock").exists();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(result.is_err());
+        assert!(!lock_left);
+    }
+
+    #[test]
+    fn temp_files_are_installed_or_removed() {
+        let dir = scratch("temp");
+        let mut dropped = TempFile::create(&dir, "tmp_").unwrap();
+        dropped.write_all(b"never installed").unwrap();
+        drop(dropped);
+        let left_after_drop = fs::read_dir(&dir).unwrap().count();
+
+        let mut first = TempFile::create(&dir, "tmp_").unwrap();
+        first.write_all(b"first").unwrap();
+        first.install(&dir.join("objects/ab")).unwrap();
+        // the same name means the same content, so the first copy stays
+        let mut second = TempFile::create(&dir, "tmp_").unwrap();
+        second.write_all(b"second").unwrap();
+        second.install(&dir.join("objects/ab")).unwrap();
+        let installed = fs::read(dir.join("objects/ab")).unwrap();
+
+        let mut replacing = TempFile::create(&dir, "tmp_").unwrap();
+        replacing.write_all(b"replaced").unwrap();
+        replacing.replace(&dir.join("objects/ab")).unwrap();
+        let replaced = fs::read(dir.join("objects/ab")).unwrap();
+        let names: Vec<_> = fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name()).collect();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(left_after_drop, 0);
+        assert_eq!(installed, b"first");
+        assert_eq!(replaced, b"replaced");
+        assert_eq!(names, vec![std::ffi::OsString::from("objects")]);
+    }
+}
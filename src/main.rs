@@ -1,20 +1,61 @@
-#![allow(warnings)]
 use clap::{Parser, Subcommand};
 
-use crate::commands::log::log_commit;
 mod commands;
 mod error;
 mod index;
 mod color_utils;
+mod attributes;
+mod bitmap;
+mod config;
+mod diff;
+mod ignore;
+mod lfs;
+mod lockfile;
+mod midx;
+mod object_id;
+mod pack;
+mod pathspec;
+mod reachable;
+mod refs;
+mod repository;
+mod transport;
+mod tree;
 #[derive(Parser)]
 pub struct Cli {
+    // run as if started in <path>; several apply one after another
+    #[arg(short = 'C', value_name = "path")]
+    pub directory: Vec<std::path::PathBuf>,
+    #[arg(long, value_name = "path")]
+    pub git_dir: Option<std::path::PathBuf>,
+    #[arg(long, value_name = "path")]
+    pub work_tree: Option<std::path::PathBuf>,
     #[command(subcommand)]
     pub command: Commands,
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
-    Init,
+    Init {
+        directory: Option<std::path::PathBuf>,
+        #[arg(long)]
+        bare: bool,
+        #[arg(short = 'b', long, value_name = "name")]
+        initial_branch: Option<String>,
+        #[arg(long, value_name = "sha1|sha256")]
+        object_format: Option<String>,
+        #[arg(long, value_name = "dir")]
+        template: Option<std::path::PathBuf>,
+    },
+    Clone {
+        source: String,
+        directory: Option<std::path::PathBuf>,
+        #[arg(long)]
+        bare: bool,
+        #[arg(short = 'b', long, value_name = "name")]
+        branch: Option<String>,
+        #[arg(long, value_name = "depth")]
+        depth: Option<usize>,
+    },
     Branch {
         name: Option<String>,
     },
@@ -23,6 +64,14 @@ pub enum Commands {
     },
     Add {
         paths: Vec<String>,
+        #[arg(short, long)]
+        force: bool,
+        #[arg(short = 'A', long, conflicts_with = "update")]
+        all: bool,
+        #[arg(short, long)]
+        update: bool,
+        #[arg(short = 'n', long)]
+        dry_run: bool,
     },
     Log,
     Commit {
@@ -30,16 +79,249 @@ pub enum Commands {
         message: String,
     },
     Reset,
+    Config {
+        #[arg(long)]
+        global: bool,
+        #[arg(long, conflicts_with_all = ["unset", "list"])]
+        get: bool,
+        #[arg(long, conflicts_with = "list")]
+        unset: bool,
+        #[arg(short, long)]
+        list: bool,
+        key: Option<String>,
+        value: Option<String>,
+    },
+    Status,
+    Clean {
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+        #[arg(short, long)]
+        force: bool,
+        #[arg(short)]
+        d: bool,
+        #[arg(short)]
+        x: bool,
+        #[arg(short = 'X')]
+        only_ignored: bool,
+    },
+    Rm {
+        #[arg(required = true)]
+        paths: Vec<String>,
+        #[arg(long)]
+        cached: bool,
+        #[arg(short)]
+        r: bool,
+        #[arg(short, long)]
+        force: bool,
+    },
+    Mv {
+        source: String,
+        destination: String,
+        #[arg(short, long)]
+        force: bool,
+    },
+    Restore {
+        #[arg(required = true)]
+        paths: Vec<String>,
+        #[arg(short = 'S', long)]
+        staged: bool,
+        #[arg(short = 'W', long)]
+        worktree: bool,
+        #[arg(short, long)]
+        source: Option<String>,
+    },
+    Show {
+        objects: Vec<String>,
+        #[arg(long, conflicts_with = "name_only")]
+        stat: bool,
+        #[arg(long)]
+        name_only: bool,
+    },
+    LsFiles {
+        paths: Vec<String>,
+        #[arg(short, long)]
+        stage: bool,
+        #[arg(short, long)]
+        cached: bool,
+        #[arg(short, long)]
+        modified: bool,
+        #[arg(short, long)]
+        deleted: bool,
+        #[arg(short, long)]
+        others: bool,
+        #[arg(long)]
+        exclude_standard: bool,
+        #[arg(short)]
+        z: bool,
+    },
+    LsTree {
+        tree_ish: String,
+        paths: Vec<String>,
+        #[arg(short)]
+        r: bool,
+        #[arg(short)]
+        t: bool,
+        #[arg(long)]
+        name_only: bool,
+        #[arg(short)]
+        z: bool,
+    },
+    WriteTree,
+    CommitTree {
+        tree: String,
+        #[arg(short)]
+        p: Vec<String>,
+        #[arg(short)]
+        m: Vec<String>,
+    },
+    UpdateRef {
+        #[arg(short)]
+        d: bool,
+        name: String,
+        new: Option<String>,
+        old: Option<String>,
+    },
+    SymbolicRef {
+        #[arg(long)]
+        short: bool,
+        #[arg(short)]
+        q: bool,
+        name: String,
+        target: Option<String>,
+    },
+    ReadTree {
+        trees: Vec<String>,
+        #[arg(long)]
+        empty: bool,
+        #[arg(short)]
+        m: bool,
+        #[arg(long, requires = "m")]
+        aggressive: bool,
+    },
+    Fsck {
+        #[arg(long)]
+        full: bool,
+        #[arg(long)]
+        unreachable: bool,
+        #[arg(long)]
+        lost_found: bool,
+    },
+    Gc {
+        #[arg(long, value_name = "date")]
+        prune: Option<String>,
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+    Repack {
+        #[arg(short = 'a')]
+        all: bool,
+        #[arg(short = 'd')]
+        delete: bool,
+        #[arg(long, default_value_t = 10)]
+        window: usize,
+        #[arg(long, default_value_t = 50)]
+        depth: usize,
+    },
+    IndexPack {
+        pack: std::path::PathBuf,
+    },
+    VerifyPack {
+        #[arg(short, long)]
+        verbose: bool,
+        #[arg(required = true)]
+        idx: Vec<std::path::PathBuf>,
+    },
+    Lfs {
+        #[command(subcommand)]
+        action: LfsAction,
+    },
+    MultiPackIndex {
+        #[command(subcommand)]
+        action: MultiPackIndexAction,
+    },
+    Prune {
+        #[arg(long, value_name = "time")]
+        expire: Option<String>,
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+    Remote {
+        #[arg(short, long)]
+        verbose: bool,
+        #[command(subcommand)]
+        action: Option<RemoteAction>,
+    },
+    Fetch {
+        remote: String,
+    },
+    Push {
+        remote: String,
+        branch: String,
+        #[arg(short, long)]
+        force: bool,
+    },
+    CheckIgnore {
+        #[arg(required = true)]
+        paths: Vec<String>,
+        #[arg(short, long)]
+        verbose: bool,
+        #[arg(short, long, requires = "verbose")]
+        non_matching: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum LfsAction {
+    Track { patterns: Vec<String> },
+    LsFiles,
+    Fsck,
+    Push,
+    Fetch,
+}
+
+#[derive(Subcommand)]
+pub enum RemoteAction {
+    Add { name: String, url: String },
+    #[command(alias = "rm")]
+    Remove { name: String },
+}
+
+#[derive(Subcommand)]
+pub enum MultiPackIndexAction {
+    Write {
+        #[arg(long)]
+        bitmap: bool,
+    },
+    Verify,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
+    for dir in &cli.directory {
+        std::env::set_current_dir(dir)
+            .map_err(|e| error::ItError::Fatal(format!("cannot change to '{}': {e}", dir.display())))?;
+    }
+    // init and clone make a repository rather than looking for one
+    if !matches!(cli.command, Commands::Init { .. } | Commands::Clone { .. }) {
+        repository::setup(cli.git_dir, cli.work_tree)?;
+    }
+    // config has to work even when the color settings are broken
+    if !matches!(cli.command, Commands::Config { .. }) {
+        color_utils::apply_color_config()?;
+    }
 
  match cli.command {
-        Commands::Init => commands::init::init()?,
+        Commands::Init { directory, bare, initial_branch, object_format, template } => {
+            commands::init::init(commands::init::InitArgs { directory, bare, initial_branch, object_format, template })?
+        }
+        Commands::Clone { source, directory, bare, branch, depth } => {
+            commands::clone::clone(commands::clone::CloneArgs { source, directory, bare, branch, depth })?
+        }
         Commands::Branch { name } => commands::branch::branch(name)?,
         Commands::Switch { name } => commands::switch::switch(name)?,
-        Commands::Add { paths } => commands::add::add(paths)?,
+        Commands::Add { paths, force, all, update, dry_run } => {
+            commands::add::add(commands::add::AddArgs { paths, force, all, update, dry_run })?
+        }
         Commands::Log => commands::log::log()?,
         Commands::Commit { message } => {
             let tree_hash = commands::write_tree::write_tree()?;
@@ -47,6 +329,89 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             commands::commit_tree::commit_tree(&tree_hash, parent_hash, &message)?;
         }
         Commands::Reset => commands::reset::reset()?,
+        Commands::Config { global, get, unset, list, key, value } => {
+            commands::config::config(commands::config::ConfigArgs { global, get, unset, list, key, value })?
+        }
+        Commands::Status => commands::status::status()?,
+        Commands::Clean { dry_run, force, d, x, only_ignored } => {
+            commands::clean::clean(commands::clean::CleanArgs {
+                dry_run,
+                force,
+                directories: d,
+                ignored_too: x,
+                only_ignored,
+            })?
+        }
+        Commands::Rm { paths, cached, r, force } => commands::rm::rm(paths, cached, r, force)?,
+        Commands::Mv { source, destination, force } => commands::mv::mv(source, destination, force)?,
+        Commands::Restore { paths, staged, worktree, source } => {
+            commands::restore::restore(commands::restore::RestoreArgs { paths, staged, worktree, source })?
+        }
+        Commands::Show { objects, stat, name_only } => {
+            commands::show::show(commands::show::ShowArgs { objects, stat, name_only })?
+        }
+        Commands::LsFiles { paths, stage, cached, modified, deleted, others, exclude_standard, z } => {
+            commands::ls_files::ls_files(commands::ls_files::LsFilesArgs {
+                paths,
+                stage,
+                cached,
+                modified,
+                deleted,
+                others,
+                exclude_standard,
+                zero: z,
+            })?
+        }
+        Commands::LsTree { tree_ish, paths, r, t, name_only, z } => {
+            commands::ls_tree::ls_tree(commands::ls_tree::LsTreeArgs {
+                tree_ish,
+                paths,
+                recursive: r,
+                show_trees: t,
+                name_only,
+                zero: z,
+            })?
+        }
+        Commands::WriteTree => println!("{}", commands::write_tree::write_index_tree(&repository::repo_path()?)?),
+        Commands::CommitTree { tree, p, m } => commands::commit_tree::commit_tree_plumbing(&tree, &p, &m)?,
+        Commands::UpdateRef { d, name, new, old } => commands::update_ref::update_ref(name, new, old, d)?,
+        Commands::SymbolicRef { short, q, name, target } => {
+            commands::symbolic_ref::symbolic_ref(name, target, short, q)?
+        }
+        Commands::ReadTree { trees, empty, m, aggressive } => {
+            commands::read_tree::read_tree(commands::read_tree::ReadTreeArgs { trees, empty, merge: m, aggressive })?
+        }
+        Commands::Fsck { full, unreachable, lost_found } => {
+            commands::fsck::fsck(commands::fsck::FsckArgs { full, unreachable, lost_found })?
+        }
+        Commands::Gc { prune, dry_run } => commands::gc::gc(prune, dry_run)?,
+        Commands::Repack { all, delete, window, depth } => {
+            commands::repack::repack(commands::repack::RepackArgs { all, delete, window, depth })?
+        }
+        Commands::IndexPack { pack } => commands::index_pack::index_pack(&repository::user_path(&pack))?,
+        Commands::VerifyPack { verbose, idx } => commands::verify_pack::verify_pack(idx.iter().map(|p| repository::user_path(p)).collect(), verbose)?,
+        Commands::Lfs { action } => match action {
+            LfsAction::Track { patterns } => commands::lfs::track(patterns)?,
+            LfsAction::LsFiles => commands::lfs::ls_files()?,
+            LfsAction::Fsck => commands::lfs::fsck()?,
+            LfsAction::Push => commands::lfs::push()?,
+            LfsAction::Fetch => commands::lfs::fetch()?,
+        },
+        Commands::MultiPackIndex { action } => match action {
+            MultiPackIndexAction::Write { bitmap } => commands::multi_pack_index::write(bitmap)?,
+            MultiPackIndexAction::Verify => commands::multi_pack_index::verify()?,
+        },
+        Commands::Prune { expire, dry_run } => commands::prune::prune(expire, dry_run)?,
+        Commands::Remote { verbose, action } => match action {
+            None => commands::remote::list(verbose)?,
+            Some(RemoteAction::Add { name, url }) => commands::remote::add(name, url)?,
+            Some(RemoteAction::Remove { name }) => commands::remote::remove(name)?,
+        },
+        Commands::Fetch { remote } => commands::fetch::fetch(remote)?,
+        Commands::Push { remote, branch, force } => commands::push::push(remote, branch, force)?,
+        Commands::CheckIgnore { paths, verbose, non_matching } => {
+            commands::check_ignore::check_ignore(paths, verbose, non_matching)?
+        }
     };
    
 
@@ -0,0 +1,269 @@
+// The multi-pack-index: one sorted object list over every pack under
+// objects/pack, so finding an object is a single binary search however many
+// packs there are. The file is git's MIDX version 1:
+//
+//      "MIDX", version 1, hash version 1, chunk count, 0 base files, pack count
+//      chunk table: (4-byte id, 8-byte offset) per chunk, then a zero id
+//      PNAM    the packs' .idx names, sorted, NUL-terminated, padded to 4 bytes
+//      OIDF    256-entry fan-out of the object ids
+//      OIDL    sorted object ids
+//      OOFF    (pack id, offset) per object; a set high bit indexes LOFF
+//      LOFF    64-bit offsets, only when a pack is larger than 2 GiB
+//      RIDX    object positions in pseudo-pack order, which bitmaps use
+//      SHA-1 of everything before it
+//
+// Pseudo-pack order lists the preferred pack's objects first, then every
+// other pack by id, each in offset order, as if they were one big pack.
+use crate::{error::ItError, lockfile::write_atomic, pack};
+use sha1::{Digest, Sha1};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+const PNAM: &[u8; 4] = b"PNAM";
+const OIDF: &[u8; 4] = b"OIDF";
+const OIDL: &[u8; 4] = b"OIDL";
+const OOFF: &[u8; 4] = b"OOFF";
+const LOFF: &[u8; 4] = b"LOFF";
+const RIDX: &[u8; 4] = b"RIDX";
+
+pub struct MultiPackIndex {
+    pub path: PathBuf,
+    pub pack_names: Vec<String>,
+    pub hashes: Vec<[u8; 20]>,
+    // (pack id, offset), by position in `hashes`
+    pub objects: Vec<(u32, u64)>,
+    // position in `hashes` of each object in pseudo-pack order
+    pub pack_order: Option<Vec<u32>>,
+    pub checksum: [u8; 20],
+    fanout: [u32; 256],
+}
+
+impl MultiPackIndex {
+    pub fn load(path: &Path) -> Result<MultiPackIndex, ItError> {
+        let data = fs::read(path)?;
+        let corrupt = |msg: &str| ItError::CorruptObject(format!("{}: {msg}", path.display()));
+        if data.len() < 12 + 20 || &data[..4] != b"MIDX" {
+            return Err(corrupt("not a multi-pack-index"));
+        }
+        if data[4] != 1 || data[5] != 1 {
+            return Err(corrupt("unsupported version"));
+        }
+        let chunk_count = data[6] as usize;
+        let pack_count = be32(&data[8..]) as usize;
+
+        let mut table = Vec::new();
+        for i in 0..=chunk_count {
+            let at = 12 + i * 12;
+            let entry = data.get(at..at + 12).ok_or_else(|| corrupt("chunk table is truncated"))?;
+            let offset = u64::from_be_bytes(entry[4..].try_into().unwrap()) as usize;
+            table.push((<[u8; 4]>::try_from(&entry[..4]).unwrap(), offset));
+        }
+        let chunk = |id: &[u8; 4]| -> Option<&[u8]> {
+            let i = table[..chunk_count].iter().position(|(cid, _)| cid == id)?;
+            data.get(table[i].1..table[i + 1].1)
+        };
+
+        let names = chunk(PNAM).ok_or_else(|| corrupt("missing PNAM chunk"))?;
+        let pack_names: Vec<String> = names
+            .split(|&b| b == 0)
+            .filter(|n| !n.is_empty())
+            .map(|n| String::from_utf8_lossy(n).to_string())
+            .collect();
+        if pack_names.len() != pack_count {
+            return Err(corrupt("pack names do not match the pack count"));
+        }
+
+        let fanout_data = chunk(OIDF).filter(|c| c.len() == 1024).ok_or_else(|| corrupt("bad OIDF chunk"))?;
+        let mut fanout = [0u32; 256];
+        for (i, count) in fanout.iter_mut().enumerate() {
+            *count = be32(&fanout_data[i * 4..]);
+        }
+        let count = fanout[255] as usize;
+        let oids = chunk(OIDL).filter(|c| c.len() == count * 20).ok_or_else(|| corrupt("bad OIDL chunk"))?;
+        let hashes = oids.chunks(20).map(|c| c.try_into().unwrap()).collect();
+
+        let offsets = chunk(OOFF).filter(|c| c.len() == count * 8).ok_or_else(|| corrupt("bad OOFF chunk"))?;
+        let large = chunk(LOFF).unwrap_or_default();
+        let mut objects = Vec::with_capacity(count);
+        for entry in offsets.chunks(8) {
+            let pack = be32(entry);
+            let offset = be32(&entry[4..]);
+            let offset = if offset & 0x8000_0000 != 0 {
+                let at = (offset & 0x7fff_ffff) as usize * 8;
+                let bytes = large.get(at..at + 8).ok_or_else(|| corrupt("bad large offset"))?;
+                u64::from_be_bytes(bytes.try_into().unwrap())
+            } else {
+                offset as u64
+            };
+            if pack as usize >= pack_count {
+                return Err(corrupt("object in an unknown pack"));
+            }
+            objects.push((pack, offset));
+        }
+
+        let pack_order = match chunk(RIDX) {
+            Some(ridx) if ridx.len() == count * 4 => Some(ridx.chunks(4).map(be32).collect()),
+            Some(_) => return Err(corrupt("bad RIDX chunk")),
+            None => None,
+        };
+
+        let end = data.len();
+        Ok(MultiPackIndex {
+            path: path.to_path_buf(),
+            pack_names,
+            hashes,
+            objects,
+            pack_order,
+            checksum: data[end - 20..].try_into().unwrap(),
+            fanout,
+        })
+    }
+
+    pub fn find(&self, sha: &[u8; 20]) -> Option<usize> {
+        let first = sha[0] as usize;
+        let lo = if first == 0 { 0 } else { self.fanout[first - 1] as usize };
+        let hi = self.fanout[first] as usize;
+        self.hashes.get(lo..hi)?.binary_search(sha).ok().map(|i| lo + i)
+    }
+}
+
+pub fn midx_path(objects_dir: &Path) -> PathBuf {
+    objects_dir.join("pack").join("multi-pack-index")
+}
+
+// The file is rewritten in place, so the cache is keyed by size and mtime.
+static MIDX_CACHE: Mutex<Option<(u64, SystemTime, Arc<MultiPackIndex>)>> = Mutex::new(None);
+
+// The multi-pack-index, when there is one and every pack it names still
+// exists. A stale or unreadable one is ignored and packs are searched one by
+// one; `it multi-pack-index verify` reports what is wrong with it.
+pub fn load_current(objects_dir: &Path) -> Result<Option<Arc<MultiPackIndex>>, ItError> {
+    let path = midx_path(objects_dir);
+    let Ok(meta) = fs::metadata(&path) else {
+        return Ok(None);
+    };
+    let key = (meta.len(), meta.modified()?);
+
+    let mut cache = MIDX_CACHE.lock().unwrap();
+    let midx = match cache.as_ref() {
+        Some((len, mtime, midx)) if (*len, *mtime) == key && midx.path == path => midx.clone(),
+        _ => match MultiPackIndex::load(&path) {
+            Ok(midx) => {
+                let midx = Arc::new(midx);
+                *cache = Some((key.0, key.1, midx.clone()));
+                midx
+            }
+            Err(_) => return Ok(None),
+        },
+    };
+    let pack_dir = objects_dir.join("pack");
+    let complete = midx
+        .pack_names
+        .iter()
+        .all(|name| pack_dir.join(name).is_file() && pack_dir.join(name).with_extension("pack").is_file());
+    Ok(complete.then_some(midx))
+}
+
+// Writes a multi-pack-index over every pack. When several packs hold an
+// object the preferred pack, the largest one, wins, then the newest pack.
+pub fn write_midx(objects_dir: &Path) -> Result<MultiPackIndex, ItError> {
+    let paths = pack::pack_paths(objects_dir)?;
+    if paths.is_empty() {
+        return Err(ItError::Fatal("no pack files to index".to_string()));
+    }
+    let mut packs = Vec::new();
+    for path in &paths {
+        let mtime = fs::metadata(path.with_extension("pack"))?.modified()?;
+        packs.push((pack::load_index(path)?, mtime));
+    }
+    let pack_names: Vec<String> =
+        paths.iter().map(|p| p.file_name().unwrap_or_default().to_string_lossy().to_string()).collect();
+    let preferred = (0..packs.len()).max_by_key(|&i| (packs[i].0.hashes.len(), std::cmp::Reverse(i))).unwrap_or(0);
+
+    let mut priority: Vec<usize> = (0..packs.len()).collect();
+    priority.sort_by_key(|&i| (i != preferred, std::cmp::Reverse(packs[i].1), i));
+    let mut chosen: BTreeMap<[u8; 20], (u32, u64)> = BTreeMap::new();
+    for id in priority {
+        let pack = &packs[id].0;
+        for (sha, offset) in pack.hashes.iter().zip(&pack.offsets) {
+            chosen.entry(*sha).or_insert((id as u32, *offset));
+        }
+    }
+    let hashes: Vec<[u8; 20]> = chosen.keys().copied().collect();
+    let objects: Vec<(u32, u64)> = chosen.values().copied().collect();
+    let mut pack_order: Vec<u32> = (0..objects.len() as u32).collect();
+    pack_order.sort_by_key(|&i| {
+        let (pack, offset) = objects[i as usize];
+        (pack as usize != preferred, pack, offset)
+    });
+
+    let mut names = Vec::new();
+    for name in &pack_names {
+        names.extend_from_slice(name.as_bytes());
+        names.push(0);
+    }
+    names.resize(names.len().div_ceil(4) * 4, 0);
+
+    let mut fanout = Vec::with_capacity(1024);
+    let mut counts = [0u32; 256];
+    for sha in &hashes {
+        counts[sha[0] as usize] += 1;
+    }
+    let mut total = 0u32;
+    for count in counts {
+        total += count;
+        fanout.extend_from_slice(&total.to_be_bytes());
+    }
+
+    let mut offsets = Vec::with_capacity(objects.len() * 8);
+    let mut large = Vec::new();
+    for (pack, offset) in &objects {
+        offsets.extend_from_slice(&pack.to_be_bytes());
+        if *offset < 0x8000_0000 {
+            offsets.extend_from_slice(&(*offset as u32).to_be_bytes());
+        } else {
+            offsets.extend_from_slice(&(0x8000_0000 | (large.len() / 8) as u32).to_be_bytes());
+            large.extend_from_slice(&offset.to_be_bytes());
+        }
+    }
+
+    let mut chunks: Vec<(&[u8; 4], Vec<u8>)> = vec![
+        (PNAM, names),
+        (OIDF, fanout),
+        (OIDL, hashes.concat()),
+        (OOFF, offsets),
+    ];
+    if !large.is_empty() {
+        chunks.push((LOFF, large));
+    }
+    chunks.push((RIDX, pack_order.iter().flat_map(|i| i.to_be_bytes()).collect()));
+
+    let mut data = Vec::new();
+    data.extend_from_slice(b"MIDX");
+    data.extend_from_slice(&[1, 1, chunks.len() as u8, 0]);
+    data.extend_from_slice(&(pack_names.len() as u32).to_be_bytes());
+    let mut offset = (12 + (chunks.len() + 1) * 12) as u64;
+    for (id, chunk) in &chunks {
+        data.extend_from_slice(*id);
+        data.extend_from_slice(&offset.to_be_bytes());
+        offset += chunk.len() as u64;
+    }
+    data.extend_from_slice(&[0; 4]);
+    data.extend_from_slice(&offset.to_be_bytes());
+    for (_, chunk) in &chunks {
+        data.extend_from_slice(chunk);
+    }
+    let checksum: [u8; 20] = Sha1::digest(&data).into();
+    data.extend_from_slice(&checksum);
+
+    let path = midx_path(objects_dir);
+    write_atomic(&path, &data)?;
+    MultiPackIndex::load(&path)
+}
+
+fn be32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes(bytes[..4].try_into().unwrap())
+}
@@ -0,0 +1,178 @@
+use crate::config::Config;
+use crate::error::ItError;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::path::Path;
+use std::sync::Mutex;
+
+// Object names. A repository hashes objects with SHA-1 (20-byte ids, the
+// default) or SHA-256 (32-byte ids), chosen at `it init --object-format` and
+// recorded as `extensions.objectFormat` in .it/config. Every id in the
+// repository (objects, index entries, tree entries, refs, reflogs) uses that
+// format; ids of the two formats never mix.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ObjectFormat {
+    Sha1,
+    Sha256,
+}
+
+impl ObjectFormat {
+    pub fn from_name(name: &str) -> Option<ObjectFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "sha1" => Some(ObjectFormat::Sha1),
+            "sha256" => Some(ObjectFormat::Sha256),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ObjectFormat::Sha1 => "sha1",
+            ObjectFormat::Sha256 => "sha256",
+        }
+    }
+
+    pub fn raw_len(self) -> usize {
+        match self {
+            ObjectFormat::Sha1 => 20,
+            ObjectFormat::Sha256 => 32,
+        }
+    }
+
+    pub fn hex_len(self) -> usize {
+        self.raw_len() * 2
+    }
+
+    pub fn null_id(self) -> ObjectId {
+        ObjectId { bytes: [0; 32], len: self.raw_len() as u8 }
+    }
+
+    pub fn hasher(self) -> Hasher {
+        match self {
+            ObjectFormat::Sha1 => Hasher::Sha1(Sha1::new()),
+            ObjectFormat::Sha256 => Hasher::Sha256(Sha256::new()),
+        }
+    }
+
+    pub fn digest(self, data: &[u8]) -> ObjectId {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finish()
+    }
+
+    // Whether `s` is a full lowercase hex id of this format.
+    pub fn is_hex_id(self, s: &str) -> bool {
+        s.len() == self.hex_len() && s.chars().all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase())
+    }
+
+    // The format of the repository at `repo_path`. Without
+    // extensions.objectFormat it is SHA-1; an extension needs
+    // core.repositoryFormatVersion 1, as in git.
+    pub fn load(repo_path: &Path) -> Result<ObjectFormat, ItError> {
+        let config = Config::load_file(&repo_path.join("config"))?;
+        let Some(name) = config.get("extensions.objectformat") else {
+            return Ok(ObjectFormat::Sha1);
+        };
+        if config.get_int("core.repositoryformatversion")?.unwrap_or(0) < 1 {
+            return Err(ItError::InvalidConfig(
+                "extensions.objectFormat requires core.repositoryFormatVersion = 1".to_string(),
+            ));
+        }
+        ObjectFormat::from_name(name)
+            .ok_or_else(|| ItError::InvalidConfig(format!("unknown object format '{name}'")))
+    }
+
+    // The format of the repository the command runs in, set once it has
+    // been found. Outside a repository this is SHA-1.
+    pub fn current() -> ObjectFormat {
+        CURRENT.lock().unwrap().unwrap_or(ObjectFormat::Sha1)
+    }
+
+    // Fixes the format for the rest of the process: once the repository is
+    // found, or when a command creates one and then writes objects to it.
+    pub fn set_current(format: ObjectFormat) {
+        *CURRENT.lock().unwrap() = Some(format);
+    }
+}
+
+static CURRENT: Mutex<Option<ObjectFormat>> = Mutex::new(None);
+
+pub enum Hasher {
+    Sha1(Sha1),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha1(h) => h.update(data),
+            Hasher::Sha256(h) => h.update(data),
+        }
+    }
+
+    pub fn finish(self) -> ObjectId {
+        match self {
+            Hasher::Sha1(h) => ObjectId::from_bytes(&h.finalize()),
+            Hasher::Sha256(h) => ObjectId::from_bytes(&h.finalize()),
+        }
+        .expect("digest has the length of its format")
+    }
+}
+
+// A binary object id of either format. Ids compare by their bytes, which
+// orders them the same way as their hex form.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObjectId {
+    bytes: [u8; 32],
+    len: u8,
+}
+
+impl ObjectId {
+    // An id from its raw bytes, 20 for SHA-1 or 32 for SHA-256.
+    pub fn from_bytes(raw: &[u8]) -> Option<ObjectId> {
+        if raw.len() != 20 && raw.len() != 32 {
+            return None;
+        }
+        let mut bytes = [0; 32];
+        bytes[..raw.len()].copy_from_slice(raw);
+        Some(ObjectId { bytes, len: raw.len() as u8 })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+
+    pub fn to_hex(self) -> String {
+        hex::encode(self.as_bytes())
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.as_bytes().iter().all(|&b| b == 0)
+    }
+}
+
+impl AsRef<[u8]> for ObjectId {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl Default for ObjectId {
+    fn default() -> ObjectId {
+        ObjectFormat::Sha1.null_id()
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl fmt::Debug for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ObjectId({})", self.to_hex())
+    }
+}
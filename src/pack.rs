@@ -0,0 +1,994 @@
+// Packfiles: git's version 2 .pack/.idx pairs under objects/pack.
+//
+// A pack is "PACK", a version and an object count, then one entry per object:
+// a type/size header and the zlib-compressed body. Deltified entries name
+// their base either by a backwards offset into the same pack (OFS_DELTA) or by
+// hash (REF_DELTA), and their body is a git delta against that base. A SHA-1
+// of everything before it ends the file. The .idx beside it maps hashes to
+// pack offsets through a 256-entry fan-out table and sorted hash list.
+use crate::{
+    commands::hash_object::{CHUNK_SIZE, ExactReader, ObjectStream},
+    error::ItError,
+    midx::{self, MultiPackIndex},
+};
+use flate2::{Compression, Crc, bufread::ZlibDecoder, write::ZlibEncoder};
+use sha1::{Digest, Sha1};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+pub const OBJ_COMMIT: u8 = 1;
+pub const OBJ_TREE: u8 = 2;
+pub const OBJ_BLOB: u8 = 3;
+pub const OBJ_TAG: u8 = 4;
+pub const OBJ_OFS_DELTA: u8 = 6;
+pub const OBJ_REF_DELTA: u8 = 7;
+
+const IDX_MAGIC: [u8; 4] = [0xff, b't', b'O', b'c'];
+// sources are indexed in blocks of this many bytes when searching for copies
+const BLOCK: usize = 16;
+// git's copy instructions carry at most 0x10000 bytes when the size is omitted
+const MAX_COPY: usize = 0x10000;
+// git's pack-objects never writes longer delta chains than this, so a longer
+// one (or a REF_DELTA cycle) means the pack is corrupt
+const MAX_DELTA_DEPTH: usize = 4095;
+
+pub fn type_name(code: u8) -> &'static str {
+    match code {
+        OBJ_COMMIT => "commit",
+        OBJ_TREE => "tree",
+        OBJ_BLOB => "blob",
+        OBJ_TAG => "tag",
+        OBJ_OFS_DELTA => "ofs-delta",
+        OBJ_REF_DELTA => "ref-delta",
+        _ => "unknown",
+    }
+}
+
+pub fn type_code(name: &str) -> Option<u8> {
+    match name {
+        "commit" => Some(OBJ_COMMIT),
+        "tree" => Some(OBJ_TREE),
+        "blob" => Some(OBJ_BLOB),
+        "tag" => Some(OBJ_TAG),
+        _ => None,
+    }
+}
+
+pub struct PackIndex {
+    pub idx_path: PathBuf,
+    pub pack_path: PathBuf,
+    // sorted, with offsets[i] and crcs[i] belonging to hashes[i]
+    pub hashes: Vec<[u8; 20]>,
+    pub offsets: Vec<u64>,
+    pub crcs: Vec<u32>,
+    pub pack_checksum: [u8; 20],
+    fanout: [u32; 256],
+}
+
+impl PackIndex {
+    pub fn load(idx_path: &Path) -> Result<PackIndex, ItError> {
+        let data = fs::read(idx_path)?;
+        let corrupt = |msg: &str| ItError::CorruptObject(format!("{}: {msg}", idx_path.display()));
+        if data.len() < 8 + 1024 + 40 || data[..4] != IDX_MAGIC || be32(&data[4..]) != 2 {
+            return Err(corrupt("not a version 2 pack index"));
+        }
+
+        let mut fanout = [0u32; 256];
+        for (i, count) in fanout.iter_mut().enumerate() {
+            *count = be32(&data[8 + i * 4..]);
+        }
+        let count = fanout[255] as usize;
+        let hashes_at = 8 + 1024;
+        let crcs_at = hashes_at + count * 20;
+        let offsets_at = crcs_at + count * 4;
+        let large_at = offsets_at + count * 4;
+        if data.len() < large_at + 40 || fanout.windows(2).any(|w| w[0] > w[1]) {
+            return Err(corrupt("index is truncated"));
+        }
+
+        let hashes = (0..count)
+            .map(|i| data[hashes_at + i * 20..hashes_at + i * 20 + 20].try_into().unwrap())
+            .collect();
+        let crcs = (0..count).map(|i| be32(&data[crcs_at + i * 4..])).collect();
+        let mut offsets = Vec::with_capacity(count);
+        for i in 0..count {
+            let offset = be32(&data[offsets_at + i * 4..]);
+            // the high bit points into the table of 64-bit offsets
+            if offset & 0x8000_0000 != 0 {
+                let at = large_at + (offset & 0x7fff_ffff) as usize * 8;
+                if at + 8 > data.len() - 40 {
+                    return Err(corrupt("bad large offset"));
+                }
+                offsets.push(u64::from_be_bytes(data[at..at + 8].try_into().unwrap()));
+            } else {
+                offsets.push(offset as u64);
+            }
+        }
+
+        let end = data.len();
+        Ok(PackIndex {
+            idx_path: idx_path.to_path_buf(),
+            pack_path: idx_path.with_extension("pack"),
+            hashes,
+            offsets,
+            crcs,
+            pack_checksum: data[end - 40..end - 20].try_into().unwrap(),
+            fanout,
+        })
+    }
+
+    pub fn find(&self, sha: &[u8; 20]) -> Option<usize> {
+        let first = sha[0] as usize;
+        let lo = if first == 0 { 0 } else { self.fanout[first - 1] as usize };
+        let hi = self.fanout[first] as usize;
+        self.hashes[lo..hi].binary_search(sha).ok().map(|i| lo + i)
+    }
+}
+
+// Pack indexes never change once written (their name is the pack's checksum),
+// so each one is parsed once per process.
+static INDEX_CACHE: Mutex<Vec<Arc<PackIndex>>> = Mutex::new(Vec::new());
+
+// The .idx files under `objects_dir` that have their .pack beside them.
+pub fn pack_paths(objects_dir: &Path) -> Result<Vec<PathBuf>, ItError> {
+    let Ok(entries) = fs::read_dir(objects_dir.join("pack")) else {
+        return Ok(Vec::new());
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|e| e == "idx") && p.with_extension("pack").is_file())
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+pub fn load_index(idx_path: &Path) -> Result<Arc<PackIndex>, ItError> {
+    let mut cache = INDEX_CACHE.lock().unwrap();
+    if let Some(index) = cache.iter().find(|i| i.idx_path == idx_path) {
+        return Ok(index.clone());
+    }
+    let index = Arc::new(PackIndex::load(idx_path)?);
+    cache.push(index.clone());
+    Ok(index)
+}
+
+// Every pack under `objects_dir` that has both its .idx and .pack.
+pub fn packs(objects_dir: &Path) -> Result<Vec<Arc<PackIndex>>, ItError> {
+    pack_paths(objects_dir)?.iter().map(|p| load_index(p)).collect()
+}
+
+// Packs the multi-pack-index does not cover, which are searched one by one.
+fn uncovered_packs(objects_dir: &Path, midx: Option<&MultiPackIndex>) -> Result<Vec<PathBuf>, ItError> {
+    let mut paths = pack_paths(objects_dir)?;
+    if let Some(midx) = midx {
+        paths.retain(|p| !p.file_name().is_some_and(|n| midx.pack_names.iter().any(|m| n == m.as_str())));
+    }
+    Ok(paths)
+}
+
+// Finds the pack and offset holding `sha`, through the multi-pack-index when
+// there is one.
+fn locate(objects_dir: &Path, sha: &[u8; 20]) -> Result<Option<(Arc<PackIndex>, u64)>, ItError> {
+    let midx = midx::load_current(objects_dir)?;
+    let in_midx = midx.as_ref().and_then(|midx| {
+        let (pack, offset) = midx.objects[midx.find(sha)?];
+        Some((objects_dir.join("pack").join(&midx.pack_names[pack as usize]), offset))
+    });
+    if let Some((path, offset)) = in_midx {
+        return Ok(Some((load_index(&path)?, offset)));
+    }
+    for path in uncovered_packs(objects_dir, midx.as_deref())? {
+        let pack = load_index(&path)?;
+        if let Some(i) = pack.find(sha) {
+            let offset = pack.offsets[i];
+            return Ok(Some((pack, offset)));
+        }
+    }
+    Ok(None)
+}
+
+fn parse_sha(hash: &str) -> Option<[u8; 20]> {
+    hex::decode(hash).ok()?.try_into().ok()
+}
+
+pub fn contains(objects_dir: &Path, hash: &str) -> Result<bool, ItError> {
+    let Some(sha) = parse_sha(hash) else {
+        return Ok(false);
+    };
+    Ok(locate(objects_dir, &sha)?.is_some())
+}
+
+// Packed objects whose hash starts with `prefix`.
+pub fn find_prefix(objects_dir: &Path, prefix: &str) -> Result<BTreeSet<String>, ItError> {
+    let midx = midx::load_current(objects_dir)?;
+    let mut found = BTreeSet::new();
+    if let Some(midx) = &midx {
+        found.extend(midx.hashes.iter().map(hex::encode).filter(|h| h.starts_with(prefix)));
+    }
+    for path in uncovered_packs(objects_dir, midx.as_deref())? {
+        found.extend(load_index(&path)?.hashes.iter().map(hex::encode).filter(|h| h.starts_with(prefix)));
+    }
+    Ok(found)
+}
+
+// The type, size and a reader over the body of the object, if any pack holds
+// it. A whole entry is inflated as it is read; a delta has to be resolved in
+// memory.
+pub fn open_packed(objects_dir: &Path, hash: &str) -> Result<Option<ObjectStream>, ItError> {
+    let Some(sha) = parse_sha(hash) else {
+        return Ok(None);
+    };
+    let Some((pack, offset)) = locate(objects_dir, &sha)? else {
+        return Ok(None);
+    };
+    let mut reader = BufReader::new(fs::File::open(&pack.pack_path)?);
+    let (kind, size, base) = read_entry_header(&mut reader, offset)?;
+    if let DeltaBase::None = base {
+        let body = ExactReader::new(ZlibDecoder::new(reader), size as u64, hash);
+        return Ok(Some((type_name(kind).to_string(), size as u64, Box::new(body))));
+    }
+    let (kind, body) = read_entry_from(&mut reader, &pack, offset)?;
+    Ok(Some((type_name(kind).to_string(), body.len() as u64, Box::new(std::io::Cursor::new(body)))))
+}
+
+pub enum DeltaBase {
+    None,
+    Offset(u64),
+    Ref([u8; 20]),
+}
+
+// Parses the entry header at `offset`, leaving the reader at the start of
+// the compressed data. Returns the raw type, the inflated size and the base.
+pub fn read_entry_header<R: Read + Seek>(reader: &mut R, offset: u64) -> Result<(u8, usize, DeltaBase), ItError> {
+    let corrupt = |msg: &str| ItError::CorruptObject(format!("pack entry at {offset}: {msg}"));
+    reader.seek(SeekFrom::Start(offset))?;
+
+    let mut byte = read_u8(reader)?;
+    let kind = (byte >> 4) & 7;
+    let mut size = (byte & 0x0f) as usize;
+    let mut shift = 4;
+    while byte & 0x80 != 0 {
+        if shift > 57 {
+            return Err(corrupt("size overflows"));
+        }
+        byte = read_u8(reader)?;
+        size |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+    }
+
+    let base = match kind {
+        OBJ_COMMIT | OBJ_TREE | OBJ_BLOB | OBJ_TAG => DeltaBase::None,
+        OBJ_OFS_DELTA => {
+            let mut byte = read_u8(reader)?;
+            let mut distance = (byte & 0x7f) as u64;
+            while byte & 0x80 != 0 {
+                if distance >= 1 << 56 {
+                    return Err(corrupt("base offset overflows"));
+                }
+                byte = read_u8(reader)?;
+                distance = ((distance + 1) << 7) | (byte & 0x7f) as u64;
+            }
+            if distance == 0 || distance > offset {
+                return Err(corrupt("base offset out of range"));
+            }
+            DeltaBase::Offset(offset - distance)
+        }
+        OBJ_REF_DELTA => {
+            let mut sha = [0u8; 20];
+            reader.read_exact(&mut sha)?;
+            DeltaBase::Ref(sha)
+        }
+        other => return Err(corrupt(&format!("unknown type {other}"))),
+    };
+    Ok((kind, size, base))
+}
+
+// Inflates exactly one zlib stream of `size` bytes from the reader's position,
+// returning the data and the number of compressed bytes consumed. The size
+// comes from the pack, so only what actually inflates is allocated.
+pub fn inflate<R: std::io::BufRead>(reader: &mut R, size: usize) -> Result<(Vec<u8>, u64), ItError> {
+    let mut decoder = ZlibDecoder::new(reader);
+    let mut data = Vec::with_capacity(size.min(CHUNK_SIZE));
+    decoder.by_ref().take(size as u64 + 1).read_to_end(&mut data)?;
+    if data.len() != size {
+        return Err(ItError::CorruptObject(format!("inflated {} bytes, expected {size}", data.len())));
+    }
+    Ok((data, decoder.total_in()))
+}
+
+// Reads the entry at `offset`, resolving delta chains, as (type, body).
+pub fn read_entry(pack: &PackIndex, offset: u64) -> Result<(u8, Vec<u8>), ItError> {
+    let mut reader = BufReader::new(fs::File::open(&pack.pack_path)?);
+    read_entry_from(&mut reader, pack, offset)
+}
+
+// Follows the chain of bases from `offset` using the entry headers alone,
+// then inflates the base and applies the deltas on the way back up. A
+// REF_DELTA base has to be in the same pack, as in git.
+fn read_entry_from(reader: &mut BufReader<fs::File>, pack: &PackIndex, offset: u64) -> Result<(u8, Vec<u8>), ItError> {
+    let mut deltas = Vec::new();
+    let mut entry = offset;
+    let (kind, size) = loop {
+        let (kind, size, base) = read_entry_header(reader, entry)?;
+        let base_offset = match base {
+            DeltaBase::None => break (kind, size),
+            _ if deltas.len() >= MAX_DELTA_DEPTH => {
+                return Err(ItError::CorruptObject(format!("pack entry at {offset}: delta chain is too deep")));
+            }
+            DeltaBase::Offset(base_offset) => base_offset,
+            DeltaBase::Ref(sha) => match pack.find(&sha) {
+                Some(i) => pack.offsets[i],
+                None => {
+                    return Err(ItError::CorruptObject(format!(
+                        "pack entry at {entry}: delta base {} is not in the pack",
+                        hex::encode(sha)
+                    )));
+                }
+            },
+        };
+        deltas.push((entry, size));
+        entry = base_offset;
+    };
+
+    let (mut body, _) = inflate(reader, size)?;
+    for (delta_offset, delta_size) in deltas.into_iter().rev() {
+        read_entry_header(reader, delta_offset)?;
+        let (delta, _) = inflate(reader, delta_size)?;
+        body = apply_delta(&body, &delta)?;
+    }
+    Ok((kind, body))
+}
+
+// One entry as stored in a pack, before delta resolution.
+pub struct RawEntry {
+    pub offset: u64,
+    pub kind: u8,
+    pub base: DeltaBase,
+    // the inflated body, a delta for OFS_DELTA and REF_DELTA entries
+    pub data: Vec<u8>,
+    // header, base reference and compressed data
+    pub packed_len: u64,
+    pub crc: u32,
+}
+
+// An entry after resolution: its real type and hash, how many deltas deep it
+// is and the index of its base entry.
+pub struct ResolvedEntry {
+    pub kind: u8,
+    pub sha: [u8; 20],
+    pub depth: usize,
+    pub base: Option<usize>,
+}
+
+// Walks a whole pack from its header to its trailer, which has to match the
+// SHA-1 of everything before it. Returns the entries and that checksum.
+pub fn parse_pack(data: &[u8]) -> Result<(Vec<RawEntry>, [u8; 20]), ItError> {
+    let corrupt = |msg: String| ItError::CorruptObject(msg);
+    if data.len() < 32 || &data[..4] != b"PACK" {
+        return Err(corrupt("not a pack file".to_string()));
+    }
+    let version = be32(&data[4..]);
+    if version != 2 && version != 3 {
+        return Err(corrupt(format!("unsupported pack version {version}")));
+    }
+    let count = be32(&data[8..]) as usize;
+    let end = data.len() - 20;
+    // every entry takes at least a header byte and a zlib stream
+    if count > end - 12 {
+        return Err(corrupt(format!("pack claims {count} objects in {} bytes", end - 12)));
+    }
+    let checksum: [u8; 20] = data[end..].try_into().unwrap();
+    if Sha1::digest(&data[..end])[..] != checksum {
+        return Err(corrupt("pack checksum mismatch".to_string()));
+    }
+
+    let mut cursor = std::io::Cursor::new(&data[..end]);
+    let mut entries = Vec::with_capacity(count);
+    let mut offset = 12u64;
+    for _ in 0..count {
+        let (kind, size, base) = read_entry_header(&mut cursor, offset)?;
+        let data_start = cursor.position();
+        let (body, consumed) = inflate(&mut cursor, size)?;
+        let next = data_start + consumed;
+        let mut crc = Crc::new();
+        crc.update(&data[offset as usize..next as usize]);
+        entries.push(RawEntry { offset, kind, base, data: body, packed_len: next - offset, crc: crc.sum() });
+        offset = next;
+    }
+    if offset as usize != end {
+        return Err(corrupt(format!("{} bytes of garbage after the last entry", end - offset as usize)));
+    }
+    Ok((entries, checksum))
+}
+
+// Resolves every delta against its base within the pack, hashing each
+// object. A REF_DELTA base has to be in the pack too; thin packs are refused.
+pub fn resolve_entries(entries: &[RawEntry]) -> Result<Vec<ResolvedEntry>, ItError> {
+    let by_offset: HashMap<u64, usize> = entries.iter().enumerate().map(|(i, e)| (e.offset, i)).collect();
+    let mut bodies: Vec<Option<Vec<u8>>> = vec![None; entries.len()];
+    let mut resolved: Vec<Option<ResolvedEntry>> = (0..entries.len()).map(|_| None).collect();
+    let mut by_sha: HashMap<[u8; 20], usize> = HashMap::new();
+
+    // every pass resolves the entries whose base is already known, starting
+    // with the ones that are not deltas
+    loop {
+        let mut ready = Vec::new();
+        for (i, entry) in entries.iter().enumerate().filter(|(i, _)| resolved[*i].is_none()) {
+            let base = match &entry.base {
+                DeltaBase::None => {
+                    ready.push((i, entry.kind, entry.data.clone(), 0, None));
+                    continue;
+                }
+                DeltaBase::Offset(offset) => by_offset.get(offset).copied(),
+                DeltaBase::Ref(sha) => by_sha.get(sha).copied(),
+            };
+            if let Some(base) = base.filter(|&b| resolved[b].is_some()) {
+                let (kind, depth) = resolved[base].as_ref().map(|r| (r.kind, r.depth + 1)).unwrap();
+                let body = apply_delta(bodies[base].as_ref().unwrap(), &entry.data)?;
+                ready.push((i, kind, body, depth, Some(base)));
+            }
+        }
+        if ready.is_empty() {
+            break;
+        }
+        for (i, kind, body, depth, base) in ready {
+            let sha = object_sha(kind, &body);
+            by_sha.insert(sha, i);
+            bodies[i] = Some(body);
+            resolved[i] = Some(ResolvedEntry { kind, sha, depth, base });
+        }
+    }
+
+    let unresolved = resolved.iter().filter(|r| r.is_none()).count();
+    if unresolved > 0 {
+        return Err(ItError::CorruptObject(format!("pack has {unresolved} unresolved deltas")));
+    }
+    Ok(resolved.into_iter().map(Option::unwrap).collect())
+}
+
+fn object_sha(kind: u8, body: &[u8]) -> [u8; 20] {
+    let mut hasher = Sha1::new();
+    hasher.update(format!("{} {}\0", type_name(kind), body.len()));
+    hasher.update(body);
+    hasher.finalize().into()
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> Result<u8, ItError> {
+    let mut byte = [0u8; 1];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn be32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes(bytes[..4].try_into().unwrap())
+}
+
+// Delta format: source and target sizes as little-endian base-128 numbers,
+// then instructions. A byte with the high bit set copies from the source, its
+// low 4 bits saying which offset bytes follow and the next 3 which size bytes
+// follow. Any other non-zero byte n inserts the next n bytes literally.
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>, ItError> {
+    let corrupt = || ItError::CorruptObject("malformed delta".to_string());
+    let mut pos = 0;
+    let source_size = read_size(delta, &mut pos).ok_or_else(corrupt)?;
+    let target_size = read_size(delta, &mut pos).ok_or_else(corrupt)?;
+    if source_size != base.len() {
+        return Err(ItError::CorruptObject(format!(
+            "delta expects a {source_size} byte base, got {}",
+            base.len()
+        )));
+    }
+
+    // the sizes come from the delta, so nothing is trusted until it is written
+    let mut out = Vec::with_capacity(target_size.min(CHUNK_SIZE));
+    while pos < delta.len() {
+        let op = delta[pos];
+        pos += 1;
+        if op & 0x80 != 0 {
+            let mut offset = 0usize;
+            let mut size = 0usize;
+            for i in 0..4 {
+                if op & (1 << i) != 0 {
+                    offset |= (*delta.get(pos).ok_or_else(corrupt)? as usize) << (8 * i);
+                    pos += 1;
+                }
+            }
+            for i in 0..3 {
+                if op & (0x10 << i) != 0 {
+                    size |= (*delta.get(pos).ok_or_else(corrupt)? as usize) << (8 * i);
+                    pos += 1;
+                }
+            }
+            if size == 0 {
+                size = MAX_COPY;
+            }
+            let end = offset.checked_add(size).ok_or_else(corrupt)?;
+            let copy = base.get(offset..end).ok_or_else(corrupt)?;
+            if out.len() + copy.len() > target_size {
+                return Err(corrupt());
+            }
+            out.extend_from_slice(copy);
+        } else if op != 0 {
+            let end = pos + op as usize;
+            let insert = delta.get(pos..end).ok_or_else(corrupt)?;
+            if out.len() + insert.len() > target_size {
+                return Err(corrupt());
+            }
+            out.extend_from_slice(insert);
+            pos = end;
+        } else {
+            return Err(corrupt());
+        }
+    }
+    if out.len() != target_size {
+        return Err(corrupt());
+    }
+    Ok(out)
+}
+
+// Builds a delta turning `base` into `target`: 16-byte blocks of the base are
+// hashed, matches found through them are extended as far as they go and
+// become copies, everything else is inserted. Gives up once the delta grows
+// past `max_size`.
+pub fn create_delta(base: &[u8], target: &[u8], max_size: usize) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    write_size(&mut out, base.len());
+    write_size(&mut out, target.len());
+
+    let mut blocks: HashMap<&[u8], Vec<usize>> = HashMap::new();
+    for at in (0..base.len().saturating_sub(BLOCK - 1)).step_by(BLOCK) {
+        let positions = blocks.entry(&base[at..at + BLOCK]).or_default();
+        // highly repetitive data would otherwise make matching quadratic
+        if positions.len() < 64 {
+            positions.push(at);
+        }
+    }
+
+    let mut literal = Vec::new();
+    let mut i = 0;
+    while i < target.len() {
+        let mut best = (0, 0);
+        if let Some(candidates) = target.get(i..i + BLOCK).and_then(|block| blocks.get(block)) {
+            for &source in candidates {
+                let len = base[source..].iter().zip(&target[i..]).take_while(|(a, b)| a == b).count();
+                if len > best.1 {
+                    best = (source, len);
+                }
+            }
+        }
+
+        if best.1 < BLOCK {
+            literal.push(target[i]);
+            i += 1;
+            if literal.len() == 0x7f {
+                flush_literal(&mut out, &mut literal);
+            }
+        } else {
+            i += best.1;
+            // the match may also cover bytes just queued as literals
+            let (mut source, mut len) = best;
+            while source > 0 && literal.last() == Some(&base[source - 1]) {
+                literal.pop();
+                source -= 1;
+                len += 1;
+            }
+            flush_literal(&mut out, &mut literal);
+            push_copy(&mut out, source, len);
+        }
+        if out.len() + literal.len() > max_size {
+            return None;
+        }
+    }
+    flush_literal(&mut out, &mut literal);
+    (out.len() <= max_size).then_some(out)
+}
+
+fn flush_literal(out: &mut Vec<u8>, literal: &mut Vec<u8>) {
+    if !literal.is_empty() {
+        out.push(literal.len() as u8);
+        out.append(literal);
+    }
+}
+
+fn push_copy(out: &mut Vec<u8>, mut source: usize, mut len: usize) {
+    while len > 0 {
+        let chunk = len.min(MAX_COPY);
+        let op_at = out.len();
+        out.push(0x80);
+        for i in 0..4 {
+            let byte = (source >> (8 * i)) as u8;
+            if byte != 0 {
+                out[op_at] |= 1 << i;
+                out.push(byte);
+            }
+        }
+        // a full MAX_COPY chunk is encoded as size 0
+        if chunk != MAX_COPY {
+            for i in 0..3 {
+                let byte = (chunk >> (8 * i)) as u8;
+                if byte != 0 {
+                    out[op_at] |= 0x10 << i;
+                    out.push(byte);
+                }
+            }
+        }
+        source += chunk;
+        len -= chunk;
+    }
+}
+
+fn read_size(data: &[u8], pos: &mut usize) -> Option<usize> {
+    let mut size = 0usize;
+    let mut shift = 0;
+    loop {
+        let byte = *data.get(*pos)?;
+        *pos += 1;
+        size |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Some(size);
+        }
+        shift += 7;
+        if shift > 63 {
+            return None;
+        }
+    }
+}
+
+fn write_size(out: &mut Vec<u8>, mut size: usize) {
+    while size >= 0x80 {
+        out.push((size as u8 & 0x7f) | 0x80);
+        size >>= 7;
+    }
+    out.push(size as u8);
+}
+
+// git's pack_name_hash: the last characters weigh the most, so files with the
+// same name or extension end up next to each other in the delta search.
+pub fn name_hash(name: &str) -> u32 {
+    name.bytes()
+        .filter(|c| !c.is_ascii_whitespace())
+        .fold(0u32, |hash, c| (hash >> 2).wrapping_add((c as u32) << 24))
+}
+
+pub struct PackObject {
+    pub hash: String,
+    pub kind: String,
+    pub data: Vec<u8>,
+    pub name_hash: u32,
+}
+
+pub struct PackOptions {
+    // how many preceding objects are tried as delta bases
+    pub window: usize,
+    // longest allowed delta chain
+    pub depth: usize,
+    // OFS_DELTA instead of REF_DELTA for bases
+    pub ofs_delta: bool,
+}
+
+pub struct PackSummary {
+    pub name: String,
+    pub objects: usize,
+    pub deltas: usize,
+}
+
+// Writes `objects` as a new pack and index under objects/pack. Objects are
+// ordered by type, name hash and decreasing size, and each one is tried as a
+// delta against the `window` objects before it, so bases always come first in
+// the pack and OFS_DELTA distances are positive.
+pub fn write_pack(objects_dir: &Path, mut objects: Vec<PackObject>, options: &PackOptions) -> Result<PackSummary, ItError> {
+    let mut seen = BTreeSet::new();
+    objects.retain(|o| seen.insert(o.hash.clone()));
+    objects.sort_by(|a, b| {
+        (type_code(&a.kind), a.name_hash, std::cmp::Reverse(a.data.len()), &a.hash).cmp(&(
+            type_code(&b.kind),
+            b.name_hash,
+            std::cmp::Reverse(b.data.len()),
+            &b.hash,
+        ))
+    });
+    let count = objects.len();
+
+    let mut deltas: Vec<Option<(usize, Vec<u8>)>> = Vec::with_capacity(count);
+    let mut depth = vec![0usize; count];
+    for i in 0..count {
+        let target = &objects[i];
+        let mut best: Option<(usize, Vec<u8>)> = None;
+        // tiny objects are not worth a delta
+        if target.data.len() >= 64 {
+            for j in (i.saturating_sub(options.window)..i).rev() {
+                let base = &objects[j];
+                if base.kind != target.kind || depth[j] >= options.depth || base.data.len() < target.data.len() / 32 {
+                    continue;
+                }
+                let max_size = match &best {
+                    Some((_, delta)) => delta.len() - 1,
+                    None => target.data.len() / 2,
+                };
+                if let Some(delta) = create_delta(&base.data, &target.data, max_size) {
+                    best = Some((j, delta));
+                }
+            }
+        }
+        if let Some((j, _)) = &best {
+            depth[i] = depth[*j] + 1;
+        }
+        deltas.push(best);
+    }
+
+    let mut pack = Vec::new();
+    pack.extend_from_slice(b"PACK");
+    pack.extend_from_slice(&2u32.to_be_bytes());
+    pack.extend_from_slice(&(count as u32).to_be_bytes());
+
+    let mut offsets = vec![0u64; count];
+    let mut crcs = vec![0u32; count];
+    for i in 0..count {
+        let start = pack.len();
+        offsets[i] = start as u64;
+        let (kind, body) = match &deltas[i] {
+            Some((_, delta)) if options.ofs_delta => (OBJ_OFS_DELTA, delta),
+            Some((_, delta)) => (OBJ_REF_DELTA, delta),
+            None => (type_code(&objects[i].kind).unwrap_or(OBJ_BLOB), &objects[i].data),
+        };
+
+        pack.extend(encode_entry_header(kind, body.len()));
+        match (&deltas[i], kind) {
+            (Some((j, _)), OBJ_OFS_DELTA) => pack.extend(encode_offset(offsets[i] - offsets[*j])),
+            (Some((j, _)), _) => pack.extend(hex::decode(&objects[*j].hash).map_err(|_| ItError::InvalidRef(objects[*j].hash.clone()))?),
+            _ => {}
+        }
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(body)?;
+        pack.extend(encoder.finish()?);
+
+        let mut crc = Crc::new();
+        crc.update(&pack[start..]);
+        crcs[i] = crc.sum();
+    }
+    let checksum: [u8; 20] = Sha1::digest(&pack).into();
+    pack.extend_from_slice(&checksum);
+
+    let shas: Vec<[u8; 20]> = objects
+        .iter()
+        .map(|o| parse_sha(&o.hash).ok_or_else(|| ItError::InvalidRef(o.hash.clone())))
+        .collect::<Result<_, _>>()?;
+    let idx = build_index(&shas, &offsets, &crcs, &checksum);
+
+    let name = hex::encode(checksum);
+    let pack_dir = objects_dir.join("pack");
+    fs::create_dir_all(&pack_dir)?;
+    // the .idx goes last: a pack is only looked at once its index exists
+    write_new_file(&pack_dir, &format!("pack-{name}.pack"), &pack)?;
+    write_new_file(&pack_dir, &format!("pack-{name}.idx"), &idx)?;
+
+    let deltas = deltas.iter().filter(|d| d.is_some()).count();
+    Ok(PackSummary { name, objects: count, deltas })
+}
+
+// The type and size that start an entry, as read_entry_header decodes them:
+// the type and low 4 size bits in the first byte, then 7 bits a byte.
+fn encode_entry_header(kind: u8, mut size: usize) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut byte = (kind << 4) | (size & 0x0f) as u8;
+    size >>= 4;
+    while size > 0 {
+        bytes.push(byte | 0x80);
+        byte = (size & 0x7f) as u8;
+        size >>= 7;
+    }
+    bytes.push(byte);
+    bytes
+}
+
+// The inverse of the decoding in read_entry_header: base-128, most
+// significant group first, with each continuation adding one.
+fn encode_offset(mut distance: u64) -> Vec<u8> {
+    let mut bytes = vec![(distance & 0x7f) as u8];
+    distance >>= 7;
+    while distance > 0 {
+        distance -= 1;
+        bytes.push(0x80 | (distance & 0x7f) as u8);
+        distance >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
+
+pub fn build_index(shas: &[[u8; 20]], offsets: &[u64], crcs: &[u32], pack_checksum: &[u8; 20]) -> Vec<u8> {
+    let mut order: Vec<usize> = (0..shas.len()).collect();
+    order.sort_by_key(|&i| shas[i]);
+
+    let mut idx = Vec::new();
+    idx.extend_from_slice(&IDX_MAGIC);
+    idx.extend_from_slice(&2u32.to_be_bytes());
+    let mut counts = [0u32; 256];
+    for sha in shas {
+        counts[sha[0] as usize] += 1;
+    }
+    let mut total = 0;
+    for count in counts {
+        total += count;
+        idx.extend_from_slice(&total.to_be_bytes());
+    }
+    for &i in &order {
+        idx.extend_from_slice(&shas[i]);
+    }
+    for &i in &order {
+        idx.extend_from_slice(&crcs[i].to_be_bytes());
+    }
+    let mut large = Vec::new();
+    for &i in &order {
+        if offsets[i] < 0x8000_0000 {
+            idx.extend_from_slice(&(offsets[i] as u32).to_be_bytes());
+        } else {
+            idx.extend_from_slice(&(0x8000_0000 | (large.len() / 8) as u32).to_be_bytes());
+            large.extend_from_slice(&offsets[i].to_be_bytes());
+        }
+    }
+    idx.extend(large);
+    idx.extend_from_slice(pack_checksum);
+    let checksum: [u8; 20] = Sha1::digest(&idx).into();
+    idx.extend_from_slice(&checksum);
+    idx
+}
+
+// Writes through a temporary file and renames it into place, so readers
+// never see a partial file. An existing file with this name already has
+// the same content, its name being a checksum.
+pub fn write_new_file(dir: &Path, name: &str, data: &[u8]) -> Result<(), ItError> {
+    let path = dir.join(name);
+    if path.exists() {
+        return Ok(());
+    }
+    let tmp = dir.join(format!("tmp_{}_{name}", std::process::id()));
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, &path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // xorshift, so every run sees the same bytes
+    fn noise(mut seed: u64, len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                seed as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn delta_round_trip() {
+        let base = noise(1, 100_000);
+        let mut edited = base.clone();
+        edited.splice(5000..5010, noise(2, 300));
+        let targets = [
+            Vec::new(),
+            b"nothing in common".to_vec(),
+            base.clone(),
+            edited,
+            [noise(3, 50), base.clone(), noise(4, 70)].concat(),
+            [&base[..20_000], &base[60_000..]].concat(),
+            // copies longer than MAX_COPY are split into several
+            [base.clone(), base.clone()].concat(),
+        ];
+        for target in &targets {
+            let delta = create_delta(&base, target, usize::MAX).unwrap();
+            assert_eq!(&apply_delta(&base, &delta).unwrap(), target);
+        }
+
+        let delta = create_delta(&base, &targets[3], usize::MAX).unwrap();
+        assert!(delta.len() < 400, "an edit of 300 bytes took a {} byte delta", delta.len());
+        assert!(create_delta(&base, &targets[1], 4).is_none());
+    }
+
+    #[test]
+    fn apply_delta_rejects_malformed() {
+        let base = noise(5, 1000);
+        let target = [&base[..400], b"inserted", &base[400..]].concat();
+        let delta = create_delta(&base, &target, usize::MAX).unwrap();
+        assert!(apply_delta(&base[1..], &delta).is_err());
+        assert!(apply_delta(&base, &delta[..delta.len() - 1]).is_err());
+
+        // the target size is checked against what the instructions write,
+        // and a huge one is not allocated up front
+        for target_size in [0, 2, 1 << 40] {
+            let mut delta = Vec::new();
+            write_size(&mut delta, base.len());
+            write_size(&mut delta, target_size);
+            delta.extend_from_slice(&[1, b'x']);
+            assert!(apply_delta(&base, &delta).is_err());
+        }
+        // a copy reaching past the end of the base
+        let mut delta = Vec::new();
+        write_size(&mut delta, base.len());
+        write_size(&mut delta, 16);
+        delta.extend_from_slice(&[0x80 | 0x01 | 0x02 | 0x10, 0xf0, 0x03, 16]);
+        assert!(apply_delta(&base, &delta).is_err());
+    }
+
+    #[test]
+    fn entry_header_round_trip() {
+        let sizes = [(OBJ_BLOB, 0), (OBJ_COMMIT, 15), (OBJ_TREE, 16), (OBJ_TAG, 2047), (OBJ_BLOB, 2048), (OBJ_BLOB, 1 << 40)];
+        for (kind, size) in sizes {
+            let mut bytes = encode_entry_header(kind, size);
+            let header_len = bytes.len() as u64;
+            bytes.push(0x78);
+            let mut cursor = Cursor::new(&bytes);
+            let (read_kind, read_size, base) = read_entry_header(&mut cursor, 0).unwrap();
+            assert_eq!((read_kind, read_size), (kind, size));
+            assert!(matches!(base, DeltaBase::None));
+            assert_eq!(cursor.position(), header_len);
+        }
+
+        let sha = [0xab; 20];
+        let bytes = [encode_entry_header(OBJ_REF_DELTA, 40), sha.to_vec()].concat();
+        let (kind, size, base) = read_entry_header(&mut Cursor::new(&bytes), 0).unwrap();
+        assert_eq!((kind, size), (OBJ_REF_DELTA, 40));
+        assert!(matches!(base, DeltaBase::Ref(read) if read == sha));
+    }
+
+    #[test]
+    fn delta_offset_round_trip() {
+        // around the points where the offset takes another byte
+        for distance in [1u64, 127, 128, 129, 16511, 16512, 16513, 2113663, 2113664] {
+            let offset = distance + 12;
+            let mut bytes = vec![0u8; offset as usize];
+            bytes.extend(encode_entry_header(OBJ_OFS_DELTA, 300));
+            bytes.extend(encode_offset(distance));
+            let mut cursor = Cursor::new(&bytes);
+            let (kind, size, base) = read_entry_header(&mut cursor, offset).unwrap();
+            assert_eq!((kind, size), (OBJ_OFS_DELTA, 300));
+            assert!(matches!(base, DeltaBase::Offset(12)), "distance {distance}");
+            assert_eq!(cursor.position(), bytes.len() as u64);
+        }
+
+        // a base before the start of the pack
+        let mut bytes = vec![0u8; 12];
+        bytes.extend(encode_entry_header(OBJ_OFS_DELTA, 1));
+        bytes.extend(encode_offset(13));
+        assert!(read_entry_header(&mut Cursor::new(&bytes), 12).is_err());
+    }
+
+    #[test]
+    fn pack_index_round_trip() {
+        let count = 300u64;
+        let shas: Vec<[u8; 20]> = (0..count).map(|i| Sha1::digest(i.to_be_bytes()).into()).collect();
+        // every seventh entry needs the table of 64-bit offsets
+        let offsets: Vec<u64> =
+            (0..count).map(|i| if i % 7 == 0 { 0x8000_0000 + i * 1000 } else { 12 + i * 100 }).collect();
+        let crcs: Vec<u32> = (0..count as u32).map(|i| i.wrapping_mul(2_654_435_761)).collect();
+        let idx = build_index(&shas, &offsets, &crcs, &[7; 20]);
+
+        let dir = std::env::temp_dir().join(format!("it-pack-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let idx_path = dir.join("pack-test.idx");
+        fs::write(&idx_path, &idx).unwrap();
+        let index = PackIndex::load(&idx_path);
+        fs::write(&idx_path, &idx[..idx.len() - 41]).unwrap();
+        let truncated = PackIndex::load(&idx_path);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let index = index.unwrap();
+        assert_eq!(index.pack_checksum, [7; 20]);
+        assert_eq!(index.pack_path, dir.join("pack-test.pack"));
+        assert!(index.hashes.windows(2).all(|w| w[0] < w[1]));
+        for (i, sha) in shas.iter().enumerate() {
+            let at = index.find(sha).unwrap();
+            assert_eq!((index.offsets[at], index.crcs[at]), (offsets[i], crcs[i]));
+        }
+        assert!(index.find(&[0; 20]).is_none());
+        assert!(truncated.is_err());
+    }
+}
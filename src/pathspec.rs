@@ -0,0 +1,202 @@
+use crate::error::ItError;
+use crate::ignore::wildmatch;
+use crate::index::index_path;
+use crate::repository;
+use std::path::Path;
+
+// git-style pathspecs. A plain path matches itself and everything below it,
+// a pattern with wildcards is matched against the whole path with `*` free
+// to cross directories. Magic prefixes:
+//
+//      :(exclude)pat  :!pat  :^pat     drop matching paths
+//      :(glob)pat                      `*` stops at `/`, `**` spans directories
+//      :(literal)pat                   no wildcards at all
+//      :(top)pat  :/pat                relative to the worktree root rather
+//                                      than the current directory
+
+#[derive(Debug, Clone)]
+pub struct PathspecItem {
+    pub original: String,
+    // worktree-relative, "" for the whole tree
+    pub pattern: String,
+    pub exclude: bool,
+    pub literal: bool,
+    pub glob: bool,
+}
+
+impl PathspecItem {
+    fn parse(arg: &str) -> Result<PathspecItem, ItError> {
+        let mut item = PathspecItem {
+            original: arg.to_string(),
+            pattern: String::new(),
+            exclude: false,
+            literal: false,
+            glob: false,
+        };
+
+        let mut rest = arg;
+        let mut top = false;
+        if let Some(magic) = arg.strip_prefix(":(") {
+            let close = magic
+                .find(')')
+                .ok_or_else(|| ItError::Fatal(format!("Missing ')' at the end of pathspec magic in '{arg}'")))?;
+            for word in magic[..close].split(',').map(str::trim).filter(|w| !w.is_empty()) {
+                match word {
+                    "exclude" => item.exclude = true,
+                    "literal" => item.literal = true,
+                    "glob" => item.glob = true,
+                    "top" => top = true,
+                    other => {
+                        return Err(ItError::Fatal(format!("Invalid pathspec magic '{other}' in '{arg}'")));
+                    }
+                }
+            }
+            rest = &magic[close + 1..];
+        } else if let Some(short) = arg.strip_prefix(':') {
+            let magic_len = short.find(|c| !matches!(c, '!' | '^' | '/')).unwrap_or(short.len());
+            item.exclude = short[..magic_len].contains(['!', '^']);
+            top = short[..magic_len].contains('/');
+            // an optional ':' ends the short magic, as in ":!:pat"
+            rest = short[magic_len..].strip_prefix(':').unwrap_or(&short[magic_len..]);
+        }
+
+        if item.literal && item.glob {
+            return Err(ItError::Fatal(format!("'literal' and 'glob' are incompatible in '{arg}'")));
+        }
+        // pathspecs are relative to the directory the command was run from
+        item.pattern = match top {
+            true => index_path(Path::new(rest)),
+            false => repository::worktree_path(rest)?,
+        };
+        Ok(item)
+    }
+
+    pub fn has_wildcard(&self) -> bool {
+        !self.literal && self.pattern.contains(['*', '?', '['])
+    }
+
+    // the leading directories that contain no wildcard, where a walk can start
+    pub fn literal_dir(&self) -> &str {
+        if !self.has_wildcard() {
+            return &self.pattern;
+        }
+        let first = self.pattern.find(['*', '?', '[']).unwrap_or(self.pattern.len());
+        match self.pattern[..first].rfind('/') {
+            Some(slash) => &self.pattern[..slash],
+            None => "",
+        }
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        if self.pattern.is_empty() || path == self.pattern || path.starts_with(&format!("{}/", self.pattern)) {
+            return true;
+        }
+        self.has_wildcard() && wildmatch(&self.pattern, path, self.glob)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Pathspec {
+    pub items: Vec<PathspecItem>,
+}
+
+impl Pathspec {
+    pub fn parse(args: &[String]) -> Result<Pathspec, ItError> {
+        let items = args
+            .iter()
+            .map(|a| PathspecItem::parse(a))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Pathspec { items })
+    }
+
+    pub fn includes(&self) -> impl Iterator<Item = &PathspecItem> {
+        self.items.iter().filter(|i| !i.exclude)
+    }
+
+    // With no positive pathspec (none at all, or only excludes) everything
+    // is included, like git's implicit ".".
+    pub fn matches(&self, path: &str) -> bool {
+        let mut includes = self.includes().peekable();
+        let included = includes.peek().is_none() || includes.any(|i| i.matches(path));
+        included && !self.items.iter().any(|i| i.exclude && i.matches(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(args: &[&str]) -> Pathspec {
+        Pathspec::parse(&args.iter().map(|a| a.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn magic_prefixes() {
+        let item = |arg: &str| PathspecItem::parse(arg).unwrap();
+        let flags = |i: &PathspecItem| (i.pattern.clone(), i.exclude, i.literal, i.glob);
+        let f = |pattern: &str, exclude, literal, glob| (pattern.to_string(), exclude, literal, glob);
+
+        assert_eq!(flags(&item("./src//a.rs")), f("src/a.rs", false, false, false));
+        assert_eq!(flags(&item(":!src")), f("src", true, false, false));
+        assert_eq!(flags(&item(":^:src")), f("src", true, false, false));
+        assert_eq!(flags(&item(":/!src")), f("src", true, false, false));
+        assert_eq!(flags(&item(":/")), f("", false, false, false));
+        assert_eq!(flags(&item(":(exclude,glob)*.rs")), f("*.rs", true, false, true));
+        assert_eq!(flags(&item(":( literal , top )a*b")), f("a*b", false, true, false));
+        assert_eq!(flags(&item(":()x")), f("x", false, false, false));
+        assert_eq!(item(":(top)dir/../x").original, ":(top)dir/../x");
+
+        for bad in [":(exclude", ":(icase)x", ":(literal,glob)x", "../outside"] {
+            assert!(PathspecItem::parse(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn matching() {
+        let item = |arg: &str| PathspecItem::parse(arg).unwrap();
+        // a plain path covers itself and everything below, not a mere prefix
+        assert!(item("src").matches("src"));
+        assert!(item("src").matches("src/a/b.rs"));
+        assert!(!item("src").matches("srcs/a.rs"));
+        assert!(item(".").matches("anything"));
+
+        // plain wildcards cross directories, glob ones do not
+        assert!(item("*.rs").matches("src/deep/a.rs"));
+        assert!(!item(":(glob)*.rs").matches("src/a.rs"));
+        assert!(item(":(glob)*.rs").matches("a.rs"));
+        assert!(item(":(glob)src/**/*.rs").matches("src/a/b/c.rs"));
+        assert!(item("src/[ab].rs").matches("src/b.rs"));
+
+        // literal takes wildcards at their word
+        assert!(!item(":(literal)*.rs").matches("a.rs"));
+        assert!(item(":(literal)*.rs").matches("*.rs"));
+        assert!(!item(":(literal)*.rs").has_wildcard());
+    }
+
+    #[test]
+    fn literal_dirs() {
+        let dir = |arg: &str| PathspecItem::parse(arg).unwrap().literal_dir().to_string();
+        assert_eq!(dir("src/a.rs"), "src/a.rs");
+        assert_eq!(dir("src/deep/*.rs"), "src/deep");
+        assert_eq!(dir("src/a?/b"), "src");
+        assert_eq!(dir("*.rs"), "");
+        assert_eq!(dir(":(literal)a/[b]"), "a/[b]");
+    }
+
+    #[test]
+    fn excludes() {
+        let only_excludes = spec(&[":!*.log", ":(exclude)target"]);
+        assert!(only_excludes.matches("src/a.rs"));
+        assert!(!only_excludes.matches("logs/x.log"));
+        assert!(!only_excludes.matches("target/debug/it"));
+
+        let mixed = spec(&["src", "README.md", ":!src/gen"]);
+        assert_eq!(mixed.includes().count(), 2);
+        assert!(mixed.matches("src/a.rs"));
+        assert!(mixed.matches("README.md"));
+        assert!(!mixed.matches("src/gen/x.rs"));
+        assert!(!mixed.matches("Cargo.toml"));
+
+        assert!(spec(&[]).matches("anything"));
+    }
+}
@@ -0,0 +1,227 @@
+use crate::{
+    bitmap,
+    commands::hash_object::{read_object, read_object_in},
+    error::ItError,
+    index::read_index,
+    object_id::ObjectFormat,
+    pack,
+    refs::{read_ref, read_symbolic_ref},
+    tree::parse_tree,
+};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct LooseObject {
+    pub hash: String,
+    pub path: PathBuf,
+}
+
+// Every file under objects/xx/ named like an object, sorted by hash, and the
+// paths of anything else found in the fan-out directories.
+pub fn loose_objects(repo_path: &Path) -> Result<(Vec<LooseObject>, Vec<PathBuf>), ItError> {
+    let mut objects = Vec::new();
+    let mut garbage = Vec::new();
+    let hex_len = ObjectFormat::current().hex_len();
+    let Ok(dirs) = fs::read_dir(repo_path.join("objects")) else {
+        return Ok((objects, garbage));
+    };
+
+    for dir in dirs {
+        let dir = dir?;
+        let prefix = dir.file_name().to_string_lossy().to_string();
+        if prefix.len() != 2 || !prefix.chars().all(|c| c.is_ascii_hexdigit()) || !dir.path().is_dir() {
+            continue;
+        }
+        for file in fs::read_dir(dir.path())? {
+            let file = file?;
+            let rest = file.file_name().to_string_lossy().to_string();
+            if rest.len() == hex_len - 2 && rest.chars().all(|c| c.is_ascii_hexdigit()) {
+                objects.push(LooseObject { hash: format!("{prefix}{rest}"), path: file.path() });
+            } else {
+                garbage.push(file.path());
+            }
+        }
+    }
+    objects.sort_by(|a, b| a.hash.cmp(&b.hash));
+    garbage.sort();
+    Ok((objects, garbage))
+}
+
+// Every ref with the hash it holds, plus HEAD when it is detached. Symbolic
+// refs such as refs/remotes/origin/HEAD are left out, as what they point at
+// is listed on its own.
+pub fn ref_tips(repo_path: &Path) -> Result<Vec<(String, String)>, ItError> {
+    let mut names = Vec::new();
+    list_files(&repo_path.join("refs"), "refs", &mut names)?;
+    if read_symbolic_ref(repo_path, "HEAD")?.is_none() {
+        names.push("HEAD".to_string());
+    }
+
+    let mut tips = Vec::new();
+    for name in names {
+        if let Some(value) = read_ref(repo_path, &name)?.filter(|v| !v.starts_with("ref:")) {
+            tips.push((name, value));
+        }
+    }
+    Ok(tips)
+}
+
+// Hashes recorded in the reflogs, whose lines start with "<new> <old> ...".
+pub fn reflog_hashes(repo_path: &Path) -> Result<BTreeSet<String>, ItError> {
+    let mut logs = Vec::new();
+    list_files(&repo_path.join("logs"), "logs", &mut logs)?;
+
+    let mut hashes = BTreeSet::new();
+    for log in logs {
+        let content = fs::read_to_string(repo_path.join(&log)).unwrap_or_default();
+        for line in content.lines() {
+            for hash in line.split_whitespace().take(2).filter(|h| is_hex_hash(h)) {
+                if hash.chars().any(|c| c != '0') {
+                    hashes.insert(hash.to_string());
+                }
+            }
+        }
+    }
+    Ok(hashes)
+}
+
+// Commits whose parents a shallow clone left out, listed one per line in
+// .it/shallow. Walks stop at them rather than report the parents missing.
+pub fn shallow_commits(repo_path: &Path) -> Result<BTreeSet<String>, ItError> {
+    match fs::read_to_string(repo_path.join("shallow")) {
+        Ok(text) => Ok(text.lines().map(str::trim).filter(|l| is_hex_hash(l)).map(str::to_string).collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeSet::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Blobs staged in the index; gitlinks name commits of another repository.
+pub fn index_hashes(repo_path: &Path) -> Result<BTreeSet<String>, ItError> {
+    if !repo_path.join("index").exists() {
+        return Ok(BTreeSet::new());
+    }
+    Ok(read_index(repo_path)?
+        .iter()
+        .filter(|e| e.mode != 0o160000)
+        .map(|e| hex::encode(e.sha))
+        .collect())
+}
+
+// The objects a commit, tree or tag refers to, each with the name it has
+// there (empty except for tree entries).
+pub fn object_links(kind: &str, body: &[u8]) -> Result<Vec<(String, String)>, ItError> {
+    match kind {
+        "tree" => Ok(parse_tree(body)?
+            .into_iter()
+            .filter(|e| e.mode != "160000")
+            .map(|e| (hex::encode(e.sha), e.name))
+            .collect()),
+        "commit" | "tag" => {
+            let text = String::from_utf8_lossy(body);
+            let headers = text.split("\n\n").next().unwrap_or("");
+            Ok(headers
+                .lines()
+                .filter_map(|l| l.strip_prefix("tree ").or(l.strip_prefix("parent ")).or(l.strip_prefix("object ")))
+                .map(|hash| (hash.to_string(), String::new()))
+                .collect())
+        }
+        _ => Ok(Vec::new()),
+    }
+}
+
+// Everything reachable from refs, reflogs and the index, mapped to the name
+// hash of the path it was first found at (0 for commits and tags), which
+// repack uses to group similar objects. Roots with a reachability bitmap are
+// answered from it and the walk stops at anything a bitmap already covers.
+// A missing object is an error, since whatever it links to could not be
+// marked; reflog entries are the exception, old ones may already be gone.
+pub fn reachable_objects(repo_path: &Path) -> Result<BTreeMap<String, u32>, ItError> {
+    let mut queue: VecDeque<(String, String)> =
+        ref_tips(repo_path)?.into_iter().map(|(_, hash)| (hash, String::new())).collect();
+    if repo_path.join("index").exists() {
+        for entry in read_index(repo_path)?.into_iter().filter(|e| e.mode != 0o160000) {
+            queue.push_back((hex::encode(entry.sha), entry.path));
+        }
+    }
+    for hash in reflog_hashes(repo_path)? {
+        if object_exists(repo_path, &hash)? {
+            queue.push_back((hash, String::new()));
+        }
+    }
+
+    // what a bitmap reaches is closed under reachability, so the walk never
+    // needs to enter it
+    let roots: Vec<String> = queue.iter().map(|(hash, _)| hash.clone()).collect();
+    let mut reachable = bitmap::reachable_from(&repo_path.join("objects"), &roots)?;
+    let shallow = shallow_commits(repo_path)?;
+    while let Some((hash, path)) = queue.pop_front() {
+        if reachable.contains_key(&hash) {
+            continue;
+        }
+        let (kind, body) =
+            read_object(&hash).map_err(|_| ItError::CorruptObject(format!("unable to read reachable object {hash}")))?;
+        let mut links = object_links(&kind, &body)?;
+        if kind == "commit" && shallow.contains(&hash) {
+            // the tree comes first, the parents after it
+            links.truncate(1);
+        }
+        reachable.insert(hash, pack::name_hash(&path));
+        for (link, name) in links {
+            let link_path = if path.is_empty() { name } else { format!("{path}/{name}") };
+            queue.push_back((link, link_path));
+        }
+    }
+    Ok(reachable)
+}
+
+// Whether `ancestor` is `commit` or one of its ancestors, i.e. whether moving
+// a ref from `ancestor` to `commit` is a fast-forward. The walk stops at
+// shallow commits, whose parents are not there to look at.
+pub fn is_ancestor(repo_path: &Path, ancestor: &str, commit: &str) -> Result<bool, ItError> {
+    let objects_dir = repo_path.join("objects");
+    let shallow = shallow_commits(repo_path)?;
+    let mut seen = BTreeSet::new();
+    let mut queue = VecDeque::from([commit.to_string()]);
+    while let Some(hash) = queue.pop_front() {
+        if hash == ancestor {
+            return Ok(true);
+        }
+        if !seen.insert(hash.clone()) || shallow.contains(&hash) {
+            continue;
+        }
+        let (kind, body) = read_object_in(&objects_dir, &hash)?;
+        if kind != "commit" {
+            continue;
+        }
+        // the tree comes first, the parents after it
+        queue.extend(object_links(&kind, &body)?.into_iter().skip(1).map(|(parent, _)| parent));
+    }
+    Ok(false)
+}
+
+pub fn object_exists(repo_path: &Path, hash: &str) -> Result<bool, ItError> {
+    let objects_dir = repo_path.join("objects");
+    Ok(objects_dir.join(&hash[..2]).join(&hash[2..]).is_file() || pack::contains(&objects_dir, hash)?)
+}
+
+pub fn is_hex_hash(s: &str) -> bool {
+    ObjectFormat::current().is_hex_id(s)
+}
+
+// Relative paths of all files below `dir`, skipping .lock files.
+fn list_files(dir: &Path, prefix: &str, out: &mut Vec<String>) -> Result<(), ItError> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Ok(());
+    };
+    for entry in entries {
+        let entry = entry?;
+        let name = format!("{prefix}/{}", entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            list_files(&entry.path(), &name, out)?;
+        } else if !name.ends_with(".lock") {
+            out.push(name);
+        }
+    }
+    Ok(())
+}
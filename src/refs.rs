@@ -0,0 +1,319 @@
+use crate::commands::hash_object::read_object;
+use crate::error::ItError;
+use crate::index::{index_path, read_index};
+use crate::lockfile::{LockFile, write_atomic};
+use crate::object_id::ObjectFormat;
+use crate::pack;
+use crate::tree::{commit_parents, find_path, peel_tag, peel_to_tree};
+use std::fs;
+use std::path::Path;
+
+// Loose refs are files under .it/ holding a hex commit hash, e.g.
+// .it/refs/heads/main. HEAD is either "ref: refs/heads/<name>" or a hash.
+
+pub fn read_ref(repo_path: &Path, name: &str) -> Result<Option<String>, ItError> {
+    match fs::read_to_string(repo_path.join(name)) {
+        Ok(content) => Ok(Some(content.trim().to_string()).filter(|c| !c.is_empty())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Points `name` at `new` while holding `<name>.lock`. With `expected_old`
+// set this is a compare-and-swap: `Some(None)` means the ref must not exist
+// yet, `Some(Some(hash))` that it must still point at `hash`.
+pub fn update_ref(
+    repo_path: &Path,
+    name: &str,
+    new: &str,
+    expected_old: Option<Option<&str>>,
+) -> Result<(), ItError> {
+    let ref_path = repo_path.join(name);
+    let mut lock = LockFile::acquire(&ref_path)?;
+
+    if let Some(expected) = expected_old {
+        let actual = read_ref(repo_path, name)?;
+        if actual.as_deref() != expected {
+            return Err(ItError::RefChanged {
+                name: name.to_string(),
+                expected: expected.map(str::to_string),
+                actual,
+            });
+        }
+    }
+
+    lock.write_all(format!("{new}\n").as_bytes())?;
+    lock.commit()?;
+    Ok(())
+}
+
+// Removes `name` under its lock, optionally only if it still points at
+// `expected_old`. Returns false when there was nothing to delete.
+pub fn delete_ref(repo_path: &Path, name: &str, expected_old: Option<&str>) -> Result<bool, ItError> {
+    let ref_path = repo_path.join(name);
+    let _lock = LockFile::acquire(&ref_path)?;
+
+    let actual = read_ref(repo_path, name)?;
+    if expected_old.is_some_and(|expected| actual.as_deref() != Some(expected)) {
+        return Err(ItError::RefChanged {
+            name: name.to_string(),
+            expected: expected_old.map(str::to_string),
+            actual,
+        });
+    }
+    if actual.is_none() {
+        return Ok(false);
+    }
+    fs::remove_file(&ref_path)?;
+    Ok(true)
+}
+
+// git's check-ref-format rules, for names taken from the command line:
+// HEAD or refs/..., no "..", "@{", control characters, spaces, ~^:?*[\,
+// no component starting with '.' or ending in ".lock", no trailing '/' or '.'.
+pub fn is_valid_ref_name(name: &str) -> bool {
+    if name == "HEAD" {
+        return true;
+    }
+    let bad_char = |c: char| c.is_control() || " ~^:?*[\\".contains(c);
+    name.starts_with("refs/")
+        && !name.contains("..")
+        && !name.contains("@{")
+        && !name.ends_with('.')
+        && !name.chars().any(bad_char)
+        && name
+            .split('/')
+            .all(|part| !part.is_empty() && !part.starts_with('.') && !part.ends_with(".lock"))
+}
+
+// The ref a symbolic ref such as HEAD points at, None if it holds a hash.
+pub fn read_symbolic_ref(repo_path: &Path, name: &str) -> Result<Option<String>, ItError> {
+    Ok(read_ref(repo_path, name)?.and_then(|c| c.strip_prefix("ref:").map(|t| t.trim().to_string())))
+}
+
+pub fn write_head(repo_path: &Path, content: &str) -> Result<(), ItError> {
+    write_atomic(&repo_path.join("HEAD"), format!("{}\n", content.trim()).as_bytes())
+}
+
+// The commit HEAD points at, following "ref: ..." one level. None on an
+// unborn branch.
+pub fn head_commit(repo_path: &Path) -> Result<Option<String>, ItError> {
+    match read_symbolic_ref(repo_path, "HEAD")? {
+        Some(target) => read_ref(repo_path, &target),
+        None => read_ref(repo_path, "HEAD"),
+    }
+}
+
+// Resolves a revision to a full object hash. Understands HEAD (or @),
+// FETCH_HEAD, branch and tag names, remote-tracking names such as
+// origin/main, full ref paths, full or abbreviated hashes, followed by any
+// number of `~<n>` (n-th first-parent ancestor), `^<n>` (n-th parent) and
+// `^{<type>}` / `^{}` (peel to that type) suffixes.
+pub fn resolve_rev(repo_path: &Path, rev: &str) -> Result<String, ItError> {
+    let invalid = || ItError::InvalidRef(rev.to_string());
+    let split = rev.find(['~', '^']).unwrap_or(rev.len());
+    let (base, mut suffix) = rev.split_at(split);
+
+    let mut hash = resolve_base(repo_path, base)?.ok_or_else(invalid)?;
+
+    while let Some(op) = suffix.chars().next() {
+        suffix = &suffix[1..];
+        if op == '^' && suffix.starts_with('{') {
+            let close = suffix.find('}').ok_or_else(invalid)?;
+            hash = peel(&hash, &suffix[1..close]).map_err(|_| invalid())?;
+            suffix = &suffix[close + 1..];
+            continue;
+        }
+        let digits = suffix.find(|c: char| !c.is_ascii_digit()).unwrap_or(suffix.len());
+        let n: usize = if digits == 0 { 1 } else { suffix[..digits].parse().map_err(|_| invalid())? };
+        suffix = &suffix[digits..];
+
+        // ancestry always walks commits, so tags are peeled first
+        match op {
+            '~' => {
+                for _ in 0..n {
+                    hash = commit_parents(&peel(&hash, "commit")?)?.into_iter().next().ok_or_else(invalid)?;
+                }
+            }
+            '^' if n == 0 => hash = peel(&hash, "commit")?,
+            '^' => hash = commit_parents(&peel(&hash, "commit")?)?.into_iter().nth(n - 1).ok_or_else(invalid)?,
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(hash)
+}
+
+// Resolves anything `it show` accepts: a revision, `<rev>:<path>` for an
+// entry of that revision's tree, or `:<path>` for the staged blob.
+pub fn resolve_object(repo_path: &Path, spec: &str) -> Result<String, ItError> {
+    let Some((rev, path)) = spec.split_once(':') else {
+        return resolve_rev(repo_path, spec);
+    };
+    let path = index_path(Path::new(path));
+    let missing = || ItError::Fatal(format!("path '{path}' does not exist in '{rev}'"));
+
+    if rev.is_empty() {
+        return read_index(repo_path)?
+            .into_iter()
+            .find(|e| e.path == path)
+            .map(|e| hex::encode(e.sha))
+            .ok_or_else(|| ItError::Fatal(format!("path '{path}' is not in the index")));
+    }
+    let tree = peel_to_tree(&resolve_rev(repo_path, rev)?)?;
+    find_path(&tree, &path)?.map(|(_, hash)| hash).ok_or_else(missing)
+}
+
+// `^{}` peels tags only, `^{<type>}` peels until it reaches that type.
+fn peel(hash: &str, target: &str) -> Result<String, ItError> {
+    match target {
+        "" => peel_tag(hash),
+        "object" => Ok(hash.to_string()),
+        "tree" => peel_to_tree(hash),
+        _ => {
+            for candidate in [hash.to_string(), peel_tag(hash)?] {
+                if read_object(&candidate)?.0 == target {
+                    return Ok(candidate);
+                }
+            }
+            Err(ItError::Fatal(format!("{hash} cannot be peeled to a {target}")))
+        }
+    }
+}
+
+fn resolve_base(repo_path: &Path, base: &str) -> Result<Option<String>, ItError> {
+    if base == "HEAD" || base == "@" {
+        return head_commit(repo_path);
+    }
+    if base == "FETCH_HEAD" {
+        // the first line is the branch fetched for merging
+        let fetch_head = fs::read_to_string(repo_path.join("FETCH_HEAD")).unwrap_or_default();
+        return Ok(fetch_head.split_whitespace().next().map(str::to_string));
+    }
+    let candidates = [
+        format!("refs/heads/{base}"),
+        format!("refs/tags/{base}"),
+        format!("refs/remotes/{base}"),
+        format!("refs/remotes/{base}/HEAD"),
+        base.to_string(),
+    ];
+    for candidate in candidates {
+        if candidate.starts_with("refs/") && !candidate.contains("..") {
+            // refs/remotes/<remote>/HEAD names the remote's default branch
+            let candidate = read_symbolic_ref(repo_path, &candidate)?.unwrap_or(candidate);
+            if let Some(hash) = read_ref(repo_path, &candidate)? {
+                return Ok(Some(hash));
+            }
+        }
+    }
+    if base.len() < 4 || base.len() > ObjectFormat::current().hex_len() || !base.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(None);
+    }
+    expand_abbrev(repo_path, &base.to_ascii_lowercase())
+}
+
+// Finds the single object, loose or packed, whose hash starts with `prefix`.
+fn expand_abbrev(repo_path: &Path, prefix: &str) -> Result<Option<String>, ItError> {
+    let objects_dir = repo_path.join("objects");
+    let mut found = pack::find_prefix(&objects_dir, prefix)?;
+    if let Ok(read_dir) = fs::read_dir(objects_dir.join(&prefix[..2])) {
+        for entry in read_dir {
+            let name = entry?.file_name().to_string_lossy().to_string();
+            if name.starts_with(&prefix[2..]) {
+                found.insert(format!("{}{}", &prefix[..2], name));
+            }
+        }
+    }
+    if found.len() > 1 {
+        return Err(ItError::Fatal(format!("short object ID {prefix} is ambiguous")));
+    }
+    Ok(found.pop_first())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: &str = "1111111111111111111111111111111111111111";
+    const B: &str = "2222222222222222222222222222222222222222";
+
+    fn scratch(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("it-refs-test-{}-{name}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn changed(result: Result<impl std::fmt::Debug, ItError>, want: Option<&str>, have: Option<&str>) {
+        match result {
+            Err(ItError::RefChanged { expected, actual, .. }) => {
+                assert_eq!((expected.as_deref(), actual.as_deref()), (want, have));
+            }
+            other => panic!("expected RefChanged, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn update_ref_compare_and_swap() {
+        let repo = scratch("update");
+        let name = "refs/heads/main";
+        update_ref(&repo, name, A, Some(None)).unwrap();
+        assert_eq!(read_ref(&repo, name).unwrap().as_deref(), Some(A));
+
+        // must not exist yet, or must still point at the old value
+        changed(update_ref(&repo, name, B, Some(None)), None, Some(A));
+        changed(update_ref(&repo, name, B, Some(Some(B))), Some(B), Some(A));
+        changed(update_ref(&repo, "refs/heads/other", B, Some(Some(A))), Some(A), None);
+        assert_eq!(read_ref(&repo, name).unwrap().as_deref(), Some(A));
+        assert!(!repo.join("refs/heads/main.lock").exists());
+
+        update_ref(&repo, name, B, Some(Some(A))).unwrap();
+        assert_eq!(read_ref(&repo, name).unwrap().as_deref(), Some(B));
+        update_ref(&repo, name, A, None).unwrap();
+        assert_eq!(read_ref(&repo, name).unwrap().as_deref(), Some(A));
+
+        // someone else holds the lock
+        let lock = LockFile::acquire(&repo.join(name)).unwrap();
+        assert!(matches!(update_ref(&repo, name, B, None), Err(ItError::Locked(_))));
+        assert!(matches!(delete_ref(&repo, name, None), Err(ItError::Locked(_))));
+        drop(lock);
+        assert_eq!(read_ref(&repo, name).unwrap().as_deref(), Some(A));
+        fs::remove_dir_all(&repo).unwrap();
+    }
+
+    #[test]
+    fn delete_ref_compare_and_swap() {
+        let repo = scratch("delete");
+        let name = "refs/tags/v1";
+        assert!(!delete_ref(&repo, name, None).unwrap());
+        changed(delete_ref(&repo, name, Some(A)), Some(A), None);
+
+        update_ref(&repo, name, A, None).unwrap();
+        changed(delete_ref(&repo, name, Some(B)), Some(B), Some(A));
+        assert!(repo.join(name).exists());
+        assert!(delete_ref(&repo, name, Some(A)).unwrap());
+        assert!(!repo.join(name).exists());
+        assert!(!repo.join("refs/tags/v1.lock").exists());
+        fs::remove_dir_all(&repo).unwrap();
+    }
+
+    #[test]
+    fn ref_name_rules() {
+        for good in ["HEAD", "refs/heads/main", "refs/heads/feature/x-1", "refs/tags/v1.0"] {
+            assert!(is_valid_ref_name(good), "{good}");
+        }
+        for bad in [
+            "main",
+            "refs/heads/a..b",
+            "refs/heads/a@{1}",
+            "refs/heads/a.",
+            "refs/heads/a b",
+            "refs/heads/a~1",
+            "refs/heads/a:b",
+            "refs/heads/.hidden",
+            "refs/heads/x.lock",
+            "refs/heads//x",
+            "refs/heads/",
+        ] {
+            assert!(!is_valid_ref_name(bad), "{bad}");
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use crate::config::Config;
+use crate::error::ItError;
+use crate::object_id::ObjectFormat;
+use std::env;
+use std::ffi::OsString;
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+
+// Finding the repository a command runs in, done once at startup.
+//
+//      --git-dir <dir> / IT_DIR        the repository directory, with the
+//                                      current directory as the worktree
+//                                      unless core.bare is set
+//      --work-tree <dir> / IT_WORK_TREE  the worktree, overriding either
+//      otherwise                       the nearest `.it` in the current
+//                                      directory or one of its parents,
+//                                      whose parent is the worktree, or
+//                                      the nearest directory that is itself
+//                                      a (bare) repository
+//
+// `-C <path>` changes directory before any of this. When the current
+// directory is inside the worktree, the process then moves to the worktree
+// root and remembers where it started as the prefix, so that commands deal
+// only in worktree-relative paths and paths given on the command line are
+// read relative to the directory they were typed in.
+
+pub struct Repository {
+    pub git_dir: PathBuf,
+    pub worktree: Option<PathBuf>,
+    // the starting directory relative to the worktree, "" at its root
+    pub prefix: String,
+}
+
+static REPOSITORY: OnceLock<Option<Repository>> = OnceLock::new();
+
+// Flags take precedence over the environment.
+pub fn setup(git_dir: Option<PathBuf>, work_tree: Option<PathBuf>) -> Result<(), ItError> {
+    let cwd = env::current_dir()?;
+    let Some(repo) = discover(&cwd, git_dir, work_tree, |name| env::var_os(name))? else {
+        let _ = REPOSITORY.set(None);
+        return Ok(());
+    };
+    if let Some(worktree) = repo.worktree.as_ref().filter(|w| cwd.starts_with(w)) {
+        env::set_current_dir(worktree)?;
+    }
+    ObjectFormat::set_current(ObjectFormat::load(&repo.git_dir)?);
+
+    let _ = REPOSITORY.set(Some(repo));
+    Ok(())
+}
+
+// The repository seen from `cwd`, with `var` standing in for the environment.
+fn discover(
+    cwd: &Path,
+    git_dir: Option<PathBuf>,
+    work_tree: Option<PathBuf>,
+    var: impl Fn(&str) -> Option<OsString>,
+) -> Result<Option<Repository>, ItError> {
+    let git_dir = git_dir.or_else(|| var("IT_DIR").map(PathBuf::from));
+    let work_tree = work_tree.or_else(|| var("IT_WORK_TREE").map(PathBuf::from));
+
+    let found = match git_dir {
+        Some(dir) => {
+            let dir = cwd.join(dir);
+            if !is_repository(&dir) {
+                return Err(ItError::Fatal(format!("not a it repository: '{}'", dir.display())));
+            }
+            let worktree = (!is_bare(&dir)?).then(|| cwd.to_path_buf());
+            Some((dir, worktree))
+        }
+        None => cwd.ancestors().find_map(|d| {
+            if is_repository(&d.join(".it")) {
+                Some((d.join(".it"), Some(d.to_path_buf())))
+            } else {
+                is_repository(d).then(|| (d.to_path_buf(), None))
+            }
+        }),
+    };
+    let Some((git_dir, worktree)) = found else {
+        return Ok(None);
+    };
+    let worktree = work_tree.map(|w| cwd.join(w)).or(worktree).map(|w| normalize(&w));
+
+    let prefix = match worktree.as_ref().map(|w| cwd.strip_prefix(w)) {
+        Some(Ok(rel)) => rel.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/"),
+        _ => String::new(),
+    };
+    Ok(Some(Repository { git_dir: normalize(&git_dir), worktree, prefix }))
+}
+
+pub fn is_bare(git_dir: &Path) -> Result<bool, ItError> {
+    let config = Config::load_file(&git_dir.join("config"))?;
+    Ok(config.get_bool("core.bare")?.unwrap_or(false))
+}
+
+// A directory that holds HEAD, objects and refs.
+pub fn is_repository(dir: &Path) -> bool {
+    dir.join("HEAD").is_file() && dir.join("objects").is_dir() && dir.join("refs").is_dir()
+}
+
+pub fn current() -> Option<&'static Repository> {
+    REPOSITORY.get().and_then(Option::as_ref)
+}
+
+// The repository directory, `.it` unless given otherwise.
+pub fn repo_path() -> Result<PathBuf, ItError> {
+    current().map(|r| r.git_dir.clone()).ok_or(ItError::NotARepository)
+}
+
+pub fn worktree() -> Result<PathBuf, ItError> {
+    let repo = current().ok_or(ItError::NotARepository)?;
+    repo.worktree
+        .clone()
+        .ok_or_else(|| ItError::Fatal("this operation must be run in a work tree".to_string()))
+}
+
+pub fn objects_dir() -> PathBuf {
+    repo_path().unwrap_or_else(|_| PathBuf::from(".it")).join("objects")
+}
+
+pub fn prefix() -> &'static str {
+    current().map(|r| r.prefix.as_str()).unwrap_or("")
+}
+
+// A path typed on the command line as a worktree-relative path, "" for the
+// worktree root.
+pub fn worktree_path(arg: &str) -> Result<String, ItError> {
+    let outside = || ItError::Fatal(format!("'{arg}' is outside repository"));
+    let path = Path::new(arg);
+    let rel = match path.is_absolute() {
+        true => path.strip_prefix(worktree()?).map_err(|_| outside())?.to_path_buf(),
+        false => Path::new(prefix()).join(path),
+    };
+    let mut parts: Vec<String> = Vec::new();
+    for component in rel.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().to_string()),
+            Component::ParentDir => {
+                parts.pop().ok_or_else(outside)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(parts.join("/"))
+}
+
+// A file named on the command line that is not a worktree path, such as a
+// pack, read relative to the directory the command was started in.
+pub fn user_path(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+    Path::new(prefix()).join(path)
+}
+
+// Resolves `.` and `..` without touching the filesystem.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn make_repo(dir: &Path, bare: bool) {
+        fs::create_dir_all(dir.join("objects")).unwrap();
+        fs::create_dir_all(dir.join("refs")).unwrap();
+        fs::write(dir.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(dir.join("config"), format!("[core]\n\tbare = {bare}\n")).unwrap();
+    }
+
+    fn no_env(_: &str) -> Option<OsString> {
+        None
+    }
+
+    // (git_dir, worktree, prefix), with the scratch root stripped off
+    fn found(root: &Path, repo: Option<Repository>) -> Option<(String, Option<String>, String)> {
+        let rel = |p: &Path| p.strip_prefix(root).unwrap().to_string_lossy().to_string();
+        repo.map(|r| (rel(&r.git_dir), r.worktree.as_deref().map(rel), r.prefix))
+    }
+
+    fn expect(git_dir: &str, worktree: Option<&str>, prefix: &str) -> Option<(String, Option<String>, String)> {
+        Some((git_dir.to_string(), worktree.map(str::to_string), prefix.to_string()))
+    }
+
+    #[test]
+    fn discovery() {
+        let root = std::env::temp_dir().join(format!("it-repository-test-{}", std::process::id()));
+        make_repo(&root.join("work/.it"), false);
+        make_repo(&root.join("work/vendor/lib/.it"), false);
+        make_repo(&root.join("server.it"), true);
+        for dir in ["work/src/deep", "work/vendor/lib/x", "server.it/refs/heads", "elsewhere"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        let at = |cwd: &str, git_dir: Option<&str>, work_tree: Option<&str>, var: &dyn Fn(&str) -> Option<OsString>| {
+            let (git_dir, work_tree) = (git_dir.map(PathBuf::from), work_tree.map(PathBuf::from));
+            found(&root, discover(&root.join(cwd), git_dir, work_tree, var).unwrap())
+        };
+
+        // the nearest .it above the current directory (where -C leads)
+        assert_eq!(at("work", None, None, &no_env), expect("work/.it", Some("work"), ""));
+        assert_eq!(at("work/src/deep", None, None, &no_env), expect("work/.it", Some("work"), "src/deep"));
+        assert_eq!(
+            at("work/vendor/lib/x", None, None, &no_env),
+            expect("work/vendor/lib/.it", Some("work/vendor/lib"), "x")
+        );
+        // a bare repository is found from inside it and has no worktree
+        assert_eq!(at("server.it/refs/heads", None, None, &no_env), expect("server.it", None, ""));
+        assert_eq!(at("elsewhere", None, None, &no_env), None);
+
+        // --git-dir makes the current directory the worktree, unless bare
+        assert_eq!(at("elsewhere", Some("../work/.it"), None, &no_env), expect("work/.it", Some("elsewhere"), ""));
+        assert_eq!(at("elsewhere", Some("../server.it"), None, &no_env), expect("server.it", None, ""));
+        // --work-tree overrides either, and sets the prefix when inside it
+        assert_eq!(at("work/src", Some("../.it"), Some(".."), &no_env), expect("work/.it", Some("work"), "src"));
+        assert_eq!(at("elsewhere", None, Some("../work"), &no_env), None);
+        assert_eq!(at("work/src", None, Some("../../elsewhere"), &no_env), expect("work/.it", Some("elsewhere"), ""));
+        assert!(discover(&root.join("elsewhere"), Some(PathBuf::from(".")), None, no_env).is_err());
+
+        // IT_DIR and IT_WORK_TREE stand in for the flags, which win over them
+        let env = |name: &str| match name {
+            "IT_DIR" => Some(OsString::from("../server.it")),
+            "IT_WORK_TREE" => Some(OsString::from(".")),
+            _ => None,
+        };
+        assert_eq!(at("elsewhere", None, None, &env), expect("server.it", Some("elsewhere"), ""));
+        assert_eq!(at("elsewhere", Some("../work/.it"), None, &env), expect("work/.it", Some("elsewhere"), ""));
+        assert_eq!(at("work", Some(".it"), Some("src"), &env), expect("work/.it", Some("work/src"), ""));
+        let work_tree_only = |name: &str| (name == "IT_WORK_TREE").then(|| OsString::from("../.."));
+        assert_eq!(at("work/src/deep", None, None, &work_tree_only), expect("work/.it", Some("work"), "src/deep"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn normalize_paths() {
+        assert_eq!(normalize(Path::new("/a/./b/../c")), PathBuf::from("/a/c"));
+        assert_eq!(normalize(Path::new("a/../../b")), PathBuf::from("b"));
+    }
+}
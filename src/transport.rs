@@ -0,0 +1,442 @@
+// The local file transport: another repository reached through the
+// filesystem, named by a path or a file:// URL. The path may be a worktree
+// holding `.it` or a bare repository directory.
+//
+// Objects move by walking reachability from the refs being transferred and
+// copying whatever the receiving side lacks. An object the receiver already
+// has is not entered, as its history is assumed to be there too; transfers
+// keep that true by moving objects in only once all of them have arrived.
+// Loose objects are hard-linked where the filesystem allows and copied
+// otherwise; packed ones are written out as loose objects, hashed on the way
+// in.
+use crate::{
+    commands::hash_object::{CHUNK_SIZE, ObjectWriter, object_path_in, open_object_in, read_object_in},
+    error::ItError,
+    lockfile::{TempFile, write_atomic},
+    object_id::ObjectFormat,
+    reachable::{is_ancestor, object_exists, object_links, shallow_commits},
+    repository::{is_repository, normalize},
+};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+#[derive(Default)]
+pub struct Transfer {
+    // objects copied
+    pub objects: usize,
+    // commits whose parents were left behind
+    pub shallow: BTreeSet<String>,
+}
+
+// The filesystem path a remote URL names, absolute.
+pub fn url_path(url: &str) -> Result<PathBuf, ItError> {
+    let path = url.strip_prefix("file://").unwrap_or(url);
+    Ok(normalize(&std::env::current_dir()?.join(crate::config::expand_home(path))))
+}
+
+// The repository directory of the remote at `url`.
+pub fn open(url: &str) -> Result<PathBuf, ItError> {
+    let path = url_path(url)?;
+    let repo_path = if is_repository(&path.join(".it")) {
+        path.join(".it")
+    } else if is_repository(&path) {
+        path
+    } else {
+        return Err(ItError::Fatal(format!("repository '{url}' does not exist")));
+    };
+    Ok(repo_path)
+}
+
+// Objects of the two formats never mix, so both sides must use the same.
+pub fn check_format(remote: &Path) -> Result<(), ItError> {
+    let theirs = ObjectFormat::load(remote)?;
+    if theirs != ObjectFormat::current() {
+        return Err(ItError::Fatal(format!(
+            "mismatched object formats: {} here, {} in {}",
+            ObjectFormat::current().name(),
+            theirs.name(),
+            remote.display()
+        )));
+    }
+    Ok(())
+}
+
+// The branches and tags of a repository, by name.
+pub fn list_refs(repo_path: &Path) -> Result<Vec<(String, String)>, ItError> {
+    let mut refs: Vec<(String, String)> = crate::reachable::ref_tips(repo_path)?
+        .into_iter()
+        .filter(|(name, _)| name.starts_with("refs/heads/") || name.starts_with("refs/tags/"))
+        .collect();
+    refs.sort();
+    Ok(refs)
+}
+
+// Copies everything reachable from `tips` in `from` that `to` lacks. With a
+// depth, only that many commits of each line of history are taken. Commits
+// that are shallow in `from` stay shallow in `to`.
+//
+// The walk stops at objects `to` already has, so it must never hold an object
+// without what it links to. Objects therefore arrive in a quarantine
+// directory and only move into objects/ once all of them are there, each
+// after everything it links to; a transfer that fails partway leaves `to` as
+// it was.
+pub fn copy_objects(from: &Path, to: &Path, tips: &[String], depth: Option<usize>) -> Result<Transfer, ItError> {
+    let (from_objects, to_objects) = (from.join("objects"), to.join("objects"));
+    let quarantine = Quarantine::create(&to_objects)?;
+    let their_shallow = shallow_commits(from)?;
+    let mut transfer = Transfer::default();
+    let mut seen = BTreeSet::new();
+    // each copied object with the objects it links to
+    let mut copied: Vec<(String, Vec<String>)> = Vec::new();
+    let mut queue: VecDeque<(String, usize)> = tips.iter().map(|tip| (tip.clone(), 1)).collect();
+
+    while let Some((hash, generation)) = queue.pop_front() {
+        if !seen.insert(hash.clone()) || object_exists(to, &hash)? {
+            continue;
+        }
+        let kind = copy_object(&from_objects, &quarantine.dir, &hash)?;
+        transfer.objects += 1;
+        if kind == "blob" {
+            copied.push((hash, Vec::new()));
+            continue;
+        }
+
+        let (_, body) = read_object_in(&from_objects, &hash)?;
+        let mut links = object_links(&kind, &body)?;
+        // a commit links its tree first, then its parents
+        let cut = their_shallow.contains(&hash) || depth.is_some_and(|d| generation >= d);
+        if kind == "commit" && cut && links.len() > 1 {
+            links.truncate(1);
+            transfer.shallow.insert(hash.clone());
+        }
+        for (i, (link, _)) in links.iter().enumerate() {
+            let next = if kind == "commit" && i > 0 { generation + 1 } else { generation };
+            queue.push_back((link.clone(), next));
+        }
+        copied.push((hash, links.into_iter().map(|(link, _)| link).collect()));
+    }
+
+    quarantine.migrate(&dependency_order(&copied))?;
+    Ok(transfer)
+}
+
+// The copied objects ordered so that each comes after everything it links
+// to among them.
+fn dependency_order(copied: &[(String, Vec<String>)]) -> Vec<String> {
+    let links: HashMap<&str, &[String]> = copied.iter().map(|(hash, l)| (hash.as_str(), l.as_slice())).collect();
+    let mut order = Vec::with_capacity(copied.len());
+    let mut done = BTreeSet::new();
+    for (root, _) in copied {
+        // (object, whether its links have been pushed already)
+        let mut stack = vec![(root.as_str(), false)];
+        while let Some((hash, expanded)) = stack.pop() {
+            if done.contains(hash) {
+                continue;
+            }
+            if expanded {
+                done.insert(hash);
+                order.push(hash.to_string());
+                continue;
+            }
+            stack.push((hash, true));
+            for link in links[hash] {
+                if links.contains_key(link.as_str()) && !done.contains(link.as_str()) {
+                    stack.push((link, false));
+                }
+            }
+        }
+    }
+    order
+}
+
+// A directory beside the fan-out directories that incoming objects are
+// written to, laid out the same way. Removed, with whatever is left in it,
+// when dropped.
+struct Quarantine {
+    dir: PathBuf,
+    objects_dir: PathBuf,
+}
+
+impl Quarantine {
+    fn create(objects_dir: &Path) -> io::Result<Quarantine> {
+        let mut n = 0;
+        loop {
+            let dir = objects_dir.join(format!("incoming_{}_{n}", std::process::id()));
+            match fs::create_dir(&dir) {
+                Ok(()) => return Ok(Quarantine { dir, objects_dir: objects_dir.to_path_buf() }),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    // Moves the objects into the objects directory in the given order.
+    fn migrate(self, order: &[String]) -> io::Result<()> {
+        for hash in order {
+            let source = object_path_in(&self.dir, hash);
+            let dest = object_path_in(&self.objects_dir, hash);
+            if dest.exists() {
+                continue;
+            }
+            fs::create_dir_all(dest.parent().unwrap_or(&self.objects_dir))?;
+            fs::rename(&source, &dest)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Quarantine {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+// What `hash` names in `repo_path` once annotated tags are looked through.
+pub fn peel(repo_path: &Path, hash: &str) -> Result<String, ItError> {
+    let objects_dir = repo_path.join("objects");
+    let mut hash = hash.to_string();
+    loop {
+        let (kind, body) = read_object_in(&objects_dir, &hash)?;
+        if kind != "tag" {
+            return Ok(hash);
+        }
+        hash = object_links(&kind, &body)?
+            .into_iter()
+            .next()
+            .map(|(target, _)| target)
+            .ok_or_else(|| ItError::CorruptObject(format!("{hash}: tag names no object")))?;
+    }
+}
+
+// Copies one object and returns its type.
+fn copy_object(from_objects: &Path, to_objects: &Path, hash: &str) -> Result<String, ItError> {
+    let (kind, size, mut content) = open_object_in(from_objects, hash).map_err(|e| match e {
+        ItError::Io(e) if e.kind() == io::ErrorKind::NotFound => {
+            ItError::CorruptObject(format!("{hash}: missing from {}", from_objects.display()))
+        }
+        e => e,
+    })?;
+    let loose = object_path_in(from_objects, hash);
+    if loose.is_file() {
+        link_or_copy(&loose, &object_path_in(to_objects, hash))?;
+        return Ok(kind);
+    }
+
+    let mut writer = ObjectWriter::new_in(to_objects, &kind, size)?;
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        let n = content.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        writer.write(&buf[..n])?;
+    }
+    let id = writer.finish()?;
+    if id.to_hex() != hash {
+        return Err(ItError::CorruptObject(format!("{hash}: object hashes to {id}")));
+    }
+    Ok(kind)
+}
+
+// Links every loose object and pack of `from` into `to` as they are, the way
+// a full clone of a local repository starts out. Returns the number of files.
+pub fn link_objects(from: &Path, to: &Path) -> Result<usize, ItError> {
+    let (from_objects, to_objects) = (from.join("objects"), to.join("objects"));
+    let mut count = 0;
+    for dir in fs::read_dir(&from_objects)? {
+        let dir = dir?;
+        let name = dir.file_name().to_string_lossy().to_string();
+        let fan_out = name.len() == 2 && name.chars().all(|c| c.is_ascii_hexdigit());
+        if !(fan_out || name == "pack") || !dir.file_type()?.is_dir() {
+            continue;
+        }
+        for file in fs::read_dir(dir.path())? {
+            let file = file?;
+            let file_name = file.file_name().to_string_lossy().to_string();
+            if file.file_type()?.is_file() && !file_name.starts_with("tmp") && !file_name.ends_with(".lock") {
+                link_or_copy(&file.path(), &to_objects.join(&name).join(&file_name))?;
+                count += 1;
+            }
+        }
+    }
+    Ok(count)
+}
+
+// Records commits as shallow in `repo_path`, beside any already listed.
+pub fn add_shallow(repo_path: &Path, commits: &BTreeSet<String>) -> Result<(), ItError> {
+    if commits.is_empty() {
+        return Ok(());
+    }
+    let mut all = shallow_commits(repo_path)?;
+    all.extend(commits.iter().cloned());
+    let text: String = all.iter().map(|hash| format!("{hash}\n")).collect();
+    write_atomic(&repo_path.join("shallow"), text.as_bytes())
+}
+
+// Objects never change once written, so a hard link is as good as a copy.
+fn link_or_copy(source: &Path, dest: &Path) -> io::Result<()> {
+    if dest.exists() {
+        return Ok(());
+    }
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::hard_link(source, dest).is_ok() {
+        return Ok(());
+    }
+    let mut temp = TempFile::create(dest.parent().unwrap_or(Path::new(".")), "tmp_obj_")?;
+    io::copy(&mut fs::File::open(source)?, &mut temp)?;
+    temp.install(dest)
+}
+
+// A fetch refspec such as `+refs/heads/*:refs/remotes/origin/*`: which
+// remote refs to take and the local names they go to. A leading `+` allows
+// updates that are not fast-forwards.
+pub struct Refspec {
+    pub force: bool,
+    pub src: String,
+    pub dst: String,
+}
+
+impl Refspec {
+    pub fn parse(spec: &str) -> Result<Refspec, ItError> {
+        let invalid = || ItError::Fatal(format!("invalid refspec '{spec}'"));
+        let (force, rest) = match spec.strip_prefix('+') {
+            Some(rest) => (true, rest),
+            None => (false, spec),
+        };
+        let (src, dst) = rest.split_once(':').ok_or_else(invalid)?;
+        if src.matches('*').count() != dst.matches('*').count() || src.matches('*').count() > 1 {
+            return Err(invalid());
+        }
+        Ok(Refspec { force, src: src.to_string(), dst: dst.to_string() })
+    }
+
+    // The local name for the remote ref `name`, if this refspec takes it.
+    pub fn map(&self, name: &str) -> Option<String> {
+        match self.src.split_once('*') {
+            Some((before, after)) => {
+                let matched = name.strip_prefix(before)?.strip_suffix(after)?;
+                Some(self.dst.replacen('*', matched, 1))
+            }
+            None => (name == self.src).then(|| self.dst.clone()),
+        }
+    }
+}
+
+// One line of the report fetch and push print for each ref, as git does:
+//
+//      * [new branch]      main       -> origin/main
+//        81f4ae1..1febfc3  main       -> origin/main
+//      + 1febfc3...81f4ae1 main       -> origin/main  (forced update)
+//      ! [rejected]        main       -> main (non-fast-forward)
+pub fn update_line(flag: char, summary: &str, from: &str, to: &str, note: &str) -> String {
+    format!(" {flag} {summary:<17} {from:<10} -> {to}{note}")
+}
+
+// The flag and summary for a ref moving from `old` to `new`.
+pub fn update_summary(old: Option<&str>, new: &str, forced: bool, new_kind: &str) -> (char, String) {
+    let short = |hash: &str| hash[..7].to_string();
+    match old {
+        None => ('*', format!("[new {new_kind}]")),
+        Some(old) if forced => ('+', format!("{}...{}", short(old), short(new))),
+        Some(old) => (' ', format!("{}..{}", short(old), short(new))),
+    }
+}
+
+// Whether moving a ref from `old` to `new` loses no commits. A commit
+// `repo_path` does not have cannot be an ancestor of one it has.
+pub fn is_fast_forward(repo_path: &Path, old: Option<&str>, new: &str) -> Result<bool, ItError> {
+    match old {
+        Some(old) => Ok(object_exists(repo_path, old)? && is_ancestor(repo_path, old, new)?),
+        None => Ok(true),
+    }
+}
+
+// "main" for refs/heads/main, "v1" for refs/tags/v1.
+pub fn short_ref_name(name: &str) -> &str {
+    ["refs/heads/", "refs/tags/", "refs/remotes/"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::hash_object::{build_object, compress_data, compute_hash, store_object_in};
+
+    // a commit with the given parents, stored in `repo`
+    fn commit(repo: &Path, parents: &[&str], message: &str) -> String {
+        let mut body = String::from("tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n");
+        for parent in parents {
+            body.push_str(&format!("parent {parent}\n"));
+        }
+        body.push_str(&format!("author A <a@example.com> 0 +0000\ncommitter A <a@example.com> 0 +0000\n\n{message}\n"));
+        let raw = build_object(body.as_bytes(), "commit");
+        let hash = compute_hash(&raw);
+        store_object_in(&repo.join("objects"), &hash, &compress_data(&raw).unwrap()).unwrap();
+        hash
+    }
+
+    #[test]
+    fn fast_forward_checks() {
+        let repo = std::env::temp_dir().join(format!("it-transport-test-{}", std::process::id()));
+        fs::create_dir_all(repo.join("objects")).unwrap();
+        //   a - b - c - m
+        //        \     /
+        //         d --
+        let a = commit(&repo, &[], "a");
+        let b = commit(&repo, &[&a], "b");
+        let c = commit(&repo, &[&b], "c");
+        let d = commit(&repo, &[&b], "d");
+        let m = commit(&repo, &[&c, &d], "m");
+        let missing = "0123456789012345678901234567890123456789";
+        let ff = |old: Option<&str>, new: &str| is_fast_forward(&repo, old, new).unwrap();
+
+        assert!(ff(None, &a));
+        assert!(ff(Some(&a), &a));
+        assert!(ff(Some(&a), &c));
+        assert!(ff(Some(&d), &m));
+        assert!(!ff(Some(&c), &a));
+        assert!(!ff(Some(&c), &d));
+        assert!(!ff(Some(&m), &d));
+        // what the remote has and we lack is never an ancestor, push asks for a fetch first
+        assert!(!ff(Some(missing), &m));
+
+        // history below a shallow commit is not there to prove ancestry
+        fs::write(repo.join("shallow"), format!("{c}\n")).unwrap();
+        assert!(!ff(Some(&a), &c));
+        assert!(ff(Some(&d), &m));
+        fs::remove_dir_all(&repo).unwrap();
+    }
+
+    #[test]
+    fn refspecs() {
+        let spec = Refspec::parse("+refs/heads/*:refs/remotes/origin/*").unwrap();
+        assert!(spec.force);
+        assert_eq!(spec.map("refs/heads/feature/x").as_deref(), Some("refs/remotes/origin/feature/x"));
+        assert_eq!(spec.map("refs/tags/v1"), None);
+        let exact = Refspec::parse("refs/heads/main:refs/remotes/up/main").unwrap();
+        assert!(!exact.force);
+        assert_eq!(exact.map("refs/heads/main").as_deref(), Some("refs/remotes/up/main"));
+        assert_eq!(exact.map("refs/heads/main2"), None);
+        for bad in ["refs/heads/*", "refs/heads/*:refs/x", "refs/*/*:refs/*/*"] {
+            assert!(Refspec::parse(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn update_summaries() {
+        let (old, new) = ("1111111aaaa", "2222222bbbb");
+        assert_eq!(update_summary(None, new, false, "tag"), ('*', "[new tag]".to_string()));
+        assert_eq!(update_summary(Some(old), new, false, "branch"), (' ', "1111111..2222222".to_string()));
+        assert_eq!(update_summary(Some(old), new, true, "branch"), ('+', "1111111...2222222".to_string()));
+        assert_eq!(
+            update_line('!', "[rejected]", "main", "main", " (non-fast-forward)"),
+            " ! [rejected]        main       -> main (non-fast-forward)"
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use crate::commands::hash_object::{open_object, read_object};
+use crate::error::ItError;
+use crate::index::{MODE_FILE, MODE_SYMLINK, write_worktree_file};
+use crate::lfs::{Lfs, POINTER_MAX_SIZE, Pointer};
+use crate::object_id::{ObjectFormat, ObjectId};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+// Tree objects are a sequence of "<mode> <name>\0<binary id>" records, the
+// same layout write_tree::build_tree produces. The id is 20 bytes, or 32 in a
+// SHA-256 repository.
+pub struct TreeEntry {
+    pub mode: String,
+    pub name: String,
+    pub sha: ObjectId,
+}
+
+impl TreeEntry {
+    pub fn is_tree(&self) -> bool {
+        // git writes "40000", older it trees "040000"
+        self.mode == "40000" || self.mode == "040000"
+    }
+}
+
+pub fn parse_tree(body: &[u8]) -> Result<Vec<TreeEntry>, ItError> {
+    let hash_len = ObjectFormat::current().raw_len();
+    let mut entries = Vec::new();
+    let mut pos = 0;
+
+    while pos < body.len() {
+        let space = body[pos..]
+            .iter()
+            .position(|&b| b == b' ')
+            .ok_or_else(|| ItError::CorruptObject("tree entry missing mode".to_string()))?;
+        let mode = std::str::from_utf8(&body[pos..pos + space])
+            .map_err(|_| ItError::CorruptObject("tree entry has non-ascii mode".to_string()))?
+            .to_string();
+        pos += space + 1;
+
+        let null = body[pos..]
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| ItError::CorruptObject("tree entry missing name terminator".to_string()))?;
+        let name = String::from_utf8_lossy(&body[pos..pos + null]).to_string();
+        pos += null + 1;
+
+        if pos + hash_len > body.len() {
+            return Err(ItError::CorruptObject(format!("tree entry '{name}' truncated")));
+        }
+        let sha = ObjectId::from_bytes(&body[pos..pos + hash_len]).unwrap();
+        pos += hash_len;
+
+        entries.push(TreeEntry { mode, name, sha });
+    }
+
+    Ok(entries)
+}
+
+pub fn read_tree(hash: &str) -> Result<Vec<TreeEntry>, ItError> {
+    let (obj_type, body) = read_object(hash)?;
+    if obj_type != "tree" {
+        return Err(ItError::CorruptObject(format!("{hash} is a {obj_type}, not a tree")));
+    }
+    parse_tree(&body)
+}
+
+// The tree a commit points at.
+pub fn commit_tree_hash(commit_hash: &str) -> Result<String, ItError> {
+    let (obj_type, body) = read_object(commit_hash)?;
+    if obj_type != "commit" {
+        return Err(ItError::InvalidRef(commit_hash.to_string()));
+    }
+    String::from_utf8_lossy(&body)
+        .lines()
+        .find_map(|l| l.strip_prefix("tree "))
+        .map(|t| t.trim().to_string())
+        .ok_or_else(|| ItError::CorruptObject(format!("{commit_hash}: commit has no tree")))
+}
+
+// The parents a commit lists, first parent first.
+pub fn commit_parents(commit_hash: &str) -> Result<Vec<String>, ItError> {
+    let (obj_type, body) = read_object(commit_hash)?;
+    if obj_type != "commit" {
+        return Err(ItError::InvalidRef(commit_hash.to_string()));
+    }
+    Ok(String::from_utf8_lossy(&body)
+        .lines()
+        .take_while(|l| !l.is_empty())
+        .filter_map(|l| l.strip_prefix("parent "))
+        .map(|p| p.trim().to_string())
+        .collect())
+}
+
+// Follows annotated tags until it reaches an object that is not a tag.
+pub fn peel_tag(hash: &str) -> Result<String, ItError> {
+    let mut hash = hash.to_string();
+    loop {
+        let (obj_type, body) = read_object(&hash)?;
+        if obj_type != "tag" {
+            return Ok(hash);
+        }
+        hash = String::from_utf8_lossy(&body)
+            .lines()
+            .find_map(|l| l.strip_prefix("object "))
+            .map(|o| o.trim().to_string())
+            .ok_or_else(|| ItError::CorruptObject(format!("{hash}: tag has no object")))?;
+    }
+}
+
+// A tag, commit or tree hash, peeled down to the tree.
+pub fn peel_to_tree(hash: &str) -> Result<String, ItError> {
+    let hash = peel_tag(hash)?;
+    let (obj_type, _) = read_object(&hash)?;
+    match obj_type.as_str() {
+        "tree" => Ok(hash),
+        "commit" => commit_tree_hash(&hash),
+        other => Err(ItError::Fatal(format!("{hash} is a {other}, not a tree-ish"))),
+    }
+}
+
+// The (mode, hash) of `path` inside a tree; a directory gives its subtree.
+pub fn find_path(tree_hash: &str, path: &str) -> Result<Option<(String, String)>, ItError> {
+    let mut current = ("40000".to_string(), tree_hash.to_string());
+    for name in path.split('/').filter(|n| !n.is_empty()) {
+        if current.0 != "40000" && current.0 != "040000" {
+            return Ok(None);
+        }
+        match read_tree(&current.1)?.into_iter().find(|e| e.name == name) {
+            Some(entry) => current = (entry.mode, hex::encode(entry.sha)),
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(current))
+}
+
+// Writes every file of a tree below `target_dir`, creating directories as
+// needed. Files that are not in the tree are left alone.
+pub fn checkout_tree(tree_hash: &str, target_dir: &Path, lfs: &Lfs) -> Result<(), ItError> {
+    for entry in read_tree(tree_hash)? {
+        let path = target_dir.join(&entry.name);
+        let sha = hex::encode(entry.sha);
+        if entry.is_tree() {
+            fs::create_dir_all(&path)?;
+            checkout_tree(&sha, &path, lfs)?;
+        } else {
+            let mode = u32::from_str_radix(&entry.mode, 8).unwrap_or(MODE_FILE);
+            checkout_blob(&sha, mode, &path, lfs)?;
+        }
+    }
+    Ok(())
+}
+
+// Writes a blob to `path`. A pointer at a path tracked by lfs is replaced by
+// the content it stands for; when that is not available the pointer itself
+// is written, with a warning, so the rest of the checkout goes ahead.
+pub fn checkout_blob(sha: &str, mode: u32, path: &Path, lfs: &Lfs) -> Result<(), ItError> {
+    let (obj_type, size, mut content) = open_object(sha)?;
+    if obj_type != "blob" {
+        return Err(ItError::CorruptObject(format!("{sha} is a {obj_type}, not a blob")));
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if mode == MODE_SYMLINK || size > POINTER_MAX_SIZE || !lfs.tracks(path) {
+        write_worktree_file(path, mode, &mut content)?;
+        return Ok(());
+    }
+
+    let mut data = Vec::new();
+    content.read_to_end(&mut data)?;
+    if let Some(pointer) = Pointer::parse(&data) {
+        match lfs.smudge(&pointer)? {
+            Some(mut object) => {
+                write_worktree_file(path, mode, &mut object)?;
+                return Ok(());
+            }
+            None => eprintln!(
+                "warning: {}: lfs object {} is not available, checking out the pointer",
+                path.display(),
+                pointer.oid
+            ),
+        }
+    }
+    write_worktree_file(path, mode, &mut data.as_slice())?;
+    Ok(())
+}
+
+// Flattens a tree into "dir/file" -> (mode, sha) for every blob beneath it.
+pub fn flatten_tree(hash: &str) -> Result<BTreeMap<String, (String, ObjectId)>, ItError> {
+    let mut out = BTreeMap::new();
+    flatten_into(hash, "", &mut out)?;
+    Ok(out)
+}
+
+fn flatten_into(
+    hash: &str,
+    prefix: &str,
+    out: &mut BTreeMap<String, (String, ObjectId)>,
+) -> Result<(), ItError> {
+    for entry in read_tree(hash)? {
+        let path = if prefix.is_empty() {
+            entry.name.clone()
+        } else {
+            format!("{}/{}", prefix, entry.name)
+        };
+        if entry.is_tree() {
+            flatten_into(&hex::encode(entry.sha), &path, out)?;
+        } else {
+            out.insert(path, (entry.mode, entry.sha));
+        }
+    }
+    Ok(())
+}